@@ -7,7 +7,7 @@ use std::time::{SystemTime, UNIX_EPOCH};
 fn main() {
     // 获取项目根目录
     let project_root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
-    let target_dir = PathBuf::from(env::var("OUT_DIR").unwrap()).parent().unwrap().parent().unwrap().parent().unwrap();
+    let _target_dir = PathBuf::from(env::var("OUT_DIR").unwrap()).parent().unwrap().parent().unwrap().parent().unwrap();
 
     // 生成版本信息
     let mut version_info = String::new();
@@ -18,7 +18,7 @@ fn main() {
 
     // 2. Git commit hash (如果存在)
     let git_commit = Command::new("git")
-        .args(&["rev-parse", "--short", "HEAD"])
+        .args(["rev-parse", "--short", "HEAD"])
         .output()
         .ok()
         .and_then(|output| {
@@ -54,7 +54,7 @@ fn main() {
 
     // 5. 是否为脏工作区
     let dirty = Command::new("git")
-        .args(&["status", "--porcelain"])
+        .args(["status", "--porcelain"])
         .output()
         .ok()
         .map(|output| {
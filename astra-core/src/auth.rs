@@ -0,0 +1,419 @@
+use crate::config::ConfigReader;
+use crate::error::{AstraError, AstraResult};
+use crate::types::{AuthMethod, SftpConfig};
+use ssh2::{ErrorCode, KeyboardInteractivePrompt, Prompt, Session};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+use tracing::{debug, info};
+
+/// Order used when `auth_methods` is not configured
+pub const DEFAULT_AUTH_METHODS: [AuthMethod; 5] = [
+    AuthMethod::PublicKey,
+    AuthMethod::Password,
+    AuthMethod::Agent,
+    AuthMethod::DefaultKeys,
+    AuthMethod::KeyboardInteractive,
+];
+
+/// Identity files tried by [`AuthMethod::DefaultKeys`], like OpenSSH's defaults
+pub const DEFAULT_IDENTITY_FILES: [&str; 3] =
+    ["~/.ssh/id_ed25519", "~/.ssh/id_ecdsa", "~/.ssh/id_rsa"];
+
+/// Methods worth trying for `config`, in order.
+///
+/// Methods that need a credential the config does not have are dropped, as are methods the
+/// server did not offer (`offered` is the comma separated list from the server, if known).
+pub fn methods_to_try(config: &SftpConfig, offered: Option<&str>) -> Vec<AuthMethod> {
+    let configured = config
+        .auth_methods
+        .clone()
+        .unwrap_or_else(|| DEFAULT_AUTH_METHODS.to_vec());
+
+    let server_offers = |name: &str| match offered {
+        Some(list) => list.split(',').any(|offered| offered.trim() == name),
+        None => true,
+    };
+
+    let mut methods = Vec::new();
+    for method in configured {
+        let usable = match method {
+            AuthMethod::PublicKey => config.private_key_path.is_some(),
+            AuthMethod::Password => config.password.is_some(),
+            AuthMethod::Agent | AuthMethod::DefaultKeys | AuthMethod::KeyboardInteractive => true,
+        };
+        let offered_by_server = match method {
+            AuthMethod::PublicKey | AuthMethod::Agent | AuthMethod::DefaultKeys => {
+                server_offers("publickey")
+            }
+            AuthMethod::Password => server_offers("password"),
+            AuthMethod::KeyboardInteractive => server_offers("keyboard-interactive"),
+        };
+
+        if usable && offered_by_server && !methods.contains(&method) {
+            methods.push(method);
+        }
+    }
+    methods
+}
+
+/// Authenticate a handshaken session, trying each configured method until one succeeds
+pub fn authenticate(session: &Session, config: &SftpConfig) -> AstraResult<()> {
+    // Querying the methods also attempts "none" authentication, which some servers accept
+    let offered = session
+        .auth_methods(&config.username)
+        .ok()
+        .map(str::to_string);
+    if session.authenticated() {
+        return Ok(());
+    }
+
+    let methods = methods_to_try(config, offered.as_deref());
+    if methods.is_empty() {
+        return Err(AstraError::AuthenticationError(format!(
+            "No usable authentication method for {} (server offers: {})",
+            config.username,
+            offered.as_deref().unwrap_or("unknown")
+        )));
+    }
+
+    let mut passphrase = KeyPassphrase::new(config);
+
+    let mut failures = Vec::new();
+    let mut passphrase_error = None;
+    for method in methods {
+        debug!("Trying {} authentication", method);
+        match try_method(session, config, method, &mut passphrase) {
+            Ok(()) if session.authenticated() => {
+                info!("Authenticated as {} using {}", config.username, method);
+                return Ok(());
+            }
+            Ok(()) => failures.push(format!("{}: rejected", method)),
+            Err(e @ AstraError::PassphraseError(_)) => {
+                failures.push(format!("{}: {}", method, e));
+                passphrase_error.get_or_insert(e);
+            }
+            Err(e) => failures.push(format!("{}: {}", method, e)),
+        }
+    }
+
+    // A key that could not be decrypted is the actionable problem, not the methods tried after it
+    if let Some(e) = passphrase_error {
+        return Err(e);
+    }
+
+    Err(AstraError::AuthenticationError(format!(
+        "All authentication methods failed for {} ({})",
+        config.username,
+        failures.join("; ")
+    )))
+}
+
+fn try_method(
+    session: &Session, config: &SftpConfig, method: AuthMethod, passphrase: &mut KeyPassphrase,
+) -> AstraResult<()> {
+    let username = &config.username;
+
+    match method {
+        AuthMethod::PublicKey => {
+            let key_path = config.private_key_path.as_deref().unwrap_or_default();
+            let key_path = PathBuf::from(ConfigReader::expand_tilde_local(key_path));
+            try_key_file(session, username, &key_path, passphrase)
+        }
+        AuthMethod::Password => {
+            let password = config.password.as_deref().unwrap_or_default();
+            session
+                .userauth_password(username, password)
+                .map_err(|e| AstraError::AuthenticationError(e.to_string()))
+        }
+        AuthMethod::Agent => session
+            .userauth_agent(username)
+            .map_err(|e| AstraError::AuthenticationError(e.to_string())),
+        AuthMethod::DefaultKeys => {
+            let identities = default_identity_files();
+            if identities.is_empty() {
+                return Err(AstraError::AuthenticationError(
+                    "no default identity files found in ~/.ssh".to_string(),
+                ));
+            }
+
+            let mut last_error = None;
+            for identity in identities {
+                // Encrypted default keys are normally served by the agent; only try them when
+                // a passphrase is configured
+                if !passphrase.is_configured() && key_is_encrypted(&identity) {
+                    debug!("Skipping encrypted identity {}", identity.display());
+                    continue;
+                }
+                match try_key_file(session, username, &identity, passphrase) {
+                    Ok(()) if session.authenticated() => return Ok(()),
+                    Ok(()) => {}
+                    Err(e) => last_error = Some(e),
+                }
+            }
+            Err(last_error.unwrap_or_else(|| {
+                AstraError::AuthenticationError("default identities rejected".to_string())
+            }))
+        }
+        AuthMethod::KeyboardInteractive => {
+            let mut prompter = PasswordPrompter {
+                password: config.password.clone(),
+            };
+            session
+                .userauth_keyboard_interactive(username, &mut prompter)
+                .map_err(|e| AstraError::AuthenticationError(e.to_string()))
+        }
+    }
+}
+
+fn try_key_file(
+    session: &Session, username: &str, key_path: &Path, passphrase: &mut KeyPassphrase,
+) -> AstraResult<()> {
+    let encrypted = key_is_encrypted(key_path);
+    let passphrase = if encrypted {
+        match passphrase.get() {
+            Ok(Some(passphrase)) => Some(passphrase),
+            Ok(None) => {
+                return Err(AstraError::PassphraseError(format!(
+                    "{} is encrypted; set private_key_passphrase, private_key_passphrase_env or \
+                     private_key_passphrase_command",
+                    key_path.display()
+                )))
+            }
+            Err(e) => {
+                return Err(AstraError::PassphraseError(format!(
+                    "{}: {}",
+                    key_path.display(),
+                    e
+                )))
+            }
+        }
+    } else {
+        None
+    };
+
+    session
+        .userauth_pubkey_file(username, None, key_path, passphrase)
+        .map_err(|e| {
+            if encrypted && is_key_decoding_error(&e) {
+                AstraError::PassphraseError(format!("wrong passphrase for {}", key_path.display()))
+            } else {
+                AstraError::AuthenticationError(format!("{}: {}", key_path.display(), e))
+            }
+        })
+}
+
+/// libssh2 reports a key it cannot decrypt as a file error rather than an auth failure
+fn is_key_decoding_error(error: &ssh2::Error) -> bool {
+    matches!(
+        error.code(),
+        ErrorCode::Session(LIBSSH2_ERROR_FILE)
+            | ErrorCode::Session(LIBSSH2_ERROR_KEYFILE_AUTH_FAILED)
+    )
+}
+
+const LIBSSH2_ERROR_FILE: i32 = -16;
+const LIBSSH2_ERROR_KEYFILE_AUTH_FAILED: i32 = -48;
+
+/// The private key passphrase of one authentication, resolved only once an encrypted key needs
+/// it, so a missing variable or a failing command does not stop agent or password auth
+pub struct KeyPassphrase<'a> {
+    config: &'a SftpConfig,
+    resolved: Option<Option<String>>,
+}
+
+impl<'a> KeyPassphrase<'a> {
+    pub fn new(config: &'a SftpConfig) -> Self {
+        Self {
+            config,
+            resolved: None,
+        }
+    }
+
+    /// Whether the config names any source for a passphrase
+    pub fn is_configured(&self) -> bool {
+        self.config.private_key_passphrase.is_some()
+            || self.config.private_key_passphrase_env.is_some()
+            || self.config.private_key_passphrase_command.is_some()
+    }
+
+    pub fn get(&mut self) -> AstraResult<Option<&str>> {
+        if self.resolved.is_none() {
+            self.resolved = Some(resolve_passphrase(self.config)?);
+        }
+        Ok(self
+            .resolved
+            .as_ref()
+            .and_then(|passphrase| passphrase.as_deref()))
+    }
+}
+
+/// Passphrase for the private key, taken from the config value, the environment variable or
+/// the output of the passphrase command, in that order.
+///
+/// A command's output is cached for the rest of the process, so the sessions of a transfer pool
+/// (and every later connection of `serve`) prompt at most once.
+pub fn resolve_passphrase(config: &SftpConfig) -> AstraResult<Option<String>> {
+    if let Some(passphrase) = &config.private_key_passphrase {
+        return Ok(Some(passphrase.clone()));
+    }
+
+    if let Some(var) = &config.private_key_passphrase_env {
+        return std::env::var(var).map(Some).map_err(|_| {
+            AstraError::ConfigurationError(format!(
+                "private_key_passphrase_env: environment variable {} is not set",
+                var
+            ))
+        });
+    }
+
+    if let Some(command) = &config.private_key_passphrase_command {
+        return cached_passphrase_command(command).map(Some);
+    }
+
+    Ok(None)
+}
+
+/// Output of each passphrase command that succeeded in this process
+static COMMAND_PASSPHRASES: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+
+fn cached_passphrase_command(command: &str) -> AstraResult<String> {
+    // Held while the command runs, so sessions connecting in parallel wait for one prompt
+    let mut cache = COMMAND_PASSPHRASES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(passphrase) = cache.get(command) {
+        return Ok(passphrase.clone());
+    }
+
+    let passphrase = run_passphrase_command(command)?;
+    cache.insert(command.to_string(), passphrase.clone());
+    Ok(passphrase)
+}
+
+fn run_passphrase_command(command: &str) -> AstraResult<String> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    }
+    .map_err(|e| {
+        AstraError::ConfigurationError(format!(
+            "Failed to run private_key_passphrase_command: {}",
+            e
+        ))
+    })?;
+
+    if !output.status.success() {
+        return Err(AstraError::ConfigurationError(format!(
+            "private_key_passphrase_command exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    // Tools like `pass show` put the secret on the first line
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.lines().next().unwrap_or_default().to_string())
+}
+
+/// Whether a private key file is protected by a passphrase.
+///
+/// Handles legacy PEM keys (`Proc-Type: 4,ENCRYPTED`), PKCS#8 (`ENCRYPTED PRIVATE KEY`) and the
+/// OpenSSH format, whose header names the cipher (`none` for unencrypted keys).
+pub fn key_is_encrypted(key_path: &Path) -> bool {
+    let Ok(content) = fs::read_to_string(key_path) else {
+        return false;
+    };
+
+    if content.lines().any(|line| {
+        line.starts_with("Proc-Type: 4,ENCRYPTED") || line.starts_with("-----BEGIN ENCRYPTED")
+    }) {
+        return true;
+    }
+
+    if !content.contains("BEGIN OPENSSH PRIVATE KEY") {
+        return false;
+    }
+
+    let body: String = content
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .flat_map(|line| line.trim().chars())
+        .take(64)
+        .collect();
+    let header = decode_base64(&body);
+
+    // "openssh-key-v1\0", then the cipher name as a length-prefixed string
+    const MAGIC: &[u8] = b"openssh-key-v1\0";
+    if header.len() < MAGIC.len() + 4 || !header.starts_with(MAGIC) {
+        return false;
+    }
+    let len_bytes = &header[MAGIC.len()..MAGIC.len() + 4];
+    let len = u32::from_be_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) as usize;
+    let start = MAGIC.len() + 4;
+    match header.get(start..start + len) {
+        Some(cipher) => cipher != b"none",
+        None => false,
+    }
+}
+
+fn decode_base64(input: &str) -> Vec<u8> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+
+    let mut bytes = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for sextet in input.bytes().map_while(value) {
+        buffer = (buffer << 6) | u32::from(sextet);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    bytes
+}
+
+/// Default identity files that exist on this machine
+pub fn default_identity_files() -> Vec<PathBuf> {
+    DEFAULT_IDENTITY_FILES
+        .iter()
+        .map(|path| PathBuf::from(ConfigReader::expand_tilde_local(path)))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// Answers keyboard-interactive prompts non-interactively: hidden prompts get the configured
+/// password, echoed prompts an empty answer
+struct PasswordPrompter {
+    password: Option<String>,
+}
+
+impl KeyboardInteractivePrompt for PasswordPrompter {
+    fn prompt<'a>(
+        &mut self, _username: &str, _instructions: &str, prompts: &[Prompt<'a>],
+    ) -> Vec<String> {
+        prompts
+            .iter()
+            .map(|prompt| {
+                if prompt.echo {
+                    String::new()
+                } else {
+                    self.password.clone().unwrap_or_default()
+                }
+            })
+            .collect()
+    }
+}
@@ -1,21 +1,42 @@
+use crate::cli::{self, RootOverrides, SyncReport, TransferReport};
 use crate::error::{AstraError, AstraResult};
+use crate::output::Output;
+use crate::paths::normalize_local_path;
+use crate::progress::{ProgressEvent, ProgressReporter};
 use crate::sftp::SftpClient;
-use crate::types::{SftpConfig, SyncOperation, SyncResult};
+use crate::state::{project_root, STATE_DIR};
+use crate::transfer::{CancelToken, TransferPool};
+use crate::types::{SftpConfig, SyncMode, SyncOperation, SyncResult};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
-use tokio::sync::{mpsc, Mutex, RwLock};
-use tokio::time::sleep;
-use tracing::{error, info, warn};
+use tokio::sync::{broadcast, mpsc, Mutex, OwnedMutexGuard, RwLock};
+use tracing::{debug, info, warn};
+
+/// Task journal file inside [`STATE_DIR`]
+pub const TASKS_FILE: &str = "tasks.json";
+/// Bumped when the journal format changes incompatibly; older journals are ignored
+pub const TASKS_VERSION: u32 = 1;
+/// How often finished tasks are pruned by the [`RetentionPolicy`]
+pub const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Times a task interrupted while running is restarted before it is marked failed
+pub const MAX_TASK_RESTARTS: u32 = 1;
+/// Progress events a subscriber may fall behind by before it starts missing some
+pub const PROGRESS_CAPACITY: usize = 1024;
 
 /// Background task status
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TaskStatus {
+    /// Queued behind other tasks of the same project
     Pending,
     Running,
     Completed,
     Failed(String),
+    /// Cancelled before it started, or stopped early at a file or chunk boundary
+    Cancelled,
 }
 
 /// Background sync task
@@ -27,6 +48,85 @@ pub struct BackgroundTask {
     pub created_at: SystemTime,
     pub updated_at: SystemTime,
     pub result: Option<SyncResult>,
+    /// Configuration file the task runs against; `None` means discovery from `project`
+    #[serde(default)]
+    pub config_path: Option<String>,
+    /// Root of the project, see [`task_project`]; tasks of one project run one at a time
+    #[serde(default)]
+    pub project: String,
+    /// `--local`/`--remote` overrides the task was submitted with, applied again when a
+    /// restored task reloads its configuration
+    #[serde(default)]
+    pub local_root: Option<String>,
+    #[serde(default)]
+    pub remote_root: Option<String>,
+    /// Times the task was restarted after the process running it went away
+    #[serde(default)]
+    pub restarts: u32,
+    /// Resolved configuration, kept in memory only so that credentials are never serialized
+    #[serde(skip)]
+    pub config: Option<SftpConfig>,
+    #[serde(skip)]
+    pub cancel: CancelToken,
+}
+
+impl BackgroundTask {
+    /// A pending task that runs against an already loaded configuration
+    pub fn new(task_type: TaskType, config: SftpConfig, config_path: Option<String>) -> Self {
+        let now = SystemTime::now();
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            task_type,
+            status: TaskStatus::Pending,
+            created_at: now,
+            updated_at: now,
+            result: None,
+            config_path,
+            project: task_project(&config),
+            local_root: None,
+            remote_root: None,
+            restarts: 0,
+            config: Some(config),
+            cancel: CancelToken::new(),
+        }
+    }
+
+    /// Record the root overrides `config` was loaded with
+    pub fn with_roots(mut self, local_root: Option<String>, remote_root: Option<String>) -> Self {
+        self.local_root = local_root;
+        self.remote_root = remote_root;
+        self
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            TaskStatus::Completed | TaskStatus::Failed(_) | TaskStatus::Cancelled
+        )
+    }
+
+    /// The configuration to run with. A task restored from a journal has none in memory and
+    /// reloads it from `config_path`, or by discovery from its project root.
+    fn resolve_config(&self) -> AstraResult<SftpConfig> {
+        match &self.config {
+            Some(config) => Ok(config.clone()),
+            None => cli::load_config(
+                Some(self.config_path.as_deref().unwrap_or(&self.project)),
+                RootOverrides {
+                    local: self.local_root.as_deref(),
+                    remote: self.remote_root.as_deref(),
+                },
+            ),
+        }
+    }
+}
+
+/// The project whose queue and journal a task for `config` belongs to: the
+/// [`project_root`] of its local root, so a task on a subtree shares both with the whole project
+pub fn task_project(config: &SftpConfig) -> String {
+    project_root(&normalize_local_path(Path::new(&config.local_path)))
+        .to_string_lossy()
+        .to_string()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,61 +145,396 @@ pub enum TaskType {
     },
 }
 
-/// Background task manager
+/// How long finished tasks are kept, in memory and in the journals
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    /// Finished tasks older than this are dropped
+    pub max_age: Duration,
+    /// Only the most recent finished tasks of each project are kept
+    pub max_finished_per_project: usize,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(7 * 24 * 60 * 60),
+            max_finished_per_project: 100,
+        }
+    }
+}
+
+/// The tasks of one project as stored in `.astra-settings/tasks.json` under its project root.
+///
+/// Only what [`BackgroundTask`] serializes is written, so credentials never reach the disk.
+#[derive(Debug, Serialize, Deserialize)]
+struct TaskJournal {
+    version: u32,
+    tasks: Vec<BackgroundTask>,
+}
+
+impl TaskJournal {
+    fn path(project: &str) -> PathBuf {
+        Path::new(project).join(STATE_DIR).join(TASKS_FILE)
+    }
+
+    /// Tasks journaled for `project`; a missing or unreadable journal yields none
+    fn read(project: &str) -> Vec<BackgroundTask> {
+        let path = Self::path(project);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => return Vec::new(),
+        };
+
+        match serde_json::from_str::<TaskJournal>(&content) {
+            Ok(journal) if journal.version == TASKS_VERSION => journal.tasks,
+            Ok(_) => {
+                debug!(
+                    "Ignoring task journal {} from another version",
+                    path.display()
+                );
+                Vec::new()
+            }
+            Err(e) => {
+                warn!("Ignoring unreadable task journal {}: {}", path.display(), e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Replace the journal of `project` atomically
+    fn write(project: &str, tasks: Vec<BackgroundTask>) -> AstraResult<()> {
+        let path = Self::path(project);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let journal = TaskJournal {
+            version: TASKS_VERSION,
+            tasks,
+        };
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_string_pretty(&journal)?)?;
+        fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+}
+
+/// State shared by the task manager, its workers and the periodic cleanup
+struct TaskStore {
+    tasks: RwLock<HashMap<String, BackgroundTask>>,
+    /// Held while a journal is written, so an older snapshot never replaces a newer one
+    journal: Mutex<()>,
+    /// Projects whose journal has been read, and whether this process owns (writes) it
+    loaded: Mutex<HashMap<String, bool>>,
+    /// Held while a task or a direct command of the project runs, see
+    /// [`TaskManager::lock_project`]
+    running: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    /// Task state changes and the file progress of running tasks
+    events: broadcast::Sender<ProgressEvent>,
+}
+
+impl TaskStore {
+    fn new() -> Self {
+        Self {
+            tasks: RwLock::default(),
+            journal: Mutex::default(),
+            loaded: Mutex::default(),
+            running: Mutex::default(),
+            events: broadcast::channel(PROGRESS_CAPACITY).0,
+        }
+    }
+
+    /// Wait until nothing else of `project` runs, then hold it until the guard is dropped.
+    /// Waiters are served in the order they asked.
+    async fn lock_project(&self, project: &str) -> OwnedMutexGuard<()> {
+        let lock = self
+            .running
+            .lock()
+            .await
+            .entry(project.to_string())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+
+    /// Tell subscribers that a task is now in `status`
+    fn announce(&self, task_id: &str, status: &TaskStatus) {
+        // Nobody listening is not an error
+        let _ = self.events.send(ProgressEvent::TaskState {
+            task_id: task_id.to_string(),
+            status: status.clone(),
+        });
+    }
+
+    /// A reporter forwarding file progress to subscribers
+    fn reporter(&self) -> ProgressReporter {
+        let events = self.events.clone();
+        ProgressReporter::new(move |event| {
+            let _ = events.send(event);
+        })
+    }
+
+    /// Write the current tasks of `project` to its journal
+    async fn persist(&self, project: &str) {
+        if self.loaded.lock().await.get(project) != Some(&true) {
+            return;
+        }
+
+        let _guard = self.journal.lock().await;
+        let snapshot: Vec<BackgroundTask> = self
+            .tasks
+            .read()
+            .await
+            .values()
+            .filter(|task| task.project == project)
+            .cloned()
+            .collect();
+        if let Err(e) = TaskJournal::write(project, snapshot) {
+            warn!("Failed to journal tasks of {}: {}", project, e);
+        }
+    }
+
+    async fn update(&self, task_id: &str, project: &str, change: impl FnOnce(&mut BackgroundTask)) {
+        if let Some(task) = self.tasks.write().await.get_mut(task_id) {
+            change(task);
+            task.updated_at = SystemTime::now();
+        }
+        self.persist(project).await;
+    }
+
+    /// Drop finished tasks the policy no longer keeps, rewriting the affected journals
+    async fn apply_retention(&self, policy: RetentionPolicy) {
+        let now = SystemTime::now();
+        let projects: HashSet<String> = {
+            let mut tasks = self.tasks.write().await;
+
+            let mut finished: HashMap<&str, Vec<(SystemTime, &str)>> = HashMap::new();
+            for task in tasks.values().filter(|task| task.is_finished()) {
+                finished
+                    .entry(task.project.as_str())
+                    .or_default()
+                    .push((task.updated_at, task.id.as_str()));
+            }
+
+            let mut expired = HashSet::new();
+            for list in finished.values_mut() {
+                // Newest first, so the count limit keeps the most recent tasks
+                list.sort_by_key(|entry| std::cmp::Reverse(entry.0));
+                for (index, (updated_at, id)) in list.iter().enumerate() {
+                    let age = now.duration_since(*updated_at).unwrap_or(Duration::ZERO);
+                    if index >= policy.max_finished_per_project || age >= policy.max_age {
+                        expired.insert(id.to_string());
+                    }
+                }
+            }
+
+            let mut projects = HashSet::new();
+            tasks.retain(|id, task| {
+                let keep = !expired.contains(id);
+                if !keep {
+                    projects.insert(task.project.clone());
+                }
+                keep
+            });
+            projects
+        };
+
+        for project in projects {
+            self.persist(&project).await;
+        }
+    }
+}
+
+/// Background task manager.
+///
+/// Submitted tasks wait as [`TaskStatus::Pending`] in a per-project queue: tasks of the same
+/// project run one at a time in submission order, so two saves of one file never race each
+/// other onto the server, while different projects proceed in parallel. Work outside the queue
+/// joins in through [`lock_project`](Self::lock_project).
+///
+/// Every change is journaled to the project's `.astra-settings/tasks.json`, and finished tasks
+/// are pruned by a [`RetentionPolicy`] every [`CLEANUP_INTERVAL`].
 pub struct TaskManager {
-    tasks: Arc<RwLock<std::collections::HashMap<String, BackgroundTask>>>,
+    store: Arc<TaskStore>,
     sender: mpsc::UnboundedSender<BackgroundTask>,
+    retention: RetentionPolicy,
+}
+
+impl Default for TaskManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TaskManager {
     pub fn new() -> Self {
+        Self::with_retention(RetentionPolicy::default())
+    }
+
+    pub fn with_retention(retention: RetentionPolicy) -> Self {
         let (sender, mut receiver) = mpsc::unbounded_channel::<BackgroundTask>();
-        let tasks = Arc::new(RwLock::new(std::collections::HashMap::new()));
+        let store = Arc::new(TaskStore::new());
 
-        // Start background worker
-        let tasks_clone = tasks.clone();
+        // Route every task to the queue of its project, starting a worker per new project
+        let store_clone = store.clone();
         tokio::spawn(async move {
-            while let Some(mut task) = receiver.recv().await {
-                let mut tasks = tasks_clone.write().await;
-
-                // Update task status to running
-                task.status = TaskStatus::Running;
-                task.updated_at = SystemTime::now();
-                tasks.insert(task.id.clone(), task.clone());
-
-                // Execute task in background
-                let task_for_execution = task.clone();
-                let tasks_for_update = tasks_clone.clone();
-                tokio::spawn(async move {
-                    let result = Self::execute_task(&task_for_execution).await;
-
-                    // Update task with result
-                    let mut tasks = tasks_for_update.write().await;
-                    if let Some(stored_task) = tasks.get_mut(&task_for_execution.id) {
-                        match result {
-                            Ok(sync_result) => {
-                                stored_task.status = TaskStatus::Completed;
-                                stored_task.result = Some(sync_result);
-                            }
-                            Err(e) => {
-                                stored_task.status = TaskStatus::Failed(e.to_string());
-                            }
-                        }
-                        stored_task.updated_at = SystemTime::now();
-                    }
-                });
+            let mut queues: HashMap<String, mpsc::UnboundedSender<BackgroundTask>> = HashMap::new();
+            while let Some(task) = receiver.recv().await {
+                let queue = queues
+                    .entry(task.project.clone())
+                    .or_insert_with(|| Self::spawn_project_worker(store_clone.clone()));
+                let _ = queue.send(task);
+            }
+        });
+
+        // Periodic cleanup, stopping once the manager is gone
+        let weak_store = Arc::downgrade(&store);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+            loop {
+                interval.tick().await;
+                match weak_store.upgrade() {
+                    Some(store) => store.apply_retention(retention).await,
+                    None => break,
+                }
             }
         });
 
         Self {
-            tasks,
+            store,
             sender,
+            retention,
+        }
+    }
+
+    fn spawn_project_worker(store: Arc<TaskStore>) -> mpsc::UnboundedSender<BackgroundTask> {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<BackgroundTask>();
+        tokio::spawn(async move {
+            while let Some(task) = receiver.recv().await {
+                Self::run_queued(&store, task).await;
+            }
+        });
+        sender
+    }
+
+    async fn run_queued(store: &TaskStore, task: BackgroundTask) {
+        let _running = store.lock_project(&task.project).await;
+
+        // A task cancelled while it was waiting in the queue never starts
+        {
+            let mut tasks = store.tasks.write().await;
+            match tasks.get_mut(&task.id) {
+                Some(stored_task) if stored_task.status == TaskStatus::Pending => {
+                    stored_task.status = TaskStatus::Running;
+                    stored_task.updated_at = SystemTime::now();
+                }
+                _ => return,
+            }
+        }
+        store.announce(&task.id, &TaskStatus::Running);
+        store.persist(&task.project).await;
+
+        let result = Self::execute_task(&task, store.reporter().for_task(&task.id)).await;
+
+        let status = match &result {
+            _ if task.cancel.is_cancelled() => TaskStatus::Cancelled,
+            Ok(sync_result) if sync_result.success => TaskStatus::Completed,
+            Ok(sync_result) => TaskStatus::Failed(sync_result.message.clone()),
+            Err(e) => TaskStatus::Failed(e.to_string()),
+        };
+        store.announce(&task.id, &status);
+        store
+            .update(&task.id, &task.project, |stored_task| {
+                stored_task.status = status;
+                stored_task.result = result.ok();
+            })
+            .await;
+    }
+
+    /// Restore the journaled tasks of `project`, once per process.
+    ///
+    /// With `resume` this process takes over the journal: pending tasks are queued again and
+    /// tasks interrupted while running are restarted up to [`MAX_TASK_RESTARTS`] times, then
+    /// marked failed. Restarting is safe because a transfer only replaces its target by renaming
+    /// a complete temp file over it; the restarted one continues from the temp file the
+    /// interrupted one left, where resuming applies. Without `resume` the tasks are only made
+    /// visible and the journal is never written, for processes that inspect it while another one
+    /// owns it.
+    pub async fn load_project(&self, project: &str, resume: bool) -> usize {
+        {
+            let mut loaded = self.store.loaded.lock().await;
+            if project.is_empty() || loaded.contains_key(project) {
+                return 0;
+            }
+            loaded.insert(project.to_string(), resume);
+        }
+
+        let mut queued = Vec::new();
+        let mut restored = 0;
+        {
+            let mut tasks = self.store.tasks.write().await;
+            for mut task in TaskJournal::read(project) {
+                if tasks.contains_key(&task.id) {
+                    continue;
+                }
+                task.project = project.to_string();
+
+                if resume {
+                    match task.status {
+                        TaskStatus::Pending => queued.push(task.clone()),
+                        TaskStatus::Running if task.restarts < MAX_TASK_RESTARTS => {
+                            info!("Resuming interrupted task {}", task.id);
+                            task.restarts += 1;
+                            task.status = TaskStatus::Pending;
+                            task.updated_at = SystemTime::now();
+                            queued.push(task.clone());
+                        }
+                        TaskStatus::Running => {
+                            warn!("Task {} was interrupted too often, failing it", task.id);
+                            task.status =
+                                TaskStatus::Failed("Interrupted by a restart".to_string());
+                            task.updated_at = SystemTime::now();
+                        }
+                        TaskStatus::Completed | TaskStatus::Failed(_) | TaskStatus::Cancelled => {}
+                    }
+                }
+
+                tasks.insert(task.id.clone(), task);
+                restored += 1;
+            }
         }
+
+        if resume {
+            for task in queued {
+                let _ = self.sender.send(task);
+            }
+            self.store.apply_retention(self.retention).await;
+            self.store.persist(project).await;
+        }
+        restored
+    }
+
+    /// Hold `project` for work done outside the queue, such as the direct commands of
+    /// `astra serve`. Its tasks wait until the guard is dropped, and the caller waits for a
+    /// running one to finish, so nothing of one project ever writes concurrently.
+    pub async fn lock_project(&self, project: &str) -> OwnedMutexGuard<()> {
+        self.store.lock_project(project).await
     }
 
     /// Submit a new background task
     pub async fn submit_task(&self, task: BackgroundTask) -> AstraResult<String> {
         let task_id = task.id.clone();
+        let project = task.project.clone();
+        // Earlier tasks of the project stay in its journal next to the new one
+        self.load_project(&project, true).await;
+        // Visible as pending right away, not only once the worker picks it up
+        self.store
+            .tasks
+            .write()
+            .await
+            .insert(task_id.clone(), task.clone());
+        self.store.announce(&task_id, &TaskStatus::Pending);
+        self.store.persist(&project).await;
         self.sender
             .send(task)
             .map_err(|e| AstraError::TaskError(format!("Failed to submit task: {}", e)))?;
@@ -108,100 +543,175 @@ impl TaskManager {
 
     /// Get task status
     pub async fn get_task_status(&self, task_id: &str) -> Option<BackgroundTask> {
-        let tasks = self.tasks.read().await;
+        let tasks = self.store.tasks.read().await;
         tasks.get(task_id).cloned()
     }
 
-    /// Get all tasks
+    /// Get all tasks, oldest first
     pub async fn get_all_tasks(&self) -> Vec<BackgroundTask> {
-        let tasks = self.tasks.read().await;
-        tasks.values().cloned().collect()
+        let tasks = self.store.tasks.read().await;
+        let mut tasks: Vec<BackgroundTask> = tasks.values().cloned().collect();
+        tasks.sort_by_key(|task| task.created_at);
+        tasks
     }
 
-    /// Clean up old completed tasks
-    pub async fn cleanup_old_tasks(&self, max_age: Duration) {
-        let mut tasks = self.tasks.write().await;
-        let now = SystemTime::now();
+    /// Cancel a task and return its status afterwards.
+    ///
+    /// A pending task becomes [`TaskStatus::Cancelled`] immediately. A running task keeps
+    /// `Running` until its transfers reach the next file or chunk boundary, then ends up
+    /// `Cancelled` with whatever results it had so far.
+    pub async fn cancel_task(&self, task_id: &str) -> AstraResult<TaskStatus> {
+        let (status, project) = {
+            let mut tasks = self.store.tasks.write().await;
+            let task = tasks
+                .get_mut(task_id)
+                .ok_or_else(|| AstraError::TaskError(format!("Task not found: {}", task_id)))?;
+
+            match task.status {
+                TaskStatus::Pending => {
+                    task.status = TaskStatus::Cancelled;
+                    task.updated_at = SystemTime::now();
+                    self.store.announce(task_id, &task.status);
+                }
+                TaskStatus::Running => {}
+                TaskStatus::Completed | TaskStatus::Failed(_) | TaskStatus::Cancelled => {
+                    return Err(AstraError::TaskError(format!(
+                        "Task {} has already finished",
+                        task_id
+                    )));
+                }
+            }
+            task.cancel.cancel();
+            (task.status.clone(), task.project.clone())
+        };
 
-        tasks.retain(|_, task| {
-            let age = now
-                .duration_since(task.updated_at)
-                .unwrap_or(Duration::ZERO);
-            age < max_age || matches!(task.status, TaskStatus::Running | TaskStatus::Pending)
-        });
+        self.store.persist(&project).await;
+        Ok(status)
+    }
+
+    /// A reporter publishing file progress to subscribers like a task does, without a task id,
+    /// for work done outside the queue
+    pub fn reporter(&self) -> ProgressReporter {
+        self.store.reporter()
+    }
+
+    /// Receive every task state change and the file progress of running tasks.
+    ///
+    /// A subscriber that falls more than [`PROGRESS_CAPACITY`] events behind skips the oldest.
+    pub fn subscribe(&self) -> broadcast::Receiver<ProgressEvent> {
+        self.store.events.subscribe()
     }
 
-    /// Execute a background task
-    async fn execute_task(task: &BackgroundTask) -> AstraResult<SyncResult> {
+    /// Clean up finished tasks older than `max_age`
+    pub async fn cleanup_old_tasks(&self, max_age: Duration) {
+        self.store
+            .apply_retention(RetentionPolicy {
+                max_age,
+                max_finished_per_project: usize::MAX,
+            })
+            .await;
+    }
+
+    /// Execute a background task.
+    ///
+    /// ssh2 is synchronous, so the transfer runs on tokio's blocking thread pool with its own
+    /// session(s) and the task records whatever actually happened.
+    async fn execute_task(
+        task: &BackgroundTask, progress: ProgressReporter,
+    ) -> AstraResult<SyncResult> {
         info!("Executing background task: {}", task.id);
 
-        match &task.task_type {
+        let config = task.resolve_config()?;
+        let task_type = task.task_type.clone();
+        let cancel = task.cancel.clone();
+        tokio::task::spawn_blocking(move || Self::run_task(config, task_type, cancel, progress))
+            .await
+            .map_err(|e| AstraError::TaskError(format!("Background task panicked: {}", e)))?
+    }
+
+    fn run_task(
+        config: SftpConfig, task_type: TaskType, cancel: CancelToken, progress: ProgressReporter,
+    ) -> AstraResult<SyncResult> {
+        let output = Output::quiet();
+        let workers = config.advanced.max_concurrent_transfers;
+        cancel.check()?;
+        let mut client = SftpClient::new(config)?;
+        client.set_cancel_token(cancel);
+        client.set_progress(progress);
+        let mut sessions = vec![client];
+
+        match task_type {
             TaskType::FullSync => {
-                // For full sync, we need a config
-                // This is a simplified version - in practice, you'd pass config
-                return Err(AstraError::TaskError(
-                    "Full sync requires configuration - not implemented for background tasks yet"
-                        .to_string(),
-                ));
+                match cli::run_sync(&mut sessions, SyncMode::default(), &[], false, output)? {
+                    (SyncReport::Result(result), _) => Ok(result),
+                    (SyncReport::Plan(_), _) => Err(AstraError::TaskError(
+                        "Full sync produced a plan instead of a result".to_string(),
+                    )),
+                }
             }
 
             TaskType::FileUpload {
                 local_path,
                 remote_path,
             } => {
-                // For file upload, we need to create a new SFTP client
-                // This is a simplified version - you'd pass config
-                warn!("File upload background task not fully implemented yet");
-                Ok(SyncResult {
-                    success: true,
-                    message: "File upload completed".to_string(),
-                    files_transferred: vec![local_path.to_string_lossy().to_string()],
-                    files_skipped: vec![],
-                    errors: vec![],
-                })
+                let (report, _) = cli::upload_path(
+                    &mut sessions,
+                    &local_path.to_string_lossy(),
+                    &remote_path.to_string_lossy(),
+                    false,
+                    output,
+                )?;
+                Ok(transfer_result(report))
             }
 
             TaskType::FileDownload {
                 remote_path,
                 local_path,
             } => {
-                warn!("File download background task not fully implemented yet");
-                Ok(SyncResult {
-                    success: true,
-                    message: "File download completed".to_string(),
-                    files_transferred: vec![local_path.to_string_lossy().to_string()],
-                    files_skipped: vec![],
-                    errors: vec![],
-                })
+                let (report, _) = cli::download_path(
+                    &mut sessions,
+                    &remote_path.to_string_lossy(),
+                    &local_path.to_string_lossy(),
+                    false,
+                    output,
+                )?;
+                Ok(transfer_result(report))
             }
 
             TaskType::CustomOperations {
                 operations,
             } => {
-                warn!("Custom operations background task not fully implemented yet");
                 let mut result = SyncResult {
                     success: true,
                     message: "Custom operations completed".to_string(),
                     files_transferred: vec![],
                     files_skipped: vec![],
                     errors: vec![],
+                    file_results: vec![],
+                    conflicts: vec![],
                 };
 
-                for operation in operations {
-                    match operation.operation_type {
-                        crate::types::OperationType::Upload => {
-                            result
-                                .files_transferred
-                                .push(operation.local_path.to_string_lossy().to_string());
-                        }
-                        crate::types::OperationType::Download => {
-                            result
-                                .files_transferred
-                                .push(operation.local_path.to_string_lossy().to_string());
-                        }
-                        _ => {}
+                if !operations.is_empty() {
+                    let mut pool =
+                        TransferPool::from_clients(sessions, workers.min(operations.len()));
+                    result.file_results = pool.execute(&operations);
+                }
+
+                for file_result in &result.file_results {
+                    let path = file_result.local_path.to_string_lossy().to_string();
+                    match &file_result.error {
+                        None => result.files_transferred.push(path),
+                        Some(error) => result.errors.push(format!("{}: {}", path, error)),
                     }
                 }
+                if !result.errors.is_empty() {
+                    result.success = false;
+                    result.message = format!(
+                        "{} of {} operations failed",
+                        result.errors.len(),
+                        operations.len()
+                    );
+                }
 
                 Ok(result)
             }
@@ -209,6 +719,22 @@ impl TaskManager {
     }
 }
 
+/// A single-file or directory transfer as a [`SyncResult`]
+fn transfer_result(report: TransferReport) -> SyncResult {
+    match report {
+        TransferReport::Tree(result) => result,
+        TransferReport::File(file_result) => SyncResult {
+            success: file_result.success,
+            message: "File transfer completed".to_string(),
+            files_transferred: vec![file_result.local_path.to_string_lossy().to_string()],
+            files_skipped: vec![],
+            errors: vec![],
+            file_results: vec![file_result],
+            conflicts: vec![],
+        },
+    }
+}
+
 /// CLI command for task management
 #[derive(Debug, Clone)]
 pub struct TaskCommands;
@@ -225,8 +751,8 @@ impl TaskCommands {
 
         println!("Background Tasks:");
         println!(
-            "{:<20} {:<15} {:<20} {:<15} {}",
-            "Task ID", "Type", "Status", "Created", "Result"
+            "{:<20} {:<15} {:<20} {:<15} Result",
+            "Task ID", "Type", "Status", "Created"
         );
         println!("{}", "-".repeat(80));
 
@@ -249,6 +775,7 @@ impl TaskCommands {
                 TaskStatus::Running => "Running",
                 TaskStatus::Completed => "Completed",
                 TaskStatus::Failed(err) => &format!("Failed: {}", err),
+                TaskStatus::Cancelled => "Cancelled",
             };
 
             let created_str = humantime::format_rfc3339_seconds(task.created_at).to_string();
@@ -306,10 +833,14 @@ impl TaskCommands {
         Ok(())
     }
 
-    /// Cancel a task (not fully implemented)
+    /// Cancel a pending or running task
     pub async fn cancel_task(task_manager: &TaskManager, task_id: &str) -> AstraResult<()> {
-        // This would require implementing task cancellation
-        println!("Task cancellation not implemented yet.");
+        match task_manager.cancel_task(task_id).await? {
+            TaskStatus::Running => {
+                println!("Cancellation requested, task stops after the current chunk.");
+            }
+            _ => println!("Task cancelled."),
+        }
         println!("Task ID: {}", task_id);
         Ok(())
     }
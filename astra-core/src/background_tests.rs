@@ -0,0 +1,304 @@
+#[cfg(test)]
+mod tests {
+    use crate::background::{
+        task_project, BackgroundTask, RetentionPolicy, TaskManager, TaskStatus, TaskType,
+    };
+    use crate::progress::ProgressEvent;
+    use crate::types::SftpConfig;
+    use serde_json::json;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    /// A project whose server refuses connections, so tasks fail fast without a network
+    fn unreachable_config(temp_dir: &TempDir) -> SftpConfig {
+        fs::create_dir(temp_dir.path().join(".astra-settings")).unwrap();
+        SftpConfig {
+            host: "127.0.0.1".to_string(),
+            port: 1,
+            username: "deploy".to_string(),
+            password: Some("secret".to_string()),
+            remote_path: "/srv/site".to_string(),
+            ..SftpConfig::for_test(temp_dir.path().to_string_lossy())
+        }
+    }
+
+    fn upload(name: &str) -> TaskType {
+        TaskType::FileUpload {
+            local_path: PathBuf::from(name),
+            remote_path: PathBuf::from("/srv/site").join(name),
+        }
+    }
+
+    /// Journal `tasks` for the project as a previous process would have left them
+    fn write_journal(config: &SftpConfig, tasks: &[BackgroundTask]) {
+        fs::write(
+            PathBuf::from(&config.local_path).join(".astra-settings/tasks.json"),
+            json!({ "version": 1, "tasks": tasks }).to_string(),
+        )
+        .unwrap();
+    }
+
+    fn journaled(config: &SftpConfig, status: TaskStatus, restarts: u32) -> BackgroundTask {
+        let mut task = BackgroundTask::new(upload("c.txt"), config.clone(), None);
+        task.status = status;
+        task.restarts = restarts;
+        task
+    }
+
+    async fn wait_until_finished(manager: &TaskManager, id: &str) -> TaskStatus {
+        for _ in 0..100 {
+            let status = manager.get_task_status(id).await.unwrap().status;
+            if !matches!(status, TaskStatus::Pending | TaskStatus::Running) {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("task {} did not finish", id);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cancelled_task_ends_cancelled() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = unreachable_config(&temp_dir);
+        let manager = TaskManager::new();
+
+        let first = manager
+            .submit_task(BackgroundTask::new(upload("a.txt"), config.clone(), None))
+            .await
+            .unwrap();
+        let second = manager
+            .submit_task(BackgroundTask::new(upload("a.txt"), config, None))
+            .await
+            .unwrap();
+
+        // The second save of the same project waits behind the first one (unless the first
+        // already failed to connect, in which case it stops at its first cancellation check)
+        assert!(matches!(
+            manager.cancel_task(&second).await.unwrap(),
+            TaskStatus::Cancelled | TaskStatus::Running
+        ));
+
+        assert!(matches!(
+            wait_until_finished(&manager, &first).await,
+            TaskStatus::Failed(_)
+        ));
+        assert_eq!(
+            wait_until_finished(&manager, &second).await,
+            TaskStatus::Cancelled
+        );
+        assert!(manager
+            .get_task_status(&second)
+            .await
+            .unwrap()
+            .result
+            .is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_locked_project_holds_back_its_tasks() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = unreachable_config(&temp_dir);
+        let manager = TaskManager::new();
+
+        // A direct command of the project is running
+        let running = manager.lock_project(&task_project(&config)).await;
+        let id = manager
+            .submit_task(BackgroundTask::new(upload("a.txt"), config, None))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            manager.get_task_status(&id).await.unwrap().status,
+            TaskStatus::Pending
+        );
+
+        drop(running);
+        assert!(matches!(
+            wait_until_finished(&manager, &id).await,
+            TaskStatus::Failed(_)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_finished_task_cannot_be_cancelled() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = TaskManager::new();
+
+        let id = manager
+            .submit_task(BackgroundTask::new(
+                upload("b.txt"),
+                unreachable_config(&temp_dir),
+                None,
+            ))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            wait_until_finished(&manager, &id).await,
+            TaskStatus::Failed(_)
+        ));
+        assert!(manager.cancel_task(&id).await.is_err());
+        assert!(manager.cancel_task("missing").await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tasks_survive_a_restart() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = unreachable_config(&temp_dir);
+        let manager = TaskManager::new();
+
+        let id = manager
+            .submit_task(BackgroundTask::new(upload("a.txt"), config.clone(), None))
+            .await
+            .unwrap();
+        wait_until_finished(&manager, &id).await;
+
+        let journal =
+            fs::read_to_string(temp_dir.path().join(".astra-settings/tasks.json")).unwrap();
+        assert!(journal.contains(&id));
+        assert!(!journal.contains("secret"));
+
+        // A fresh process only reading the journal sees the task with its outcome
+        let restarted = TaskManager::new();
+        assert_eq!(
+            restarted.load_project(&task_project(&config), false).await,
+            1
+        );
+        assert!(matches!(
+            restarted.get_task_status(&id).await.unwrap().status,
+            TaskStatus::Failed(_)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_subtree_task_is_journaled_in_the_project() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = unreachable_config(&temp_dir);
+        let subtree = temp_dir.path().join("public");
+        fs::create_dir(&subtree).unwrap();
+        let mut config = project.clone();
+        config.local_path = subtree.to_string_lossy().to_string();
+        let manager = TaskManager::new();
+
+        let task = BackgroundTask::new(upload("a.txt"), config, None)
+            .with_roots(Some(subtree.to_string_lossy().to_string()), None);
+        assert_eq!(task.project, project.local_path);
+        let id = manager.submit_task(task).await.unwrap();
+        wait_until_finished(&manager, &id).await;
+        assert!(!subtree.join(".astra-settings").exists());
+
+        // The restored task still knows which subtree it was queued for
+        let restarted = TaskManager::new();
+        assert_eq!(restarted.load_project(&project.local_path, false).await, 1);
+        assert_eq!(
+            restarted.get_task_status(&id).await.unwrap().local_root,
+            Some(subtree.to_string_lossy().to_string())
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_interrupted_task_is_failed_after_its_restarts() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = unreachable_config(&temp_dir);
+        let task = journaled(&config, TaskStatus::Running, 1);
+        write_journal(&config, std::slice::from_ref(&task));
+
+        let manager = TaskManager::new();
+        assert_eq!(manager.load_project(&task_project(&config), true).await, 1);
+
+        let expected = TaskStatus::Failed("Interrupted by a restart".to_string());
+        assert_eq!(
+            manager.get_task_status(&task.id).await.unwrap().status,
+            expected
+        );
+
+        let reread = TaskManager::new();
+        reread.load_project(&task_project(&config), false).await;
+        assert_eq!(
+            reread.get_task_status(&task.id).await.unwrap().status,
+            expected
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_retention_keeps_the_newest_finished_tasks() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = unreachable_config(&temp_dir);
+        let now = SystemTime::now();
+        let tasks: Vec<BackgroundTask> = (0..3u64)
+            .map(|age| {
+                let mut task = journaled(&config, TaskStatus::Completed, 0);
+                task.updated_at = now - Duration::from_secs(60 * (age + 1));
+                task
+            })
+            .collect();
+        write_journal(&config, &tasks);
+
+        let manager = TaskManager::with_retention(RetentionPolicy {
+            max_finished_per_project: 2,
+            ..RetentionPolicy::default()
+        });
+        manager.load_project(&task_project(&config), true).await;
+
+        let kept: Vec<String> = manager
+            .get_all_tasks()
+            .await
+            .into_iter()
+            .map(|task| task.id)
+            .collect();
+        assert_eq!(kept.len(), 2);
+        assert!(!kept.contains(&tasks[2].id));
+
+        manager.cleanup_old_tasks(Duration::ZERO).await;
+        assert!(manager.get_all_tasks().await.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_subscribers_see_task_state_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = unreachable_config(&temp_dir);
+        let manager = TaskManager::new();
+        let mut events = manager.subscribe();
+
+        let id = manager
+            .submit_task(BackgroundTask::new(upload("a.txt"), config, None))
+            .await
+            .unwrap();
+
+        let mut states = Vec::new();
+        while !matches!(states.last(), Some(TaskStatus::Failed(_))) {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+                .await
+                .expect("task did not finish")
+                .unwrap();
+            if let ProgressEvent::TaskState {
+                task_id,
+                status,
+            } = event
+            {
+                assert_eq!(task_id, id);
+                states.push(status);
+            }
+        }
+        assert_eq!(states[..2], [TaskStatus::Pending, TaskStatus::Running]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_untagged_progress_reaches_subscribers() {
+        let manager = TaskManager::new();
+        let mut events = manager.subscribe();
+
+        manager.reporter().file(Path::new("a.txt")).start(Some(3));
+
+        assert_eq!(
+            events.recv().await.unwrap(),
+            ProgressEvent::FileStarted {
+                task_id: None,
+                path: PathBuf::from("a.txt"),
+                total_bytes: Some(3),
+            }
+        );
+    }
+}
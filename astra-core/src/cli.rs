@@ -1,12 +1,30 @@
+use crate::background::{self, TaskCommands, TaskManager};
 use crate::config::ConfigReader;
-use crate::error::AstraResult;
+use crate::error::{exit_code, AstraError, AstraResult};
+use crate::output::{Output, OutputFormat};
+use crate::paths::PathMapper;
+use crate::planner::{self, PlanReport, SyncPlan};
 use crate::sftp::SftpClient;
-use crate::types::{SftpConfig, SyncResult};
+use crate::state::SyncState;
+use crate::transfer::{self, TransferPool};
+use crate::types::{
+    AdvancedConfig, ConflictPolicy, ErrorPolicy, FileResult, OperationType, SftpConfig, SyncMode,
+    SyncOperation, SyncOptions, SyncReason, SyncResult,
+};
+use crate::version;
 use clap::{Parser, Subcommand};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use serde_json;
+use std::collections::HashSet;
 use std::fs;
-use std::path::Path;
-use {serde_json, tracing_subscriber};
-use crate::version;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, Registry};
 
 // Version constants are included via version module
 
@@ -25,6 +43,10 @@ pub struct Cli {
     /// Show detailed build information
     #[arg(long)]
     pub build_info: bool,
+
+    /// Output format: human-readable text, or a single versioned JSON document per command
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
 }
 
 #[derive(Subcommand)]
@@ -43,6 +65,31 @@ pub enum Commands {
         #[arg(short, long, default_value = "upload")]
         mode: String,
 
+        /// Override the local root from the configuration
+        #[arg(short, long)]
+        local: Option<String>,
+
+        /// Override the remote root from the configuration
+        #[arg(short, long)]
+        remote: Option<String>,
+
+        /// How to resolve files changed on both sides: prefer-local, prefer-remote,
+        /// newest-wins, keep-both or ask
+        #[arg(long)]
+        conflict_policy: Option<String>,
+
+        /// Abort if the sync would delete more files than this (0 means no limit)
+        #[arg(long)]
+        max_deletions: Option<usize>,
+
+        /// What to do once a file fails after its retries: continue or stop
+        #[arg(long)]
+        on_error: Option<String>,
+
+        /// Print the planned operations as JSON without changing either side
+        #[arg(long)]
+        dry_run: bool,
+
         #[arg(trailing_var_arg = true)]
         files: Vec<String>,
     },
@@ -51,9 +98,17 @@ pub enum Commands {
     Status {
         #[arg(short, long)]
         config: Option<String>,
+
+        /// Override the local root from the configuration
+        #[arg(short, long)]
+        local: Option<String>,
+
+        /// Override the remote root from the configuration
+        #[arg(short, long)]
+        remote: Option<String>,
     },
 
-    #[command(about = "Upload a single file")]
+    #[command(about = "Upload a file or a directory tree")]
     Upload {
         #[arg(short, long)]
         config: Option<String>,
@@ -63,9 +118,13 @@ pub enum Commands {
 
         #[arg(short, long)]
         remote: String,
+
+        /// Upload the whole tree below `--local` (implied when it is a directory)
+        #[arg(long)]
+        recursive: bool,
     },
 
-    #[command(about = "Download a single file")]
+    #[command(about = "Download a file or a directory tree")]
     Download {
         #[arg(short, long)]
         config: Option<String>,
@@ -75,6 +134,10 @@ pub enum Commands {
 
         #[arg(short, long)]
         local: String,
+
+        /// Download the whole tree below `--remote` (implied when it is a directory)
+        #[arg(long)]
+        recursive: bool,
     },
 
     #[command(about = "Test configuration file discovery")]
@@ -88,96 +151,252 @@ pub enum Commands {
 
     #[command(about = "Check for updates")]
     CheckUpdate,
+
+    #[command(about = "Run as a daemon serving JSON-RPC over stdin/stdout")]
+    Serve,
+
+    #[command(about = "List the journaled background tasks of the project")]
+    Tasks {
+        #[arg(short, long)]
+        config: Option<String>,
+
+        /// Show a single task in detail
+        #[arg(long)]
+        id: Option<String>,
+    },
 }
 
-pub async fn run_cli(cli: Cli) -> AstraResult<()> {
-    tracing_subscriber::fmt::init();
+impl Commands {
+    /// Name of the command as typed on the command line, used in JSON output
+    pub fn name(&self) -> &'static str {
+        match self {
+            Commands::Init {
+                ..
+            } => "init",
+            Commands::Sync {
+                ..
+            } => "sync",
+            Commands::Status {
+                ..
+            } => "status",
+            Commands::Upload {
+                ..
+            } => "upload",
+            Commands::Download {
+                ..
+            } => "download",
+            Commands::ConfigTest {
+                ..
+            } => "config-test",
+            Commands::Version => "version",
+            Commands::CheckUpdate => "check-update",
+            Commands::Serve => "serve",
+            Commands::Tasks {
+                ..
+            } => "tasks",
+        }
+    }
+}
 
+/// Handle for raising the log level once a config with `verbose_logging` is loaded
+static LOG_LEVEL: OnceCell<reload::Handle<LevelFilter, Registry>> = OnceCell::new();
+
+fn init_logging() {
+    // Logs go to stderr so that stdout only carries command output
+    let (level, handle) = reload::Layer::new(LevelFilter::INFO);
+    let _ = tracing_subscriber::registry()
+        .with(level)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_ansi(std::io::stderr().is_terminal()),
+        )
+        .try_init();
+    let _ = LOG_LEVEL.set(handle);
+}
+
+/// Switch to debug logging when `[advanced] verbose_logging` is enabled
+pub(crate) fn apply_verbose_logging(config: &SftpConfig) {
+    if config.advanced.verbose_logging {
+        if let Some(handle) = LOG_LEVEL.get() {
+            let _ = handle.modify(|level| *level = LevelFilter::DEBUG);
+        }
+    }
+}
+
+/// Run a command and return the process exit code it finished with (see [`exit_code`]).
+/// Errors are returned as-is; map them with [`crate::error::AstraError::exit_code`].
+pub async fn run_cli(cli: Cli) -> AstraResult<i32> {
+    init_logging();
+
+    let output = Output::new(cli.output);
+    let command = if cli.version || cli.build_info {
+        "version"
+    } else {
+        cli.command.name()
+    };
+
+    let result = run_command(cli, output).await;
+    if let Err(e) = &result {
+        output.emit_error(command, e);
+    }
+    result
+}
+
+async fn run_command(cli: Cli, output: Output) -> AstraResult<i32> {
     // Handle version and build info flags
     if cli.version {
-        print_version();
-        return Ok(());
+        show_version(output)?;
+        return Ok(exit_code::SUCCESS);
     }
 
     if cli.build_info {
-        print_build_info();
-        return Ok(());
+        if output.is_json() {
+            show_version(output)?;
+        } else {
+            print_build_info();
+        }
+        return Ok(exit_code::SUCCESS);
     }
 
-    match cli.command {
+    let code = match cli.command {
         Commands::Init {
             config,
         } => {
-            init_config(&config).await?;
+            init_config(&config, output).await?;
+            exit_code::SUCCESS
         }
         Commands::Sync {
             config,
             mode,
+            local,
+            remote,
+            conflict_policy,
+            max_deletions,
+            on_error,
+            dry_run,
             files,
         } => {
+            let overrides = RootOverrides {
+                local: local.as_deref(),
+                remote: remote.as_deref(),
+            };
+            let options = SyncOverrides {
+                conflict_policy: conflict_policy.as_deref(),
+                max_deletions,
+                on_error: on_error.as_deref(),
+                dry_run,
+            };
             if let Some(config_path) = config {
-                sync_files(Some(&config_path), &mode, &files).await?;
+                sync_files(
+                    Some(&config_path),
+                    &mode,
+                    options,
+                    &files,
+                    overrides,
+                    output,
+                )
+                .await?
             } else {
                 // Use automatic config discovery
-                sync_files(None, &mode, &files).await?;
+                sync_files(None, &mode, options, &files, overrides, output).await?
             }
         }
         Commands::Status {
             config,
+            local,
+            remote,
         } => {
+            let overrides = RootOverrides {
+                local: local.as_deref(),
+                remote: remote.as_deref(),
+            };
             if let Some(config_path) = config {
-                check_status(Some(&config_path)).await?;
+                check_status(Some(&config_path), overrides, output).await?;
             } else {
                 // Use automatic config discovery
-                check_status(None).await?;
+                check_status(None, overrides, output).await?;
             }
+            exit_code::SUCCESS
         }
         Commands::Upload {
             config,
             local,
             remote,
+            recursive,
         } => {
             if let Some(config_path) = config {
-                upload_single_file(Some(&config_path), &local, &remote).await?;
+                upload_single_file(Some(&config_path), &local, &remote, recursive, output).await?
             } else {
                 // Use automatic config discovery
-                upload_single_file(None, &local, &remote).await?;
+                upload_single_file(None, &local, &remote, recursive, output).await?
             }
         }
         Commands::Download {
             config,
             remote,
             local,
+            recursive,
         } => {
             if let Some(config_path) = config {
-                download_single_file(Some(&config_path), &remote, &local).await?;
+                download_single_file(Some(&config_path), &remote, &local, recursive, output).await?
             } else {
                 // Use automatic config discovery
-                download_single_file(None, &remote, &local).await?;
+                download_single_file(None, &remote, &local, recursive, output).await?
             }
         }
         Commands::ConfigTest {
             config,
         } => {
             if let Some(config_path) = config {
-                test_config(Some(&config_path)).await?;
+                test_config(Some(&config_path), output).await?;
             } else {
                 // Use automatic config discovery
-                test_config(None).await?;
+                test_config(None, output).await?;
             }
+            exit_code::SUCCESS
         }
         Commands::Version => {
-            show_version()?;
+            show_version(output)?;
+            exit_code::SUCCESS
         }
         Commands::CheckUpdate => {
-            check_for_updates().await?;
+            check_for_updates(output).await?;
+            exit_code::SUCCESS
         }
-    }
+        Commands::Serve => {
+            crate::serve::serve().await?;
+            exit_code::SUCCESS
+        }
+        Commands::Tasks {
+            config,
+            id,
+        } => {
+            show_tasks(config.as_deref(), id.as_deref(), output).await?;
+            exit_code::SUCCESS
+        }
+    };
 
-    Ok(())
+    Ok(code)
+}
+
+/// `--local`/`--remote` values layered over the loaded configuration
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct RootOverrides<'a> {
+    pub(crate) local: Option<&'a str>,
+    pub(crate) remote: Option<&'a str>,
+}
+
+/// Per-run `sync` options layered over the `[sync]` section
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SyncOverrides<'a> {
+    pub(crate) conflict_policy: Option<&'a str>,
+    pub(crate) max_deletions: Option<usize>,
+    pub(crate) on_error: Option<&'a str>,
+    pub(crate) dry_run: bool,
 }
 
-async fn init_config(config_path: &str) -> AstraResult<()> {
+async fn init_config(config_path: &str, output: Output) -> AstraResult<()> {
     let language = crate::i18n::detect_language();
     let default_config = SftpConfig {
         host: "example.com".to_string(),
@@ -193,246 +412,738 @@ async fn init_config(config_path: &str) -> AstraResult<()> {
             .to_string(),
         language: Some(language),
         enabled: Some(true),
+        known_hosts_path: None,
+        host_key_policy: None,
+        auth_methods: None,
+        private_key_passphrase: None,
+        private_key_passphrase_env: None,
+        private_key_passphrase_command: None,
+        sync: SyncOptions {
+            exclude_patterns: vec![
+                ".git/".to_string(),
+                "*.tmp".to_string(),
+                "*.log".to_string(),
+                ".DS_Store".to_string(),
+            ],
+            ..SyncOptions::default()
+        },
+        advanced: AdvancedConfig::default(),
     };
 
     let config_json = serde_json::to_string_pretty(&default_config)?;
     fs::write(config_path, config_json)?;
 
     let msg = crate::i18n::t_format("cli.config_initialized", &language, &[config_path]);
-    println!("{}", msg);
-    Ok(())
+    output.say(msg);
+    output.emit(
+        "init",
+        true,
+        &serde_json::json!({ "config_path": config_path }),
+    )
 }
 
-async fn sync_files(config_path: Option<&str>, _mode: &str, files: &[String]) -> AstraResult<()> {
-    // Initialize i18n system
-    crate::i18n::init_translations();
-
+/// Read the configuration from `config_path` (or by discovery) with the root overrides applied
+pub(crate) fn load_config(
+    config_path: Option<&str>, overrides: RootOverrides<'_>,
+) -> AstraResult<SftpConfig> {
     let config_reader = match config_path {
         Some(path) => ConfigReader::new(Some(path.to_string())),
         None => ConfigReader::new(None), // Use automatic discovery
     };
-    let config = config_reader.read_config()?;
+    ConfigReader::apply_root_overrides(
+        config_reader.read_config()?,
+        overrides.local,
+        overrides.remote,
+    )
+}
+
+/// Read the configuration for a `sync` run, with the per-run options applied
+pub(crate) fn load_sync_config(
+    config_path: Option<&str>, overrides: RootOverrides<'_>, options: SyncOverrides<'_>,
+) -> AstraResult<SftpConfig> {
+    let conflict_policy: Option<ConflictPolicy> =
+        options.conflict_policy.map(str::parse).transpose()?;
+    let on_error: Option<ErrorPolicy> = options.on_error.map(str::parse).transpose()?;
+
+    let mut config = load_config(config_path, overrides)?;
+    if let Some(conflict_policy) = conflict_policy {
+        config.sync.conflict_policy = conflict_policy;
+    }
+    if let Some(max_deletions) = options.max_deletions {
+        config.sync.max_deletions = max_deletions;
+    }
+    if let Some(on_error) = on_error {
+        config.sync.on_error = on_error;
+    }
+    Ok(config)
+}
+
+/// What `sync` reports: the plan for a dry run, the outcome otherwise
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub(crate) enum SyncReport {
+    Plan(PlanReport),
+    Result(SyncResult),
+}
+
+impl SyncReport {
+    pub(crate) fn ok(&self) -> bool {
+        match self {
+            SyncReport::Plan(_) => true,
+            SyncReport::Result(result) => result.success,
+        }
+    }
+}
+
+/// What `upload`/`download` report: a single file, or one result per file of a directory
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub(crate) enum TransferReport {
+    File(FileResult),
+    Tree(SyncResult),
+}
+
+impl TransferReport {
+    pub(crate) fn ok(&self) -> bool {
+        match self {
+            TransferReport::File(result) => result.success,
+            TransferReport::Tree(result) => result.success,
+        }
+    }
+
+    fn print(&self, command: &str, output: Output) -> AstraResult<()> {
+        if output.is_json() {
+            output.emit(command, self.ok(), self)
+        } else {
+            if let TransferReport::Tree(result) = self {
+                println!("{}", serde_json::to_string_pretty(result)?);
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn sync_files(
+    config_path: Option<&str>, mode: &str, options: SyncOverrides<'_>, files: &[String],
+    overrides: RootOverrides<'_>, output: Output,
+) -> AstraResult<i32> {
+    let mode: SyncMode = mode.parse()?;
+    let config = load_sync_config(config_path, overrides, options)?;
+
+    apply_verbose_logging(&config);
+    let mut sessions = vec![connect(config, output)?];
+    let (report, code) = run_sync(&mut sessions, mode, files, options.dry_run, output)?;
+
+    if output.is_json() {
+        output.emit("sync", report.ok(), &report)?;
+    } else {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+
+    Ok(code)
+}
+
+/// Plan and run a sync over connected sessions, returning the report and the exit code.
+///
+/// `sessions[0]` scans and plans; all sessions (plus any the transfer pool opens) carry the
+/// transfers and are handed back in `sessions` afterwards, so a long-lived caller can reuse them.
+pub(crate) fn run_sync(
+    sessions: &mut Vec<SftpClient>, mode: SyncMode, files: &[String], dry_run: bool, output: Output,
+) -> AstraResult<(SyncReport, i32)> {
+    // Initialize i18n system
+    crate::i18n::init_translations();
+
+    let client = &sessions[0];
+    let config = client.config().clone();
     let language = config.language.unwrap_or_else(crate::i18n::detect_language);
-    let config_for_path = config.clone();
 
-    let client = SftpClient::new(config)?;
+    let mut sync_result = SyncResult {
+        success: true,
+        message: crate::i18n::t("cli.sync_complete", &language),
+        files_transferred: Vec::new(),
+        files_skipped: Vec::new(),
+        errors: Vec::new(),
+        file_results: Vec::new(),
+        conflicts: Vec::new(),
+    };
 
-    // If specific files are provided, sync only those files
-    if !files.is_empty() {
-        let mut sync_result = SyncResult {
-            success: true,
-            message: "File sync completed successfully".to_string(),
-            files_transferred: Vec::new(),
-            files_skipped: Vec::new(),
-            errors: Vec::new(),
+    let mapper = PathMapper::from_config(&config);
+    let mut state = SyncState::load(&config);
+    let mut scan = None;
+
+    let plan = if !files.is_empty() {
+        // If specific files are provided, sync only those files
+        let operation_type = if mode == SyncMode::Download {
+            OperationType::Download
+        } else {
+            OperationType::Upload
         };
 
+        let mut operations = Vec::new();
         for file_path in files {
             let local_path = std::path::Path::new(file_path);
 
-            // Generate remote path based on the local path and configuration
-            let remote_path = if let Some(file_name) = local_path.file_name() {
-                // Simple approach: use the filename and append to remote_path
-                std::path::Path::new(&config_for_path.remote_path).join(file_name)
-            } else {
-                println!("Warning: Could not determine filename for {}", file_path);
-                continue;
-            };
-
-            println!(
-                "Syncing file: {} -> {}",
-                local_path.display(),
-                remote_path.display()
-            );
-
-            match client.upload_file(local_path, &remote_path) {
-                Ok(_) => {
-                    sync_result
-                        .files_transferred
-                        .push(local_path.to_string_lossy().to_string());
-                    let msg = crate::i18n::t_format("cli.file_uploaded", &language, &[file_path]);
-                    println!("✅ {}", msg);
-                }
+            // Map the local path onto the remote tree relative to the configured roots
+            let relative_path = match mapper.local_to_relative(local_path) {
+                Ok(relative_path) => relative_path,
                 Err(e) => {
+                    output.say(format!("Warning: {}", e));
                     sync_result.errors.push(e.to_string());
-                    let error_msg = crate::i18n::t("error.upload_failed", &language);
-                    println!("❌ {}: {} - {}", error_msg, file_path, e);
+                    continue;
                 }
+            };
+
+            if client.filter().is_excluded(&relative_path, false) {
+                output.say(format!("Skipping excluded file: {}", file_path));
+                sync_result.files_skipped.push(file_path.clone());
+                continue;
             }
-        }
 
-        // Update success status based on errors
-        if !sync_result.errors.is_empty() {
-            sync_result.success = false;
-            let error_count = sync_result.errors.len().to_string();
-            sync_result.message =
-                crate::i18n::t_format("cli.sync_failed", &language, &[&error_count]);
-        } else {
-            sync_result.message = crate::i18n::t("cli.sync_complete", &language);
+            let remote_path = mapper.relative_to_remote(&relative_path);
+            let bytes = match operation_type {
+                OperationType::Download => client
+                    .stat_remote_file(&remote_path)
+                    .map_or(0, |file| file.size),
+                _ => fs::metadata(local_path).map_or(0, |metadata| metadata.len()),
+            };
+            operations.push(SyncOperation {
+                operation_type,
+                local_path: local_path.to_path_buf(),
+                remote_path,
+                timestamp: chrono::Utc::now(),
+                reason: Some(SyncReason::Requested),
+                bytes,
+            });
+        }
+        SyncPlan {
+            operations,
+            conflicts: Vec::new(),
         }
-
-        let result_json = serde_json::to_string_pretty(&sync_result)?;
-        println!("{}", result_json);
     } else {
-        // No specific files provided, do full incremental sync
-        let operations = client.sync_incremental()?;
-
-        let mut sync_result = SyncResult {
-            success: true,
-            message: crate::i18n::t("cli.sync_complete", &language),
-            files_transferred: Vec::new(),
-            files_skipped: Vec::new(),
-            errors: Vec::new(),
-        };
+        // No specific files provided, do full incremental sync against the last baseline
+        let tree = client.scan_trees()?;
+        planner::check_remote_root(tree.root_missing, &mapper, &state)?;
+        let mut plan = planner::plan_operations(
+            mode,
+            &mapper,
+            &tree.local,
+            &tree.remote,
+            &state,
+            config.sync.conflict_policy,
+        );
+        // What could not be listed is skipped, and nothing is deleted on a partial scan
+        planner::restrict_to_listed(&mut plan, &mapper, &tree.unlisted);
+        for path in &tree.unlisted {
+            let message = format!(
+                "Remote {} could not be listed; it was skipped and no deletions were made",
+                mapper.relative_to_remote(path).display()
+            );
+            output.say(format!("Warning: {}", message));
+            sync_result.errors.push(message);
+        }
+        scan = Some(tree);
+        plan
+    };
 
-        for operation in &operations {
-            match operation.operation_type {
-                crate::types::OperationType::Upload => {
-                    let local_path = operation.local_path.display().to_string();
-                    let remote_path = operation.remote_path.display().to_string();
-                    let msg = crate::i18n::t_format(
-                        "cli.upload_operation",
-                        &language,
-                        &[&local_path, &remote_path],
-                    );
-                    println!("{}", msg);
-
-                    match client.upload_file(&operation.local_path, &operation.remote_path) {
-                        Ok(_) => {
-                            sync_result
-                                .files_transferred
-                                .push(operation.local_path.to_string_lossy().to_string());
-                        }
-                        Err(e) => {
-                            sync_result.errors.push(e.to_string());
-                        }
-                    }
-                }
-                crate::types::OperationType::Download => {
-                    let remote_path = operation.remote_path.display().to_string();
-                    let local_path = operation.local_path.display().to_string();
-                    let msg = crate::i18n::t_format(
-                        "cli.download_operation",
-                        &language,
-                        &[&remote_path, &local_path],
-                    );
-                    println!("{}", msg);
-
-                    match client.download_file(&operation.remote_path, &operation.local_path) {
-                        Ok(_) => {
-                            sync_result
-                                .files_transferred
-                                .push(operation.local_path.to_string_lossy().to_string());
-                        }
-                        Err(e) => {
-                            sync_result.errors.push(e.to_string());
-                        }
-                    }
-                }
-                _ => {}
-            }
+    if dry_run {
+        let report = plan_report(client, mode, &plan);
+        return Ok((SyncReport::Plan(report), exit_code::SUCCESS));
+    }
+
+    planner::check_deletion_limit(&plan.operations, config.sync.max_deletions)?;
+    client.clean_stale_temp_files();
+    sync_result.conflicts = plan.conflicts;
+    let operations = plan.operations;
+
+    for operation in &operations {
+        if let Some(msg) = describe_operation(operation, &language) {
+            output.say(msg);
         }
+    }
 
-        let result_json = serde_json::to_string_pretty(&sync_result)?;
-        println!("{}", result_json);
+    // Unresolved conflicts are only reported; everything else goes to the transfer pool
+    let transfers: Vec<SyncOperation> = operations
+        .iter()
+        .filter(|operation| operation.operation_type != OperationType::Conflict)
+        .cloned()
+        .collect();
+    if !transfers.is_empty() {
+        let workers = config
+            .advanced
+            .max_concurrent_transfers
+            .min(transfers.len());
+        let mut pool = TransferPool::from_clients(std::mem::take(sessions), workers);
+        sync_result.file_results = pool.execute(&transfers);
+        state.record_results(&mapper, &sync_result.file_results, |remote_path| {
+            pool.primary().stat_remote_file(remote_path)
+        });
+        *sessions = pool.into_clients();
     }
 
-    Ok(())
+    if let Some(scan) = &scan {
+        let touched: HashSet<PathBuf> = operations
+            .iter()
+            .filter_map(|operation| mapper.local_to_relative(&operation.local_path).ok())
+            .collect();
+        state.record_unchanged(&scan.local, &scan.remote, &touched);
+        // A path missing from a partial scan may still exist, so its baseline is kept
+        if scan.unlisted.is_empty() {
+            state.prune_missing(&scan.local, &scan.remote);
+        }
+    }
+    if let Err(e) = state.save() {
+        warn!("Failed to save sync state: {}", e);
+    }
+
+    collect_file_results(&mut sync_result, &language, output);
+
+    let pending_conflicts = sync_result
+        .conflicts
+        .iter()
+        .filter(|conflict| conflict.resolution.is_none())
+        .count();
+
+    let code = if !sync_result.errors.is_empty() {
+        sync_result.success = false;
+        let error_count = sync_result.errors.len().to_string();
+        sync_result.message = crate::i18n::t_format("cli.sync_failed", &language, &[&error_count]);
+        exit_code::PARTIAL_FAILURE
+    } else if pending_conflicts > 0 {
+        sync_result.message = crate::i18n::t_format(
+            "cli.conflicts_pending",
+            &language,
+            &[&pending_conflicts.to_string()],
+        );
+        exit_code::CONFLICTS_PENDING
+    } else {
+        exit_code::SUCCESS
+    };
+
+    Ok((SyncReport::Result(sync_result), code))
+}
+
+/// Dry-run report of `plan`, checking which remote directories it would have to create
+fn plan_report(client: &SftpClient, mode: SyncMode, plan: &SyncPlan) -> PlanReport {
+    let config = client.config();
+    PlanReport::new(
+        mode,
+        &PathMapper::from_config(config),
+        plan,
+        config.sync.max_deletions,
+        |dir| {
+            client
+                .stat_remote_file(dir)
+                .is_some_and(|file| file.is_directory)
+        },
+    )
 }
 
-async fn check_status(config_path: Option<&str>) -> AstraResult<()> {
+/// Pending bidirectional changes since the last sync, as reported by `status --output json`
+pub(crate) fn status_report(client: &SftpClient) -> AstraResult<PlanReport> {
+    let state = SyncState::load(client.config());
+    let plan = client.sync_incremental(SyncMode::Bidirectional, &state)?;
+    Ok(plan_report(client, SyncMode::Bidirectional, &plan))
+}
+
+async fn check_status(
+    config_path: Option<&str>, overrides: RootOverrides<'_>, output: Output,
+) -> AstraResult<()> {
     // Initialize i18n system
     crate::i18n::init_translations();
 
-    let config_reader = match config_path {
-        Some(path) => ConfigReader::new(Some(path.to_string())),
-        None => ConfigReader::new(None), // Use automatic discovery
-    };
-    let config = config_reader.read_config()?;
+    let config = load_config(config_path, overrides)?;
     let language = config.language.unwrap_or_else(crate::i18n::detect_language);
 
+    apply_verbose_logging(&config);
     let client = SftpClient::new(config)?;
-    let operations = client.sync_incremental()?;
+
+    if output.is_json() {
+        return output.emit("status", true, &status_report(&client)?);
+    }
+
+    let state = SyncState::load(client.config());
+    let plan = client.sync_incremental(SyncMode::Bidirectional, &state)?;
 
     let pending_msg = crate::i18n::t_format(
         "cli.pending_operations",
         &language,
-        &[&operations.len().to_string()],
+        &[&plan.operations.len().to_string()],
     );
     println!("{}", pending_msg);
 
-    for operation in &operations {
-        match operation.operation_type {
-            crate::types::OperationType::Upload => {
-                let local_path = operation.local_path.display().to_string();
-                let remote_path = operation.remote_path.display().to_string();
-                let msg = crate::i18n::t_format(
-                    "cli.upload_operation",
-                    &language,
-                    &[&local_path, &remote_path],
-                );
-                println!("  {}", msg);
-            }
-            crate::types::OperationType::Download => {
-                let remote_path = operation.remote_path.display().to_string();
-                let local_path = operation.local_path.display().to_string();
-                let msg = crate::i18n::t_format(
-                    "cli.download_operation",
-                    &language,
-                    &[&remote_path, &local_path],
-                );
-                println!("  {}", msg);
-            }
-            _ => {}
+    for operation in &plan.operations {
+        if let Some(msg) = describe_operation(operation, &language) {
+            println!("  {}", msg);
         }
     }
 
     Ok(())
 }
 
-async fn upload_single_file(
-    config_path: Option<&str>, local_path: &str, remote_path: &str,
-) -> AstraResult<()> {
-    let config_reader = match config_path {
-        Some(path) => ConfigReader::new(Some(path.to_string())),
-        None => ConfigReader::new(None), // Use automatic discovery
+/// Sort per-file results into `files_transferred` and `errors`, reporting every failure
+fn collect_file_results(
+    sync_result: &mut SyncResult, language: &crate::i18n::Language, output: Output,
+) {
+    for result in &sync_result.file_results {
+        let path = match result.operation_type {
+            OperationType::Delete | OperationType::CreateDirectory => &result.remote_path,
+            OperationType::Upload
+            | OperationType::Download
+            | OperationType::DeleteLocal
+            | OperationType::KeepBoth
+            | OperationType::Conflict => &result.local_path,
+        };
+        let path = path.to_string_lossy().to_string();
+
+        match &result.error {
+            None => sync_result.files_transferred.push(path),
+            Some(error) => {
+                let error_key = match result.operation_type {
+                    OperationType::Download => "error.download_failed",
+                    OperationType::Delete | OperationType::DeleteLocal => "error.delete_failed",
+                    _ => "error.upload_failed",
+                };
+                let error_msg = crate::i18n::t(error_key, language);
+                output.say(format!("❌ {}: {} - {}", error_msg, path, error));
+                sync_result.errors.push(format!("{}: {}", path, error));
+            }
+        }
+    }
+}
+
+/// One-line, translated description of a planned operation
+fn describe_operation(
+    operation: &SyncOperation, language: &crate::i18n::Language,
+) -> Option<String> {
+    let local_path = operation.local_path.display().to_string();
+    let remote_path = operation.remote_path.display().to_string();
+
+    let msg = match operation.operation_type {
+        OperationType::Upload => crate::i18n::t_format(
+            "cli.upload_operation",
+            language,
+            &[&local_path, &remote_path],
+        ),
+        OperationType::Download => crate::i18n::t_format(
+            "cli.download_operation",
+            language,
+            &[&remote_path, &local_path],
+        ),
+        OperationType::Delete => {
+            crate::i18n::t_format("cli.delete_operation", language, &[&remote_path])
+        }
+        OperationType::DeleteLocal => {
+            crate::i18n::t_format("cli.delete_local_operation", language, &[&local_path])
+        }
+        OperationType::Conflict => {
+            crate::i18n::t_format("cli.conflict_operation", language, &[&local_path])
+        }
+        OperationType::KeepBoth => {
+            let copy_path = planner::conflict_copy_path(&operation.local_path, operation.timestamp);
+            crate::i18n::t_format(
+                "cli.keep_both_operation",
+                language,
+                &[&local_path, &copy_path.display().to_string()],
+            )
+        }
+        OperationType::CreateDirectory => return None,
     };
-    let config = config_reader.read_config()?;
+    Some(msg)
+}
 
-    let client = SftpClient::new(config)?;
-    client.upload_file(Path::new(local_path), Path::new(remote_path))?;
+/// A session reporting transfer progress the way `output` prints
+fn connect(config: SftpConfig, output: Output) -> AstraResult<SftpClient> {
+    let mut client = SftpClient::new(config)?;
+    client.set_progress(output.progress());
+    Ok(client)
+}
+
+async fn upload_single_file(
+    config_path: Option<&str>, local_path: &str, remote_path: &str, recursive: bool, output: Output,
+) -> AstraResult<i32> {
+    let config = load_config(config_path, RootOverrides::default())?;
+
+    apply_verbose_logging(&config);
+    let mut sessions = vec![connect(config, output)?];
+    let (report, code) = upload_path(&mut sessions, local_path, remote_path, recursive, output)?;
+
+    report.print("upload", output)?;
+    Ok(code)
+}
 
-    println!(
+/// Upload a file, or every file below a directory, over connected sessions
+pub(crate) fn upload_path(
+    sessions: &mut Vec<SftpClient>, local_path: &str, remote_path: &str, recursive: bool,
+    output: Output,
+) -> AstraResult<(TransferReport, i32)> {
+    let is_directory = Path::new(local_path).is_dir();
+    if recursive && !is_directory {
+        return Err(AstraError::FileOperationError(format!(
+            "{} is not a directory",
+            local_path
+        )));
+    }
+    if is_directory {
+        let (result, code) = transfer_directory(
+            sessions,
+            OperationType::Upload,
+            local_path,
+            remote_path,
+            output,
+        )?;
+        return Ok((TransferReport::Tree(result), code));
+    }
+
+    let (outcome, attempts) = sessions[0].retry(Path::new(local_path), |client| {
+        client.upload_file(Path::new(local_path), Path::new(remote_path))
+    });
+    let bytes = outcome?;
+
+    output.say(format!(
         "File uploaded successfully: {} -> {}",
         local_path, remote_path
-    );
-    Ok(())
+    ));
+    let result = FileResult {
+        operation_type: OperationType::Upload,
+        local_path: PathBuf::from(local_path),
+        remote_path: PathBuf::from(remote_path),
+        success: true,
+        bytes,
+        error: None,
+        attempts,
+    };
+    Ok((TransferReport::File(result), exit_code::SUCCESS))
 }
 
 async fn download_single_file(
-    config_path: Option<&str>, remote_path: &str, local_path: &str,
-) -> AstraResult<()> {
-    let config_reader = match config_path {
-        Some(path) => ConfigReader::new(Some(path.to_string())),
-        None => ConfigReader::new(None), // Use automatic discovery
-    };
-    let config = config_reader.read_config()?;
+    config_path: Option<&str>, remote_path: &str, local_path: &str, recursive: bool, output: Output,
+) -> AstraResult<i32> {
+    let config = load_config(config_path, RootOverrides::default())?;
 
-    let client = SftpClient::new(config)?;
-    client.download_file(Path::new(remote_path), Path::new(local_path))?;
+    apply_verbose_logging(&config);
+    let mut sessions = vec![connect(config, output)?];
+    let (report, code) = download_path(&mut sessions, remote_path, local_path, recursive, output)?;
+
+    report.print("download", output)?;
+    Ok(code)
+}
 
-    println!(
+/// Download a file, or every file below a remote directory, over connected sessions
+pub(crate) fn download_path(
+    sessions: &mut Vec<SftpClient>, remote_path: &str, local_path: &str, recursive: bool,
+    output: Output,
+) -> AstraResult<(TransferReport, i32)> {
+    let is_directory = sessions[0]
+        .stat_remote_file(Path::new(remote_path))
+        .is_some_and(|file| file.is_directory);
+    if recursive && !is_directory {
+        return Err(AstraError::FileOperationError(format!(
+            "{} is not a remote directory",
+            remote_path
+        )));
+    }
+    if is_directory {
+        let (result, code) = transfer_directory(
+            sessions,
+            OperationType::Download,
+            local_path,
+            remote_path,
+            output,
+        )?;
+        return Ok((TransferReport::Tree(result), code));
+    }
+
+    let (outcome, attempts) = sessions[0].retry(Path::new(local_path), |client| {
+        client.download_file(Path::new(remote_path), Path::new(local_path))
+    });
+    let bytes = outcome?;
+
+    output.say(format!(
         "File downloaded successfully: {} -> {}",
         remote_path, local_path
-    );
-    Ok(())
+    ));
+    let result = FileResult {
+        operation_type: OperationType::Download,
+        local_path: PathBuf::from(local_path),
+        remote_path: PathBuf::from(remote_path),
+        success: true,
+        bytes,
+        error: None,
+        attempts,
+    };
+    Ok((TransferReport::File(result), exit_code::SUCCESS))
+}
+
+/// Upload or download every file below a directory, reporting one result per file.
+///
+/// The exclude rules are matched against paths relative to the sync root whenever the
+/// directory lies inside it, so `upload` of a subdirectory skips what `sync` would skip.
+fn transfer_directory(
+    sessions: &mut Vec<SftpClient>, operation_type: OperationType, local_dir: &str,
+    remote_dir: &str, output: Output,
+) -> AstraResult<(SyncResult, i32)> {
+    crate::i18n::init_translations();
+    let client = &sessions[0];
+    let config = client.config().clone();
+    let language = config.language.unwrap_or_else(crate::i18n::detect_language);
+    let project = PathMapper::from_config(&config);
+    let tree = PathMapper::new(local_dir, remote_dir);
+
+    let mut unlisted = Vec::new();
+    let files = match operation_type {
+        OperationType::Download => {
+            let prefix = project
+                .remote_to_relative(tree.remote_root())
+                .unwrap_or_default();
+            let listing = client.walk_remote_tree(tree.remote_root(), &prefix)?;
+            if listing.root_missing {
+                return Err(AstraError::FileOperationError(format!(
+                    "Remote directory {} does not exist",
+                    remote_dir
+                )));
+            }
+            unlisted = listing.unlisted;
+            listing.files
+        }
+        _ => {
+            let prefix = project
+                .local_to_relative(tree.local_root())
+                .unwrap_or_default();
+            client.list_local_files(tree.local_root(), &prefix)?
+        }
+    };
+
+    let operations = transfer::tree_operations(operation_type, &tree, &files);
+    for operation in &operations {
+        if let Some(msg) = describe_operation(operation, &language) {
+            output.say(msg);
+        }
+    }
+
+    let mut sync_result = SyncResult {
+        success: true,
+        message: String::new(),
+        files_transferred: Vec::new(),
+        files_skipped: Vec::new(),
+        errors: Vec::new(),
+        file_results: Vec::new(),
+        conflicts: Vec::new(),
+    };
+    if !operations.is_empty() {
+        let workers = config
+            .advanced
+            .max_concurrent_transfers
+            .min(operations.len());
+        let mut pool = TransferPool::from_clients(std::mem::take(sessions), workers);
+        sync_result.file_results = pool.execute(&operations);
+        *sessions = pool.into_clients();
+    }
+    // Whatever lies below a directory that could not be listed was not transferred
+    for path in &unlisted {
+        sync_result.file_results.push(FileResult {
+            operation_type,
+            local_path: tree.relative_to_local(path),
+            remote_path: tree.relative_to_remote(path),
+            success: false,
+            bytes: 0,
+            error: Some("remote directory could not be listed; its files were skipped".to_string()),
+            attempts: 0,
+        });
+    }
+    collect_file_results(&mut sync_result, &language, output);
+
+    let code = if sync_result.errors.is_empty() {
+        sync_result.message = crate::i18n::t_format(
+            "cli.directory_transfer_complete",
+            &language,
+            &[&sync_result.files_transferred.len().to_string()],
+        );
+        exit_code::SUCCESS
+    } else {
+        sync_result.success = false;
+        sync_result.message = crate::i18n::t_format(
+            "cli.directory_transfer_failed",
+            &language,
+            &[
+                &sync_result.errors.len().to_string(),
+                &sync_result.file_results.len().to_string(),
+            ],
+        );
+        exit_code::PARTIAL_FAILURE
+    };
+
+    Ok((sync_result, code))
 }
 
-async fn test_config(config_path: Option<&str>) -> AstraResult<()> {
+/// Show the tasks journaled for the project, read-only so a running daemon keeps ownership
+async fn show_tasks(
+    config_path: Option<&str>, id: Option<&str>, output: Output,
+) -> AstraResult<()> {
+    let config = load_config(config_path, RootOverrides::default())?;
+    let manager = TaskManager::new();
+    manager
+        .load_project(&background::task_project(&config), false)
+        .await;
+
+    match id {
+        Some(id) if output.is_json() => {
+            let task = manager
+                .get_task_status(id)
+                .await
+                .ok_or_else(|| AstraError::TaskError(format!("Task not found: {}", id)))?;
+            output.emit("tasks", true, &task)
+        }
+        Some(id) => TaskCommands::get_task_status(&manager, id).await,
+        None if output.is_json() => output.emit("tasks", true, &manager.get_all_tasks().await),
+        None => TaskCommands::list_tasks(&manager).await,
+    }
+}
+
+/// Where `config-test` found the configuration and what it resolved to, without secrets
+#[derive(Debug, Serialize)]
+struct ConfigTestReport {
+    config_path: Option<String>,
+    project_root: Option<String>,
+    host: String,
+    port: u16,
+    username: String,
+    remote_path: String,
+    local_path: String,
+    password_set: bool,
+    private_key_path: Option<String>,
+    enabled: bool,
+    connect_timeout: u64,
+    operation_timeout: u64,
+}
+
+async fn test_config(config_path: Option<&str>, output: Output) -> AstraResult<()> {
     // Initialize i18n system
     crate::i18n::init_translations();
     let language = crate::i18n::detect_language();
 
+    if output.is_json() {
+        let config_reader = ConfigReader::new(config_path.map(str::to_string));
+        let config = config_reader.read_config()?;
+        return output.emit(
+            "config-test",
+            true,
+            &ConfigTestReport {
+                config_path: config_path.map(str::to_string),
+                project_root: config_reader.find_project_root(),
+                host: config.host,
+                port: config.port,
+                username: config.username,
+                remote_path: config.remote_path,
+                local_path: config.local_path,
+                password_set: config.password.is_some(),
+                private_key_path: config.private_key_path,
+                enabled: config.enabled.unwrap_or(true),
+                connect_timeout: config.advanced.connect_timeout,
+                operation_timeout: config.advanced.operation_timeout,
+            },
+        );
+    }
+
     let testing_msg = crate::i18n::t("cli.testing_config", &language);
     println!("{}", testing_msg);
 
@@ -477,16 +1188,30 @@ async fn test_config(config_path: Option<&str>) -> AstraResult<()> {
                 println!("Private key path: None");
             }
             println!("Enabled: {}", config.enabled.unwrap_or(true));
+            println!(
+                "Timeouts: connect {}s, operation {}s",
+                config.advanced.connect_timeout, config.advanced.operation_timeout
+            );
         }
         Err(e) => {
             let error_msg = crate::i18n::t_format("cli.config_error", &language, &[&e.to_string()]);
             println!("❌ {}", error_msg);
+            return Err(e);
         }
     }
 
     Ok(())
 }
 
+/// Version details as reported by `version --output json`
+#[derive(Debug, Serialize)]
+struct VersionReport {
+    version: &'static str,
+    build_time: &'static str,
+    git_commit: &'static str,
+    clean: bool,
+}
+
 fn print_version() {
     println!("Astra-core {}", version::FULL_VERSION);
     println!("Build time: {}", version::BUILD_TIME);
@@ -520,12 +1245,25 @@ fn print_build_info() {
     }
 }
 
-fn show_version() -> AstraResult<()> {
+fn show_version(output: Output) -> AstraResult<()> {
+    if output.is_json() {
+        return output.emit(
+            "version",
+            true,
+            &VersionReport {
+                version: version::FULL_VERSION,
+                build_time: version::BUILD_TIME,
+                git_commit: version::GIT_COMMIT,
+                clean: version::is_clean(),
+            },
+        );
+    }
+
     print_version();
     Ok(())
 }
 
-async fn check_for_updates() -> AstraResult<()> {
+async fn check_for_updates(output: Output) -> AstraResult<()> {
     use chrono::{DateTime, Utc};
 
     // Initialize i18n system
@@ -533,31 +1271,39 @@ async fn check_for_updates() -> AstraResult<()> {
     let language = crate::i18n::detect_language();
 
     let checking_msg = crate::i18n::t("cli.checking_updates", &language);
-    println!("🔄 {}", checking_msg);
+    output.say(format!("🔄 {}", checking_msg));
 
     // For now, simulate checking for updates
     // In a real implementation, this would check GitHub releases or a registry
     let current_version = env!("CARGO_PKG_VERSION");
     let current_time: DateTime<Utc> = Utc::now();
 
-    println!("Current version: {}", current_version);
-    println!(
+    output.say(format!("Current version: {}", current_version));
+    output.say(format!(
         "Last checked: {}",
         current_time.format("%Y-%m-%d %H:%M:%S UTC")
-    );
+    ));
 
     // Simulate network check
-    println!("📡 Checking remote repository...");
+    output.say("📡 Checking remote repository...");
     tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
 
     // For demonstration, always say we're up to date
     let up_to_date_msg = crate::i18n::t("cli.up_to_date", &language);
-    println!("✅ {}", up_to_date_msg);
+    output.say(format!("✅ {}", up_to_date_msg));
 
     // In a real implementation, this would:
     // 1. Fetch latest release from GitHub API
     // 2. Compare versions
     // 3. Provide update instructions if available
 
-    Ok(())
+    output.emit(
+        "check-update",
+        true,
+        &serde_json::json!({
+            "current_version": current_version,
+            "checked_at": current_time,
+            "up_to_date": true,
+        }),
+    )
 }
@@ -1,14 +1,15 @@
 #[cfg(test)]
 mod tests {
     use crate::cli::{Cli, Commands};
-    use crate::types::SftpConfig;
+    use crate::output::OutputFormat;
+    use crate::types::{ConflictPolicy, ErrorPolicy, SftpConfig};
     use clap::Parser;
     use std::fs;
     use tempfile::TempDir;
 
     #[test]
     fn test_cli_command_parsing() {
-        let cli = Cli::try_parse_from(&["astra", "init", "--config", "test.json"]).unwrap();
+        let cli = Cli::try_parse_from(["astra", "init", "--config", "test.json"]).unwrap();
 
         match cli.command {
             Commands::Init {
@@ -23,32 +24,183 @@ mod tests {
     #[test]
     fn test_sync_command_parsing() {
         let cli =
-            Cli::try_parse_from(&["astra", "sync", "--config", "test.json", "--mode", "upload"])
+            Cli::try_parse_from(["astra", "sync", "--config", "test.json", "--mode", "upload"])
                 .unwrap();
 
         match cli.command {
             Commands::Sync {
                 config,
                 mode,
+                local,
+                remote,
                 files,
+                ..
             } => {
                 assert_eq!(config, Some("test.json".to_string()));
                 assert_eq!(mode, "upload");
+                assert_eq!(local, None);
+                assert_eq!(remote, None);
                 assert_eq!(files, Vec::<String>::new());
             }
             _ => panic!("Expected Sync command"),
         }
     }
 
+    #[test]
+    fn test_sync_root_overrides_parsing() {
+        let cli = Cli::try_parse_from([
+            "astra",
+            "sync",
+            "--local",
+            "/home/user/project",
+            "--remote",
+            "/srv/site",
+            "--mode",
+            "bidirectional",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Commands::Sync {
+                mode,
+                local,
+                remote,
+                ..
+            } => {
+                assert_eq!(mode, "bidirectional");
+                assert_eq!(local, Some("/home/user/project".to_string()));
+                assert_eq!(remote, Some("/srv/site".to_string()));
+            }
+            _ => panic!("Expected Sync command"),
+        }
+    }
+
+    #[test]
+    fn test_sync_conflict_policy_parsing() {
+        let cli = Cli::try_parse_from([
+            "astra",
+            "sync",
+            "--mode",
+            "bidirectional",
+            "--conflict-policy",
+            "keep-both",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Commands::Sync {
+                conflict_policy,
+                ..
+            } => {
+                let policy: ConflictPolicy = conflict_policy.unwrap().parse().unwrap();
+                assert_eq!(policy, ConflictPolicy::KeepBoth);
+            }
+            _ => panic!("Expected Sync command"),
+        }
+
+        assert!("prefer_remote".parse::<ConflictPolicy>().is_ok());
+        assert!("whatever".parse::<ConflictPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_sync_error_policy_parsing() {
+        let cli = Cli::try_parse_from(["astra", "sync", "--on-error", "stop"]).unwrap();
+
+        match cli.command {
+            Commands::Sync {
+                on_error,
+                ..
+            } => {
+                let policy: ErrorPolicy = on_error.unwrap().parse().unwrap();
+                assert_eq!(policy, ErrorPolicy::Stop);
+            }
+            _ => panic!("Expected Sync command"),
+        }
+
+        assert_eq!(
+            "continue".parse::<ErrorPolicy>().unwrap(),
+            ErrorPolicy::Continue
+        );
+        assert!("retry".parse::<ErrorPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_output_format_is_global() {
+        let cli = Cli::try_parse_from(["astra", "version"]).unwrap();
+        assert_eq!(cli.output, OutputFormat::Text);
+
+        let cli = Cli::try_parse_from(["astra", "status", "--output", "json"]).unwrap();
+        assert_eq!(cli.output, OutputFormat::Json);
+        assert_eq!(cli.command.name(), "status");
+
+        let cli = Cli::try_parse_from(["astra", "--output", "json", "config-test"]).unwrap();
+        assert_eq!(cli.command.name(), "config-test");
+
+        assert!(Cli::try_parse_from(["astra", "version", "--output", "yaml"]).is_err());
+    }
+
+    #[test]
+    fn test_sync_dry_run_parsing() {
+        let cli =
+            Cli::try_parse_from(["astra", "sync", "--dry-run", "--max-deletions", "0"]).unwrap();
+
+        match cli.command {
+            Commands::Sync {
+                dry_run,
+                max_deletions,
+                ..
+            } => {
+                assert!(dry_run);
+                assert_eq!(max_deletions, Some(0));
+            }
+            _ => panic!("Expected Sync command"),
+        }
+    }
+
+    #[test]
+    fn test_recursive_transfer_parsing() {
+        let cli = Cli::try_parse_from([
+            "astra",
+            "upload",
+            "--local",
+            "src",
+            "--remote",
+            "/srv/site/src",
+            "--recursive",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Upload {
+                recursive,
+                ..
+            } => assert!(recursive),
+            _ => panic!("Expected Upload command"),
+        }
+
+        let cli = Cli::try_parse_from(["astra", "download", "--remote", "/srv/a", "--local", "a"])
+            .unwrap();
+        match cli.command {
+            Commands::Download {
+                recursive,
+                ..
+            } => assert!(!recursive),
+            _ => panic!("Expected Download command"),
+        }
+    }
+
     #[test]
     fn test_status_command_parsing() {
-        let cli = Cli::try_parse_from(&["astra", "status", "--config", "test.json"]).unwrap();
+        let cli = Cli::try_parse_from(["astra", "status", "--config", "test.json"]).unwrap();
 
         match cli.command {
             Commands::Status {
                 config,
+                local,
+                remote,
             } => {
                 assert_eq!(config, Some("test.json".to_string()));
+                assert_eq!(local, None);
+                assert_eq!(remote, None);
             }
             _ => panic!("Expected Status command"),
         }
@@ -60,15 +212,8 @@ mod tests {
         let config_path = temp_dir.path().join("test_config.json");
 
         let config = SftpConfig {
-            enabled: Some(true),
-            host: "test.com".to_string(),
-            port: 22,
-            username: "user".to_string(),
             password: Some("pass".to_string()),
-            private_key_path: None,
-            remote_path: "/remote".to_string(),
-            local_path: temp_dir.path().to_str().unwrap().to_string(),
-            language: None,
+            ..SftpConfig::for_test(temp_dir.path().to_string_lossy())
         };
 
         let config_json = serde_json::to_string_pretty(&config).unwrap();
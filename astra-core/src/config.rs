@@ -1,4 +1,5 @@
 use crate::error::{AstraError, AstraResult};
+use crate::paths::{normalize_local_path, normalize_remote_path, PathMapper};
 use crate::types::{AstraTomlConfig, SftpConfig, VsCodeSftpConfig};
 use std::path::Path;
 use std::{env, fs};
@@ -16,7 +17,7 @@ impl ConfigReader {
     }
 
     /// Expand ~ to local home directory in a path (for local paths)
-    fn expand_tilde_local(path: &str) -> String {
+    pub fn expand_tilde_local(path: &str) -> String {
         if let Some(rest) = path.strip_prefix("~/") {
             // Handle ~/path
             if let Ok(home_dir) = env::var("HOME") {
@@ -275,6 +276,39 @@ impl ConfigReader {
         Ok(config)
     }
 
+    /// Layer `--local`/`--remote` root overrides over a loaded configuration.
+    ///
+    /// If only `local` is given and it lies inside the configured local root, the remote root
+    /// follows it to the matching subdirectory so a subtree can be synced on its own.
+    pub fn apply_root_overrides(
+        mut config: SftpConfig, local: Option<&str>, remote: Option<&str>,
+    ) -> AstraResult<SftpConfig> {
+        let mapper = PathMapper::from_config(&config);
+
+        if let Some(local) = local {
+            let local_root = normalize_local_path(Path::new(&Self::expand_tilde_local(local)));
+
+            if remote.is_none() {
+                if let Ok(remote_root) = mapper.local_to_remote(&local_root) {
+                    config.remote_path = remote_root.to_string_lossy().to_string();
+                }
+            }
+            config.local_path = local_root.to_string_lossy().to_string();
+        }
+
+        if let Some(remote) = remote {
+            if remote.trim().is_empty() {
+                return Err(AstraError::ConfigurationError(
+                    "Remote root override must not be empty".to_string(),
+                ));
+            }
+            config.remote_path =
+                normalize_remote_path(&Self::expand_tilde_remote(remote, &config.username));
+        }
+
+        Ok(config)
+    }
+
     pub fn find_project_root(&self) -> Option<String> {
         let base_path = Path::new(&self.base_dir);
         let mut current_path = base_path;
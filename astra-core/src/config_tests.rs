@@ -0,0 +1,191 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::ConfigReader;
+    use crate::types::{AdvancedConfig, ErrorPolicy, SftpConfig};
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn base_config() -> SftpConfig {
+        SftpConfig {
+            username: "deploy".to_string(),
+            password: Some("pass".to_string()),
+            remote_path: "/srv/site".to_string(),
+            ..SftpConfig::for_test("/home/deploy/project")
+        }
+    }
+
+    #[test]
+    fn test_no_overrides_keeps_config() {
+        let config = ConfigReader::apply_root_overrides(base_config(), None, None).unwrap();
+
+        assert_eq!(config.local_path, "/home/deploy/project");
+        assert_eq!(config.remote_path, "/srv/site");
+    }
+
+    #[test]
+    fn test_local_subdirectory_override_follows_remote() {
+        let config = ConfigReader::apply_root_overrides(
+            base_config(),
+            Some("/home/deploy/project/src/"),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(config.local_path, "/home/deploy/project/src");
+        assert_eq!(config.remote_path, "/srv/site/src");
+    }
+
+    #[test]
+    fn test_explicit_remote_override_wins() {
+        let config = ConfigReader::apply_root_overrides(
+            base_config(),
+            Some("/home/deploy/project/src"),
+            Some("~/staging//src/"),
+        )
+        .unwrap();
+
+        assert_eq!(config.local_path, "/home/deploy/project/src");
+        assert_eq!(config.remote_path, "/home/deploy/staging/src");
+    }
+
+    #[test]
+    fn test_local_override_outside_root_keeps_remote() {
+        let config =
+            ConfigReader::apply_root_overrides(base_config(), Some("/tmp/elsewhere"), None)
+                .unwrap();
+
+        assert_eq!(config.local_path, "/tmp/elsewhere");
+        assert_eq!(config.remote_path, "/srv/site");
+    }
+
+    #[test]
+    fn test_empty_remote_override_is_rejected() {
+        assert!(ConfigReader::apply_root_overrides(base_config(), None, Some(" ")).is_err());
+    }
+
+    #[test]
+    fn test_toml_sync_patterns_are_parsed() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join(".astra-settings")).unwrap();
+        fs::write(
+            temp_dir.path().join(".astra-settings/settings.toml"),
+            r#"
+[sftp]
+host = "test.com"
+username = "deploy"
+remote_path = "/srv/site"
+
+[sync]
+exclude_patterns = [".git/", "*.log"]
+include_patterns = ["src/"]
+respect_gitignore = true
+max_deletions = 10
+trash_dir = ".astra-trash"
+"#,
+        )
+        .unwrap();
+
+        let reader = ConfigReader::new(Some(temp_dir.path().to_string_lossy().to_string()));
+        let config = reader.read_config().unwrap();
+
+        assert_eq!(config.sync.exclude_patterns, vec![".git/", "*.log"]);
+        assert_eq!(config.sync.include_patterns, vec!["src/"]);
+        assert!(config.sync.respect_gitignore);
+        assert_eq!(config.sync.max_deletions, 10);
+        assert_eq!(config.sync.trash_dir.as_deref(), Some(".astra-trash"));
+    }
+
+    #[test]
+    fn test_vscode_ignore_becomes_exclude_patterns() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join(".vscode")).unwrap();
+        fs::write(
+            temp_dir.path().join(".vscode/sftp.json"),
+            r#"{
+                "name": "site",
+                "host": "test.com",
+                "protocol": "sftp",
+                "port": 22,
+                "username": "deploy",
+                "remotePath": "/srv/site",
+                "ignore": [".vscode", "node_modules"]
+            }"#,
+        )
+        .unwrap();
+
+        let reader = ConfigReader::new(Some(temp_dir.path().to_string_lossy().to_string()));
+        let config = reader.read_config().unwrap();
+
+        assert_eq!(
+            config.sync.exclude_patterns,
+            vec![".vscode", "node_modules"]
+        );
+    }
+
+    #[test]
+    fn test_legacy_json_without_sync_section() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("astra.json");
+        fs::write(
+            &config_path,
+            r#"{
+                "host": "test.com",
+                "port": 22,
+                "username": "deploy",
+                "password": null,
+                "private_key_path": null,
+                "remote_path": "/srv/site",
+                "local_path": "/home/deploy/project"
+            }"#,
+        )
+        .unwrap();
+
+        let reader = ConfigReader::new(Some(config_path.to_string_lossy().to_string()));
+        let config = reader.read_config().unwrap();
+
+        assert!(config.sync.exclude_patterns.is_empty());
+        assert!(!config.sync.respect_gitignore);
+        assert_eq!(config.sync.max_deletions, 50);
+        assert_eq!(config.advanced, AdvancedConfig::default());
+    }
+
+    #[test]
+    fn test_toml_advanced_section_is_parsed() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join(".astra-settings")).unwrap();
+        fs::write(
+            temp_dir.path().join(".astra-settings/settings.toml"),
+            r#"
+[sftp]
+host = "test.com"
+username = "deploy"
+remote_path = "/srv/site"
+
+[sync]
+on_error = "stop"
+
+[advanced]
+connect_timeout = 5
+operation_timeout = 60
+verbose_logging = true
+max_retries = 5
+remote_shell = true
+"#,
+        )
+        .unwrap();
+
+        let reader = ConfigReader::new(Some(temp_dir.path().to_string_lossy().to_string()));
+        let config = reader.read_config().unwrap();
+
+        assert_eq!(config.advanced.connect_timeout, 5);
+        assert_eq!(config.advanced.operation_timeout, 60);
+        assert!(config.advanced.verbose_logging);
+        // Keys that are not set keep their defaults
+        assert_eq!(config.advanced.max_concurrent_transfers, 5);
+        assert_eq!(config.advanced.keepalive_interval, 30);
+        assert_eq!(config.advanced.max_retries, 5);
+        assert!(config.advanced.remote_shell);
+        assert_eq!(config.advanced.retry_delay, 1);
+        assert_eq!(config.sync.on_error, ErrorPolicy::Stop);
+    }
+}
@@ -1,16 +1,48 @@
+use std::io::ErrorKind;
 use thiserror::Error;
 
+/// libssh2 session error codes that mean the connection, not the operation, failed
+const TRANSIENT_SESSION_ERRORS: [i32; 7] = [
+    -7,  // LIBSSH2_ERROR_SOCKET_SEND
+    -9,  // LIBSSH2_ERROR_TIMEOUT
+    -13, // LIBSSH2_ERROR_SOCKET_DISCONNECT
+    -26, // LIBSSH2_ERROR_CHANNEL_CLOSED
+    -30, // LIBSSH2_ERROR_SOCKET_TIMEOUT
+    -37, // LIBSSH2_ERROR_EAGAIN
+    -43, // LIBSSH2_ERROR_SOCKET_RECV
+];
+/// SFTP status codes for a lost connection (`SSH_FX_NO_CONNECTION`, `SSH_FX_CONNECTION_LOST`)
+const TRANSIENT_SFTP_ERRORS: [i32; 2] = [6, 7];
+
 #[derive(Debug, Error)]
 pub enum AstraError {
+    /// The server could not be reached or refused the session (unknown host, connection
+    /// refused, host key rejected); trying again will not help
     #[error("SFTP connection error: {0}")]
     SftpConnectionError(String),
 
+    /// The connection was reset, timed out or closed midway; a new one may succeed
+    #[error("SFTP connection interrupted: {0}")]
+    ConnectionInterrupted(String),
+
     #[error("Authentication error: {0}")]
     AuthenticationError(String),
 
+    #[error("Private key passphrase error: {0}")]
+    PassphraseError(String),
+
+    #[error("Host key mismatch: {0}")]
+    HostKeyMismatch(String),
+
+    #[error("Unknown host key: {0}")]
+    HostKeyUnknown(String),
+
     #[error("File operation error: {0}")]
     FileOperationError(String),
 
+    #[error("Deletion limit exceeded: {0}")]
+    DeletionLimitExceeded(String),
+
     #[error("Configuration error: {0}")]
     ConfigurationError(String),
 
@@ -31,6 +63,133 @@ pub enum AstraError {
 
     #[error("Task error: {0}")]
     TaskError(String),
+
+    #[error("Operation cancelled")]
+    Cancelled,
+}
+
+impl AstraError {
+    /// A failure to set up or keep up the session, classified by its cause
+    pub fn from_session(error: ssh2::Error) -> Self {
+        if is_transient_ssh2(&error) {
+            AstraError::ConnectionInterrupted(error.to_string())
+        } else {
+            AstraError::SftpConnectionError(error.to_string())
+        }
+    }
+
+    /// A failed SFTP call on a file. Failures of the connection keep their ssh2 error so they
+    /// are classified as transient; anything else is about the file itself.
+    pub fn from_sftp(error: ssh2::Error) -> Self {
+        if is_transient_ssh2(&error) {
+            AstraError::Ssh2Error(error)
+        } else {
+            AstraError::FileOperationError(error.to_string())
+        }
+    }
+
+    /// Whether trying again on a fresh connection may succeed: the connection was reset, timed
+    /// out or hit EOF, rather than the operation failing (permission denied, no such file, ...)
+    /// or the server being unreachable (unknown host, connection refused)
+    pub fn is_transient(&self) -> bool {
+        match self {
+            AstraError::ConnectionInterrupted(_) => true,
+            AstraError::IoError(e) => is_transient_io(e.kind()),
+            AstraError::Ssh2Error(e) => is_transient_ssh2(e),
+            _ => false,
+        }
+    }
+
+    /// Process exit code for a command that failed with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AstraError::ConfigurationError(_) => exit_code::CONFIG_ERROR,
+            AstraError::AuthenticationError(_) | AstraError::PassphraseError(_) => {
+                exit_code::AUTH_FAILURE
+            }
+            AstraError::SftpConnectionError(_) | AstraError::ConnectionInterrupted(_) => {
+                exit_code::CONNECTION_FAILURE
+            }
+            AstraError::HostKeyMismatch(_) | AstraError::HostKeyUnknown(_) => {
+                exit_code::HOST_KEY_REJECTED
+            }
+            AstraError::DeletionLimitExceeded(_) => exit_code::DELETION_LIMIT,
+            AstraError::FileOperationError(_)
+            | AstraError::IoError(_)
+            | AstraError::Ssh2Error(_)
+            | AstraError::JsonError(_)
+            | AstraError::ChronoError(_)
+            | AstraError::SystemError(_)
+            | AstraError::TaskError(_)
+            | AstraError::Cancelled => exit_code::FAILURE,
+        }
+    }
+
+    /// The variant name, used as a stable error identifier in machine-readable output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AstraError::SftpConnectionError(_) => "SftpConnectionError",
+            AstraError::ConnectionInterrupted(_) => "ConnectionInterrupted",
+            AstraError::AuthenticationError(_) => "AuthenticationError",
+            AstraError::PassphraseError(_) => "PassphraseError",
+            AstraError::HostKeyMismatch(_) => "HostKeyMismatch",
+            AstraError::HostKeyUnknown(_) => "HostKeyUnknown",
+            AstraError::FileOperationError(_) => "FileOperationError",
+            AstraError::DeletionLimitExceeded(_) => "DeletionLimitExceeded",
+            AstraError::ConfigurationError(_) => "ConfigurationError",
+            AstraError::IoError(_) => "IoError",
+            AstraError::Ssh2Error(_) => "Ssh2Error",
+            AstraError::JsonError(_) => "JsonError",
+            AstraError::ChronoError(_) => "ChronoError",
+            AstraError::SystemError(_) => "SystemError",
+            AstraError::TaskError(_) => "TaskError",
+            AstraError::Cancelled => "Cancelled",
+        }
+    }
 }
 
 pub type AstraResult<T> = Result<T, AstraError>;
+
+/// Whether an I/O error of this kind means the connection was reset, timed out or hit EOF
+pub fn is_transient_io(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::TimedOut
+            | ErrorKind::WouldBlock
+            | ErrorKind::UnexpectedEof
+    )
+}
+
+fn is_transient_ssh2(error: &ssh2::Error) -> bool {
+    match error.code() {
+        ssh2::ErrorCode::Session(code) => TRANSIENT_SESSION_ERRORS.contains(&code),
+        ssh2::ErrorCode::SFTP(code) => TRANSIENT_SFTP_ERRORS.contains(&code),
+    }
+}
+
+/// Process exit codes of the CLI. They are part of its interface (scripts and the Neovim plugin
+/// branch on them), so existing values must never change meaning.
+pub mod exit_code {
+    pub const SUCCESS: i32 = 0;
+    /// Unexpected I/O, SFTP or internal error
+    pub const FAILURE: i32 = 1;
+    /// Invalid command line arguments (reported by clap)
+    pub const USAGE: i32 = 2;
+    /// The configuration is missing or invalid
+    pub const CONFIG_ERROR: i32 = 3;
+    /// The server rejected every authentication method, or a key passphrase is missing or wrong
+    pub const AUTH_FAILURE: i32 = 4;
+    /// The server could not be reached or the SSH session could not be set up
+    pub const CONNECTION_FAILURE: i32 = 5;
+    /// The server's host key did not match known_hosts or is unknown under the strict policy
+    pub const HOST_KEY_REJECTED: i32 = 6;
+    /// The sync was aborted because it would delete more files than `max_deletions`
+    pub const DELETION_LIMIT: i32 = 7;
+    /// The command ran, but some files failed to transfer
+    pub const PARTIAL_FAILURE: i32 = 10;
+    /// Everything else was synced, but conflicts are waiting for a decision
+    pub const CONFLICTS_PENDING: i32 = 11;
+}
@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use crate::error::{exit_code, AstraError};
+
+    #[test]
+    fn test_exit_codes_per_failure_class() {
+        let cases = [
+            (
+                AstraError::ConfigurationError("missing host".to_string()),
+                exit_code::CONFIG_ERROR,
+            ),
+            (
+                AstraError::AuthenticationError("denied".to_string()),
+                exit_code::AUTH_FAILURE,
+            ),
+            (
+                AstraError::PassphraseError("wrong passphrase".to_string()),
+                exit_code::AUTH_FAILURE,
+            ),
+            (
+                AstraError::SftpConnectionError("timed out".to_string()),
+                exit_code::CONNECTION_FAILURE,
+            ),
+            (
+                AstraError::HostKeyMismatch("example.com".to_string()),
+                exit_code::HOST_KEY_REJECTED,
+            ),
+            (
+                AstraError::DeletionLimitExceeded("too many".to_string()),
+                exit_code::DELETION_LIMIT,
+            ),
+            (
+                AstraError::FileOperationError("denied".to_string()),
+                exit_code::FAILURE,
+            ),
+        ];
+
+        for (error, expected) in cases {
+            assert_eq!(error.exit_code(), expected, "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let mut codes = vec![
+            exit_code::SUCCESS,
+            exit_code::FAILURE,
+            exit_code::USAGE,
+            exit_code::CONFIG_ERROR,
+            exit_code::AUTH_FAILURE,
+            exit_code::CONNECTION_FAILURE,
+            exit_code::HOST_KEY_REJECTED,
+            exit_code::DELETION_LIMIT,
+            exit_code::PARTIAL_FAILURE,
+            exit_code::CONFLICTS_PENDING,
+        ];
+        let count = codes.len();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), count);
+    }
+
+    #[test]
+    fn test_network_failures_are_transient() {
+        use std::io::{Error, ErrorKind};
+
+        let transient = [
+            AstraError::ConnectionInterrupted("connection reset".to_string()),
+            AstraError::IoError(Error::from(ErrorKind::ConnectionReset)),
+            AstraError::IoError(Error::from(ErrorKind::TimedOut)),
+            AstraError::IoError(Error::from(ErrorKind::WouldBlock)),
+            AstraError::from_sftp(ssh2::Error::new(
+                ssh2::ErrorCode::Session(-43),
+                "socket recv",
+            )),
+            AstraError::from_sftp(ssh2::Error::new(ssh2::ErrorCode::SFTP(7), "lost")),
+            AstraError::from_session(ssh2::Error::new(
+                ssh2::ErrorCode::Session(-13),
+                "socket disconnect",
+            )),
+        ];
+        for error in &transient {
+            assert!(error.is_transient(), "{}", error);
+        }
+
+        let permanent = [
+            AstraError::IoError(Error::from(ErrorKind::PermissionDenied)),
+            AstraError::IoError(Error::from(ErrorKind::NotFound)),
+            AstraError::from_sftp(ssh2::Error::new(ssh2::ErrorCode::SFTP(3), "denied")),
+            AstraError::AuthenticationError("denied".to_string()),
+            AstraError::Cancelled,
+            AstraError::SftpConnectionError("connection refused".to_string()),
+            AstraError::SftpConnectionError("No addresses found for example.invalid".to_string()),
+            AstraError::from_session(ssh2::Error::new(
+                ssh2::ErrorCode::Session(-5),
+                "key exchange failed",
+            )),
+            AstraError::IoError(Error::from(ErrorKind::Interrupted)),
+        ];
+        for error in &permanent {
+            assert!(!error.is_transient(), "{}", error);
+        }
+        // File errors keep their kind for machine-readable output
+        assert_eq!(permanent[2].kind(), "FileOperationError");
+    }
+}
@@ -0,0 +1,122 @@
+use crate::error::{AstraError, AstraResult};
+use crate::paths::{is_temp_file, relative_trash_dir};
+use crate::state::STATE_DIR;
+use crate::types::SyncOptions;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+use tracing::warn;
+
+/// Include/exclude rules applied to both the local and the remote walk.
+///
+/// Patterns use gitignore syntax: a trailing `/` only matches directories, a leading `/` anchors
+/// the pattern at the sync root, patterns without a `/` match at any depth and `!` re-includes a
+/// previously excluded path. Paths are always matched relative to the sync root.
+#[derive(Debug, Clone)]
+pub struct PathFilter {
+    exclude: Gitignore,
+    include: Gitignore,
+    gitignore: Option<Gitignore>,
+}
+
+impl PathFilter {
+    /// A filter that lets every path through but transfer temp files
+    pub fn allow_all() -> Self {
+        Self {
+            exclude: Gitignore::empty(),
+            include: Gitignore::empty(),
+            gitignore: None,
+        }
+    }
+
+    /// Build the filter for a sync root. Settings directories (which hold credentials and the
+    /// sync state) are always excluded at any depth, and so is a remote trash directory inside
+    /// the sync root.
+    pub fn new(options: &SyncOptions, local_root: &Path) -> AstraResult<Self> {
+        let mut exclude_patterns = vec![format!("{}/", STATE_DIR)];
+        if let Some(trash_dir) = relative_trash_dir(options) {
+            exclude_patterns.push(format!("/{}/", trash_dir));
+        }
+        exclude_patterns.extend(options.exclude_patterns.iter().cloned());
+        let exclude = build_matcher(local_root, &exclude_patterns)?;
+        let include = build_matcher(local_root, &options.include_patterns)?;
+
+        let gitignore = if options.respect_gitignore {
+            let gitignore_path = local_root.join(".gitignore");
+            if gitignore_path.is_file() {
+                let (matcher, error) = Gitignore::new(&gitignore_path);
+                if let Some(e) = error {
+                    warn!(
+                        "Ignoring invalid lines in {}: {}",
+                        gitignore_path.display(),
+                        e
+                    );
+                }
+                Some(matcher)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        Ok(Self {
+            exclude,
+            include,
+            gitignore,
+        })
+    }
+
+    /// Whether a path (relative to the sync root) should be left out of the sync.
+    ///
+    /// Directories are only checked against the exclude rules so that include patterns such as
+    /// `*.php` still reach files in nested directories. Temp files of unfinished transfers are
+    /// always excluded, whatever the patterns say.
+    pub fn is_excluded(&self, relative_path: &Path, is_dir: bool) -> bool {
+        if relative_path.as_os_str().is_empty() {
+            return false;
+        }
+
+        if !is_dir && is_temp_file(relative_path) {
+            return true;
+        }
+
+        if self
+            .exclude
+            .matched_path_or_any_parents(relative_path, is_dir)
+            .is_ignore()
+        {
+            return true;
+        }
+
+        if let Some(gitignore) = &self.gitignore {
+            if gitignore
+                .matched_path_or_any_parents(relative_path, is_dir)
+                .is_ignore()
+            {
+                return true;
+            }
+        }
+
+        if is_dir || self.include.is_empty() {
+            return false;
+        }
+
+        !self
+            .include
+            .matched_path_or_any_parents(relative_path, is_dir)
+            .is_ignore()
+    }
+}
+
+fn build_matcher(root: &Path, patterns: &[String]) -> AstraResult<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in patterns {
+        builder.add_line(None, pattern).map_err(|e| {
+            AstraError::ConfigurationError(format!("Invalid sync pattern '{}': {}", pattern, e))
+        })?;
+    }
+
+    builder
+        .build()
+        .map_err(|e| AstraError::ConfigurationError(format!("Invalid sync patterns: {}", e)))
+}
@@ -0,0 +1,127 @@
+#[cfg(test)]
+mod tests {
+    use crate::filter::PathFilter;
+    use crate::types::SyncOptions;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn options(exclude: &[&str], include: &[&str]) -> SyncOptions {
+        SyncOptions {
+            exclude_patterns: exclude.iter().map(|p| p.to_string()).collect(),
+            include_patterns: include.iter().map(|p| p.to_string()).collect(),
+            ..SyncOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_default_plugin_excludes() {
+        let filter = PathFilter::new(
+            &options(&[".git/", "*.tmp", "*.log", ".DS_Store"], &[]),
+            Path::new("/project"),
+        )
+        .unwrap();
+
+        assert!(filter.is_excluded(Path::new(".git"), true));
+        assert!(filter.is_excluded(Path::new(".git/config"), false));
+        assert!(filter.is_excluded(Path::new("logs/app.log"), false));
+        assert!(filter.is_excluded(Path::new("src/.DS_Store"), false));
+        assert!(!filter.is_excluded(Path::new("src/main.rs"), false));
+        // A trailing slash only matches directories
+        assert!(!filter.is_excluded(Path::new(".git"), false));
+    }
+
+    #[test]
+    fn test_settings_directory_is_always_excluded() {
+        let filter = PathFilter::new(&SyncOptions::default(), Path::new("/project")).unwrap();
+
+        assert!(filter.is_excluded(Path::new(".astra-settings"), true));
+        assert!(filter.is_excluded(Path::new(".astra-settings/state.json"), false));
+        // Also nested ones, e.g. of a subproject, which may hold its credentials
+        assert!(filter.is_excluded(Path::new("docs/.astra-settings"), true));
+        assert!(filter.is_excluded(Path::new("docs/.astra-settings/state.json"), false));
+        assert!(!filter.is_excluded(Path::new("docs/astra-settings.md"), false));
+    }
+
+    #[test]
+    fn test_transfer_temp_files_are_always_excluded() {
+        let filter =
+            PathFilter::new(&options(&["!*.astra-part"], &[]), Path::new("/project")).unwrap();
+
+        assert!(filter.is_excluded(Path::new("src/.index.php.astra-part"), false));
+        assert!(PathFilter::allow_all().is_excluded(Path::new(".app.js.astra-part"), false));
+        assert!(!filter.is_excluded(Path::new("src/index.php"), false));
+        // A directory is not a temp file, whatever its name
+        assert!(!filter.is_excluded(Path::new(".build.astra-part"), true));
+    }
+
+    #[test]
+    fn test_trash_directory_inside_root_is_excluded() {
+        let options = SyncOptions {
+            trash_dir: Some("./.astra-trash/".to_string()),
+            ..SyncOptions::default()
+        };
+        let filter = PathFilter::new(&options, Path::new("/project")).unwrap();
+
+        assert!(filter.is_excluded(Path::new(".astra-trash/20240101-120000/a.php"), false));
+        assert!(!filter.is_excluded(Path::new("src/.astra-trash"), true));
+    }
+
+    #[test]
+    fn test_anchored_and_negated_patterns() {
+        let filter = PathFilter::new(
+            &options(&["/target/", "*.env", "!example.env"], &[]),
+            Path::new("/project"),
+        )
+        .unwrap();
+
+        assert!(filter.is_excluded(Path::new("target/debug/app"), false));
+        assert!(!filter.is_excluded(Path::new("crates/target/file.rs"), false));
+        assert!(filter.is_excluded(Path::new("config/prod.env"), false));
+        assert!(!filter.is_excluded(Path::new("config/example.env"), false));
+    }
+
+    #[test]
+    fn test_include_patterns_restrict_files_only() {
+        let filter = PathFilter::new(
+            &options(&["vendor/"], &["*.php", "assets/"]),
+            Path::new("/project"),
+        )
+        .unwrap();
+
+        assert!(!filter.is_excluded(Path::new("app/Http"), true));
+        assert!(!filter.is_excluded(Path::new("app/Http/Kernel.php"), false));
+        assert!(!filter.is_excluded(Path::new("assets/css/site.css"), false));
+        assert!(filter.is_excluded(Path::new("README.md"), false));
+        assert!(filter.is_excluded(Path::new("vendor/autoload.php"), false));
+    }
+
+    #[test]
+    fn test_respect_gitignore() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join(".gitignore"),
+            "node_modules/\n*.cache\n",
+        )
+        .unwrap();
+
+        let mut sync_options = options(&[], &[]);
+        let filter = PathFilter::new(&sync_options, temp_dir.path()).unwrap();
+        assert!(!filter.is_excluded(Path::new("node_modules/pkg/index.js"), false));
+
+        sync_options.respect_gitignore = true;
+        let filter = PathFilter::new(&sync_options, temp_dir.path()).unwrap();
+        assert!(filter.is_excluded(Path::new("node_modules"), true));
+        assert!(filter.is_excluded(Path::new("node_modules/pkg/index.js"), false));
+        assert!(filter.is_excluded(Path::new("build/app.cache"), false));
+        assert!(!filter.is_excluded(Path::new("src/index.js"), false));
+    }
+
+    #[test]
+    fn test_allow_all() {
+        let filter = PathFilter::allow_all();
+
+        assert!(!filter.is_excluded(Path::new(".git/HEAD"), false));
+        assert!(!filter.is_excluded(Path::new(""), true));
+    }
+}
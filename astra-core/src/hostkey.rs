@@ -0,0 +1,127 @@
+use crate::config::ConfigReader;
+use crate::error::{AstraError, AstraResult};
+use crate::types::{HostKeyPolicy, SftpConfig};
+use ssh2::{CheckResult, HashType, KnownHostFileKind, Session};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+/// Default location of the OpenSSH known_hosts file
+pub const DEFAULT_KNOWN_HOSTS: &str = "~/.ssh/known_hosts";
+
+/// Known-hosts file used for `config`
+pub fn known_hosts_path(config: &SftpConfig) -> PathBuf {
+    let path = config
+        .known_hosts_path
+        .as_deref()
+        .unwrap_or(DEFAULT_KNOWN_HOSTS);
+    PathBuf::from(ConfigReader::expand_tilde_local(path))
+}
+
+/// Host name as written in known_hosts: plain for port 22, `[host]:port` otherwise
+pub fn known_hosts_entry(host: &str, port: u16) -> String {
+    if port == 22 {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    }
+}
+
+/// Check the server key of a handshaken session against the known_hosts file.
+///
+/// A key that differs from the recorded one is always rejected. Unknown hosts are rejected
+/// under [`HostKeyPolicy::Strict`] and appended to the file under [`HostKeyPolicy::AcceptNew`].
+pub fn verify_host_key(session: &Session, config: &SftpConfig) -> AstraResult<()> {
+    let policy = config.host_key_policy.unwrap_or_default();
+    if policy == HostKeyPolicy::Off {
+        warn!("Host key verification is disabled for {}", config.host);
+        return Ok(());
+    }
+
+    let (key, key_type) = session.host_key().ok_or_else(|| {
+        AstraError::SftpConnectionError("Server did not provide a host key".to_string())
+    })?;
+    let fingerprint = fingerprint(session);
+
+    let path = known_hosts_path(config);
+    let mut known_hosts = session.known_hosts()?;
+    if path.exists() {
+        known_hosts
+            .read_file(&path, KnownHostFileKind::OpenSSH)
+            .map_err(|e| {
+                AstraError::ConfigurationError(format!(
+                    "Failed to read known hosts file {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+    }
+
+    match known_hosts.check_port(&config.host, config.port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(AstraError::HostKeyMismatch(format!(
+            "host key for {} does not match the entry in {} (server fingerprint {}); \
+             the server key changed or the connection is being intercepted",
+            known_hosts_entry(&config.host, config.port),
+            path.display(),
+            fingerprint
+        ))),
+        CheckResult::NotFound if policy == HostKeyPolicy::Strict => {
+            Err(AstraError::HostKeyUnknown(format!(
+                "{} is not in {} (server fingerprint {})",
+                known_hosts_entry(&config.host, config.port),
+                path.display(),
+                fingerprint
+            )))
+        }
+        CheckResult::NotFound => {
+            let entry = known_hosts_entry(&config.host, config.port);
+
+            // Append a single line instead of rewriting the user's file with write_file
+            let mut new_hosts = session.known_hosts()?;
+            new_hosts.add(&entry, key, "added by astra", key_type.into())?;
+            let mut line = match new_hosts.hosts()?.first() {
+                Some(host) => new_hosts.write_string(host, KnownHostFileKind::OpenSSH)?,
+                None => {
+                    return Err(AstraError::SftpConnectionError(
+                        "Failed to record new host key".to_string(),
+                    ))
+                }
+            };
+            if !line.ends_with('\n') {
+                line.push('\n');
+            }
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            file.write_all(line.as_bytes())?;
+
+            info!(
+                "Added {} to {} (fingerprint {})",
+                entry,
+                path.display(),
+                fingerprint
+            );
+            Ok(())
+        }
+        CheckResult::Failure => Err(AstraError::SftpConnectionError(format!(
+            "Failed to check host key for {}",
+            config.host
+        ))),
+    }
+}
+
+fn fingerprint(session: &Session) -> String {
+    match session.host_key_hash(HashType::Sha256) {
+        Some(hash) => format!(
+            "SHA256:{}",
+            hash.iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        ),
+        None => "unknown".to_string(),
+    }
+}
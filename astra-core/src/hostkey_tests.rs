@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod tests {
+    use crate::hostkey::{known_hosts_entry, known_hosts_path, verify_host_key};
+    use crate::types::{HostKeyPolicy, SftpConfig};
+    use ssh2::Session;
+
+    fn config(policy: Option<HostKeyPolicy>) -> SftpConfig {
+        SftpConfig {
+            port: 2222,
+            known_hosts_path: Some("/tmp/astra_known_hosts".to_string()),
+            host_key_policy: policy,
+            ..SftpConfig::for_test("/local")
+        }
+    }
+
+    #[test]
+    fn test_known_hosts_entry_format() {
+        assert_eq!(known_hosts_entry("example.com", 22), "example.com");
+        assert_eq!(known_hosts_entry("example.com", 2222), "[example.com]:2222");
+    }
+
+    #[test]
+    fn test_known_hosts_path_defaults_to_ssh_dir() {
+        let mut config = config(None);
+        assert_eq!(
+            known_hosts_path(&config).to_string_lossy(),
+            "/tmp/astra_known_hosts"
+        );
+
+        config.known_hosts_path = None;
+        assert!(known_hosts_path(&config).ends_with(".ssh/known_hosts"));
+    }
+
+    #[test]
+    fn test_host_key_policy_parsing() {
+        #[derive(serde::Deserialize)]
+        struct Wrapper {
+            policy: HostKeyPolicy,
+        }
+
+        let parse = |value: &str| -> HostKeyPolicy {
+            toml::from_str::<Wrapper>(&format!("policy = \"{}\"", value))
+                .unwrap()
+                .policy
+        };
+
+        assert_eq!(parse("strict"), HostKeyPolicy::Strict);
+        assert_eq!(parse("accept-new"), HostKeyPolicy::AcceptNew);
+        assert_eq!(parse("off"), HostKeyPolicy::Off);
+        assert_eq!(HostKeyPolicy::default(), HostKeyPolicy::AcceptNew);
+    }
+
+    #[test]
+    fn test_verification_requires_a_host_key() {
+        let session = Session::new().unwrap();
+
+        assert!(verify_host_key(&session, &config(Some(HostKeyPolicy::Strict))).is_err());
+        assert!(verify_host_key(&session, &config(Some(HostKeyPolicy::Off))).is_ok());
+    }
+}
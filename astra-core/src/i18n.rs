@@ -1,10 +1,12 @@
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
 
 /// Supported languages
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Language {
+    #[default]
     #[serde(rename = "en")]
     English,
     #[serde(rename = "zh")]
@@ -23,14 +25,9 @@ pub enum Language {
     Russian,
 }
 
-impl Default for Language {
-    fn default() -> Self {
-        Language::English
-    }
-}
-
 impl Language {
     /// Try to parse language from string
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "en" | "english" => Some(Language::English),
@@ -80,6 +77,12 @@ pub struct TranslationStore {
     pub messages: HashMap<String, HashMap<Language, String>>,
 }
 
+impl Default for TranslationStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TranslationStore {
     pub fn new() -> Self {
         let mut store = Self {
@@ -617,6 +620,369 @@ impl TranslationStore {
             "Скачивание: {0} -> {1}",
         );
 
+        self.add_translation(
+            "cli.delete_operation",
+            Language::English,
+            "Deleting remote: {0}",
+        );
+        self.add_translation(
+            "cli.delete_operation",
+            Language::Chinese,
+            "删除远程文件: {0}",
+        );
+        self.add_translation(
+            "cli.delete_operation",
+            Language::Japanese,
+            "リモートを削除中: {0}",
+        );
+        self.add_translation(
+            "cli.delete_operation",
+            Language::Korean,
+            "원격 삭제 중: {0}",
+        );
+        self.add_translation(
+            "cli.delete_operation",
+            Language::Spanish,
+            "Eliminando remoto: {0}",
+        );
+        self.add_translation(
+            "cli.delete_operation",
+            Language::French,
+            "Suppression distante: {0}",
+        );
+        self.add_translation(
+            "cli.delete_operation",
+            Language::German,
+            "Lösche entfernt: {0}",
+        );
+        self.add_translation(
+            "cli.delete_operation",
+            Language::Russian,
+            "Удаление на сервере: {0}",
+        );
+        self.add_translation(
+            "cli.delete_local_operation",
+            Language::English,
+            "Deleting local: {0}",
+        );
+        self.add_translation(
+            "cli.delete_local_operation",
+            Language::Chinese,
+            "删除本地文件: {0}",
+        );
+        self.add_translation(
+            "cli.delete_local_operation",
+            Language::Japanese,
+            "ローカルを削除中: {0}",
+        );
+        self.add_translation(
+            "cli.delete_local_operation",
+            Language::Korean,
+            "로컬 삭제 중: {0}",
+        );
+        self.add_translation(
+            "cli.delete_local_operation",
+            Language::Spanish,
+            "Eliminando local: {0}",
+        );
+        self.add_translation(
+            "cli.delete_local_operation",
+            Language::French,
+            "Suppression locale: {0}",
+        );
+        self.add_translation(
+            "cli.delete_local_operation",
+            Language::German,
+            "Lösche lokal: {0}",
+        );
+        self.add_translation(
+            "cli.delete_local_operation",
+            Language::Russian,
+            "Удаление локально: {0}",
+        );
+        self.add_translation(
+            "cli.conflict_operation",
+            Language::English,
+            "Conflict, changed on both sides: {0}",
+        );
+        self.add_translation(
+            "cli.conflict_operation",
+            Language::Chinese,
+            "冲突，两端均已修改: {0}",
+        );
+        self.add_translation(
+            "cli.conflict_operation",
+            Language::Japanese,
+            "競合、両側で変更されています: {0}",
+        );
+        self.add_translation(
+            "cli.conflict_operation",
+            Language::Korean,
+            "충돌, 양쪽 모두 변경됨: {0}",
+        );
+        self.add_translation(
+            "cli.conflict_operation",
+            Language::Spanish,
+            "Conflicto, modificado en ambos lados: {0}",
+        );
+        self.add_translation(
+            "cli.conflict_operation",
+            Language::French,
+            "Conflit, modifié des deux côtés: {0}",
+        );
+        self.add_translation(
+            "cli.conflict_operation",
+            Language::German,
+            "Konflikt, auf beiden Seiten geändert: {0}",
+        );
+        self.add_translation(
+            "cli.conflict_operation",
+            Language::Russian,
+            "Конфликт, изменено с обеих сторон: {0}",
+        );
+        self.add_translation(
+            "cli.keep_both_operation",
+            Language::English,
+            "Keeping both versions of {0}, remote copy saved as {1}",
+        );
+        self.add_translation(
+            "cli.keep_both_operation",
+            Language::Chinese,
+            "保留 {0} 的两个版本，远程副本保存为 {1}",
+        );
+        self.add_translation(
+            "cli.keep_both_operation",
+            Language::Japanese,
+            "{0} の両方のバージョンを保持、リモートのコピーは {1} に保存",
+        );
+        self.add_translation(
+            "cli.keep_both_operation",
+            Language::Korean,
+            "{0}의 두 버전을 모두 유지, 원격 사본은 {1}(으)로 저장",
+        );
+        self.add_translation(
+            "cli.keep_both_operation",
+            Language::Spanish,
+            "Conservando ambas versiones de {0}, copia remota guardada como {1}",
+        );
+        self.add_translation(
+            "cli.keep_both_operation",
+            Language::French,
+            "Conservation des deux versions de {0}, copie distante enregistrée sous {1}",
+        );
+        self.add_translation(
+            "cli.keep_both_operation",
+            Language::German,
+            "Beide Versionen von {0} werden behalten, entfernte Kopie gespeichert als {1}",
+        );
+        self.add_translation(
+            "cli.keep_both_operation",
+            Language::Russian,
+            "Сохраняются обе версии {0}, удалённая копия сохранена как {1}",
+        );
+        self.add_translation(
+            "cli.conflicts_pending",
+            Language::English,
+            "Sync finished with {0} unresolved conflict(s)",
+        );
+        self.add_translation(
+            "cli.conflicts_pending",
+            Language::Chinese,
+            "同步完成，有 {0} 个冲突待解决",
+        );
+        self.add_translation(
+            "cli.conflicts_pending",
+            Language::Japanese,
+            "同期完了、未解決の競合が {0} 件あります",
+        );
+        self.add_translation(
+            "cli.conflicts_pending",
+            Language::Korean,
+            "동기화 완료, 해결되지 않은 충돌 {0}개",
+        );
+        self.add_translation(
+            "cli.conflicts_pending",
+            Language::Spanish,
+            "Sincronización terminada con {0} conflicto(s) sin resolver",
+        );
+        self.add_translation(
+            "cli.conflicts_pending",
+            Language::French,
+            "Synchronisation terminée avec {0} conflit(s) non résolu(s)",
+        );
+        self.add_translation(
+            "cli.conflicts_pending",
+            Language::German,
+            "Synchronisierung mit {0} ungelösten Konflikt(en) beendet",
+        );
+        self.add_translation(
+            "cli.conflicts_pending",
+            Language::Russian,
+            "Синхронизация завершена, неразрешённых конфликтов: {0}",
+        );
+        self.add_translation(
+            "cli.directory_transfer_complete",
+            Language::English,
+            "Transferred {0} files",
+        );
+        self.add_translation(
+            "cli.directory_transfer_complete",
+            Language::Chinese,
+            "已传输 {0} 个文件",
+        );
+        self.add_translation(
+            "cli.directory_transfer_complete",
+            Language::Japanese,
+            "{0} 個のファイルを転送しました",
+        );
+        self.add_translation(
+            "cli.directory_transfer_complete",
+            Language::Korean,
+            "{0}개 파일을 전송했습니다",
+        );
+        self.add_translation(
+            "cli.directory_transfer_complete",
+            Language::Spanish,
+            "Se transfirieron {0} archivos",
+        );
+        self.add_translation(
+            "cli.directory_transfer_complete",
+            Language::French,
+            "{0} fichiers transférés",
+        );
+        self.add_translation(
+            "cli.directory_transfer_complete",
+            Language::German,
+            "{0} Dateien übertragen",
+        );
+        self.add_translation(
+            "cli.directory_transfer_complete",
+            Language::Russian,
+            "Передано файлов: {0}",
+        );
+        self.add_translation(
+            "cli.directory_transfer_failed",
+            Language::English,
+            "{0} of {1} files failed to transfer",
+        );
+        self.add_translation(
+            "cli.directory_transfer_failed",
+            Language::Chinese,
+            "{1} 个文件中有 {0} 个传输失败",
+        );
+        self.add_translation(
+            "cli.directory_transfer_failed",
+            Language::Japanese,
+            "{1} 個中 {0} 個のファイルの転送に失敗しました",
+        );
+        self.add_translation(
+            "cli.directory_transfer_failed",
+            Language::Korean,
+            "{1}개 중 {0}개 파일 전송에 실패했습니다",
+        );
+        self.add_translation(
+            "cli.directory_transfer_failed",
+            Language::Spanish,
+            "No se pudieron transferir {0} de {1} archivos",
+        );
+        self.add_translation(
+            "cli.directory_transfer_failed",
+            Language::French,
+            "Échec du transfert de {0} fichiers sur {1}",
+        );
+        self.add_translation(
+            "cli.directory_transfer_failed",
+            Language::German,
+            "{0} von {1} Dateien konnten nicht übertragen werden",
+        );
+        self.add_translation(
+            "cli.directory_transfer_failed",
+            Language::Russian,
+            "Не удалось передать {0} из {1} файлов",
+        );
+
+        self.add_translation("error.upload_failed", Language::English, "Upload failed");
+        self.add_translation("error.upload_failed", Language::Chinese, "上传失败");
+        self.add_translation(
+            "error.upload_failed",
+            Language::Japanese,
+            "アップロードに失敗しました",
+        );
+        self.add_translation("error.upload_failed", Language::Korean, "업로드 실패");
+        self.add_translation("error.upload_failed", Language::Spanish, "Error al subir");
+        self.add_translation(
+            "error.upload_failed",
+            Language::French,
+            "Échec du téléversement",
+        );
+        self.add_translation(
+            "error.upload_failed",
+            Language::German,
+            "Hochladen fehlgeschlagen",
+        );
+        self.add_translation(
+            "error.upload_failed",
+            Language::Russian,
+            "Ошибка загрузки на сервер",
+        );
+
+        self.add_translation(
+            "error.download_failed",
+            Language::English,
+            "Download failed",
+        );
+        self.add_translation("error.download_failed", Language::Chinese, "下载失败");
+        self.add_translation(
+            "error.download_failed",
+            Language::Japanese,
+            "ダウンロードに失敗しました",
+        );
+        self.add_translation("error.download_failed", Language::Korean, "다운로드 실패");
+        self.add_translation(
+            "error.download_failed",
+            Language::Spanish,
+            "Error al descargar",
+        );
+        self.add_translation(
+            "error.download_failed",
+            Language::French,
+            "Échec du téléchargement",
+        );
+        self.add_translation(
+            "error.download_failed",
+            Language::German,
+            "Herunterladen fehlgeschlagen",
+        );
+        self.add_translation(
+            "error.download_failed",
+            Language::Russian,
+            "Ошибка скачивания",
+        );
+        self.add_translation("error.delete_failed", Language::English, "Delete failed");
+        self.add_translation("error.delete_failed", Language::Chinese, "删除失败");
+        self.add_translation(
+            "error.delete_failed",
+            Language::Japanese,
+            "削除に失敗しました",
+        );
+        self.add_translation("error.delete_failed", Language::Korean, "삭제 실패");
+        self.add_translation(
+            "error.delete_failed",
+            Language::Spanish,
+            "Error al eliminar",
+        );
+        self.add_translation(
+            "error.delete_failed",
+            Language::French,
+            "Échec de la suppression",
+        );
+        self.add_translation(
+            "error.delete_failed",
+            Language::German,
+            "Löschen fehlgeschlagen",
+        );
+        self.add_translation("error.delete_failed", Language::Russian, "Ошибка удаления");
+
         self.add_translation(
             "cli.pending_operations",
             Language::English,
@@ -713,7 +1079,7 @@ impl TranslationStore {
     fn add_translation(&mut self, key: &str, language: Language, message: &str) {
         self.messages
             .entry(key.to_string())
-            .or_insert_with(HashMap::new)
+            .or_default()
             .insert(language, message.to_string());
     }
 
@@ -726,7 +1092,7 @@ impl TranslationStore {
     pub fn get_translation_or_fallback(&self, key: &str, language: &Language) -> String {
         self.get_translation(key, language)
             .or_else(|| self.get_translation(key, &Language::English))
-            .map(|s| s.clone())
+            .cloned()
             .unwrap_or_else(|| key.to_string())
     }
 
@@ -755,25 +1121,16 @@ impl TranslationStore {
 }
 
 /// Global translation store
-static mut TRANSLATION_STORE: Option<TranslationStore> = None;
+static TRANSLATION_STORE: OnceCell<TranslationStore> = OnceCell::new();
 
 /// Initialize translation store
 pub fn init_translations() {
-    unsafe {
-        if TRANSLATION_STORE.is_none() {
-            TRANSLATION_STORE = Some(TranslationStore::new());
-        }
-    }
+    TRANSLATION_STORE.get_or_init(TranslationStore::new);
 }
 
 /// Get translation store
 pub fn get_translation_store() -> &'static TranslationStore {
-    unsafe {
-        if TRANSLATION_STORE.is_none() {
-            init_translations();
-        }
-        TRANSLATION_STORE.as_ref().unwrap()
-    }
+    TRANSLATION_STORE.get_or_init(TranslationStore::new)
 }
 
 /// Get translation for a key in the specified language
@@ -827,7 +1184,7 @@ pub fn detect_language() -> Language {
     }
 
     if let Ok(lang) = env::var("LANG") {
-        if let Some(detected) = Language::from_str(&lang.split('_').next().unwrap_or("")) {
+        if let Some(detected) = Language::from_str(lang.split('_').next().unwrap_or("")) {
             return detected;
         }
     }
@@ -1,29 +1,71 @@
+pub mod auth;
 pub mod background;
 pub mod cli;
 pub mod config;
 pub mod error;
+pub mod filter;
+pub mod hostkey;
 pub mod i18n;
+pub mod output;
+pub mod paths;
+pub mod planner;
+pub mod progress;
+pub mod serve;
 pub mod sftp;
+pub mod state;
+pub mod transfer;
 pub mod types;
 pub mod version;
 
+#[cfg(test)]
+mod auth_tests;
+#[cfg(test)]
+mod background_tests;
 #[cfg(test)]
 mod cli_tests;
 #[cfg(test)]
+mod config_tests;
+#[cfg(test)]
+mod error_tests;
+#[cfg(test)]
+mod filter_tests;
+#[cfg(test)]
+mod hostkey_tests;
+#[cfg(test)]
 mod integration_tests;
 #[cfg(test)]
+mod output_tests;
+#[cfg(test)]
+mod paths_tests;
+#[cfg(test)]
+mod planner_tests;
+#[cfg(test)]
+mod progress_tests;
+#[cfg(test)]
+mod serve_tests;
+#[cfg(test)]
 mod sftp_tests;
 #[cfg(test)]
+mod state_tests;
+#[cfg(test)]
 mod test_tilde;
 #[cfg(test)]
+mod transfer_tests;
+#[cfg(test)]
 mod types_tests;
 
-use crate::error::AstraResult;
 use clap::Parser;
 use cli::{run_cli, Cli};
 
 #[tokio::main]
-async fn main() -> AstraResult<()> {
+async fn main() {
     let cli = Cli::parse();
-    run_cli(cli).await
+    let code = match run_cli(cli).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            e.exit_code()
+        }
+    };
+    std::process::exit(code);
 }
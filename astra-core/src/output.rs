@@ -0,0 +1,124 @@
+use crate::error::{AstraError, AstraResult};
+use crate::progress::ProgressReporter;
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// Version of the JSON documents written in `--output json` mode. Bumped whenever a field is
+/// removed or changes meaning; adding fields does not change it.
+pub const OUTPUT_SCHEMA_VERSION: u32 = 1;
+
+/// How a command reports its result on stdout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Localized, human-readable text
+    #[default]
+    Text,
+    /// One JSON document per command on a single line, as the last line on stdout. Transfers
+    /// print their progress events before it, one JSON object per line with an `event` field.
+    Json,
+}
+
+/// The document every command prints in JSON mode
+#[derive(Debug, Serialize)]
+pub struct Envelope<'a, T: Serialize> {
+    pub schema_version: u32,
+    pub command: &'a str,
+    /// Whether the command did everything it was asked to
+    pub ok: bool,
+    pub data: Option<T>,
+    pub error: Option<ErrorBody>,
+}
+
+/// A failed command, with the [`AstraError`] variant as a stable `kind`
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorBody {
+    pub kind: String,
+    pub message: String,
+}
+
+impl From<&AstraError> for ErrorBody {
+    fn from(error: &AstraError) -> Self {
+        Self {
+            kind: error.kind().to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Routes command output to stdout in the selected format.
+///
+/// Text is only printed in text mode and documents only in JSON mode, so a command can describe
+/// its progress for humans and still leave a clean stdout for scripts. Logs always go to stderr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Output {
+    format: OutputFormat,
+    quiet: bool,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            quiet: false,
+        }
+    }
+
+    /// Output that prints nothing, for callers that report results over another channel
+    pub fn quiet() -> Self {
+        Self {
+            format: OutputFormat::Json,
+            quiet: true,
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+
+    /// Where sessions report transfer progress: NDJSON on stdout in JSON mode, nowhere otherwise
+    pub fn progress(&self) -> ProgressReporter {
+        if self.is_json() && !self.quiet {
+            ProgressReporter::ndjson()
+        } else {
+            ProgressReporter::default()
+        }
+    }
+
+    /// Print a line of human-readable output (text mode only)
+    pub fn say(&self, message: impl std::fmt::Display) {
+        if !self.is_json() {
+            println!("{}", message);
+        }
+    }
+
+    /// Print the result document of `command` (JSON mode only)
+    pub fn emit<T: Serialize>(&self, command: &str, ok: bool, data: &T) -> AstraResult<()> {
+        if self.is_json() && !self.quiet {
+            let envelope = Envelope {
+                schema_version: OUTPUT_SCHEMA_VERSION,
+                command,
+                ok,
+                data: Some(data),
+                error: None,
+            };
+            println!("{}", serde_json::to_string(&envelope)?);
+        }
+        Ok(())
+    }
+
+    /// Print the document for a command that failed (JSON mode only)
+    pub fn emit_error(&self, command: &str, error: &AstraError) {
+        if self.is_json() && !self.quiet {
+            let envelope: Envelope<'_, ()> = Envelope {
+                schema_version: OUTPUT_SCHEMA_VERSION,
+                command,
+                ok: false,
+                data: None,
+                error: Some(ErrorBody::from(error)),
+            };
+            if let Ok(json) = serde_json::to_string(&envelope) {
+                println!("{}", json);
+            }
+        }
+    }
+}
@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod tests {
+    use crate::error::AstraError;
+    use crate::output::{Envelope, ErrorBody, OUTPUT_SCHEMA_VERSION};
+    use serde_json::json;
+
+    #[test]
+    fn test_envelope_shape() {
+        let envelope = Envelope {
+            schema_version: OUTPUT_SCHEMA_VERSION,
+            command: "upload",
+            ok: true,
+            data: Some(json!({ "bytes": 12 })),
+            error: None,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&envelope).unwrap(),
+            json!({
+                "schema_version": 1,
+                "command": "upload",
+                "ok": true,
+                "data": { "bytes": 12 },
+                "error": null,
+            })
+        );
+    }
+
+    #[test]
+    fn test_error_body_uses_variant_name() {
+        let error = AstraError::HostKeyMismatch("example.com".to_string());
+        let body = ErrorBody::from(&error);
+
+        assert_eq!(body.kind, "HostKeyMismatch");
+        assert_eq!(body.message, "Host key mismatch: example.com");
+        assert_eq!(
+            ErrorBody::from(&AstraError::IoError(std::io::Error::other("disk"))).kind,
+            "IoError"
+        );
+    }
+}
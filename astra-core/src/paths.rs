@@ -0,0 +1,250 @@
+use crate::config::ConfigReader;
+use crate::error::{AstraError, AstraResult};
+use crate::types::{SftpConfig, SyncOptions};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Maps paths between the local project root and the remote root.
+///
+/// All sync planning is done on paths relative to the two roots; remote paths are always built
+/// with POSIX separators regardless of the local platform.
+#[derive(Debug, Clone)]
+pub struct PathMapper {
+    local_root: PathBuf,
+    remote_root: PathBuf,
+}
+
+impl PathMapper {
+    pub fn new(local_root: &str, remote_root: &str) -> Self {
+        Self {
+            local_root: normalize_local_path(Path::new(local_root)),
+            remote_root: PathBuf::from(normalize_remote_path(remote_root)),
+        }
+    }
+
+    pub fn from_config(config: &SftpConfig) -> Self {
+        Self::new(&config.local_path, &config.remote_path)
+    }
+
+    pub fn local_root(&self) -> &Path {
+        &self.local_root
+    }
+
+    pub fn remote_root(&self) -> &Path {
+        &self.remote_root
+    }
+
+    /// Path of a local file relative to the local root
+    pub fn local_to_relative(&self, local_path: &Path) -> AstraResult<PathBuf> {
+        let local_path = normalize_local_path(local_path);
+
+        if let Ok(relative) = local_path.strip_prefix(&self.local_root) {
+            return Ok(relative.to_path_buf());
+        }
+
+        // Fall back to resolving symlinks on both sides, e.g. /tmp vs /private/tmp
+        if let (Ok(path), Ok(root)) = (local_path.canonicalize(), self.local_root.canonicalize()) {
+            if let Ok(relative) = path.strip_prefix(&root) {
+                return Ok(relative.to_path_buf());
+            }
+        }
+
+        Err(AstraError::FileOperationError(format!(
+            "{} is outside the local root {}",
+            local_path.display(),
+            self.local_root.display()
+        )))
+    }
+
+    /// Path of a remote file relative to the remote root
+    pub fn remote_to_relative(&self, remote_path: &Path) -> AstraResult<PathBuf> {
+        let remote_path = PathBuf::from(normalize_remote_path(&remote_path.to_string_lossy()));
+
+        remote_path
+            .strip_prefix(&self.remote_root)
+            .map(Path::to_path_buf)
+            .map_err(|_| {
+                AstraError::FileOperationError(format!(
+                    "{} is outside the remote root {}",
+                    remote_path.display(),
+                    self.remote_root.display()
+                ))
+            })
+    }
+
+    pub fn relative_to_local(&self, relative_path: &Path) -> PathBuf {
+        self.local_root.join(relative_path)
+    }
+
+    pub fn relative_to_remote(&self, relative_path: &Path) -> PathBuf {
+        let relative = to_posix(relative_path);
+        if relative.is_empty() {
+            return self.remote_root.clone();
+        }
+
+        let root = self.remote_root.to_string_lossy();
+        let joined = if root.ends_with('/') {
+            format!("{}{}", root, relative)
+        } else {
+            format!("{}/{}", root, relative)
+        };
+        PathBuf::from(normalize_remote_path(&joined))
+    }
+
+    pub fn local_to_remote(&self, local_path: &Path) -> AstraResult<PathBuf> {
+        let relative = self.local_to_relative(local_path)?;
+        Ok(self.relative_to_remote(&relative))
+    }
+
+    pub fn remote_to_local(&self, remote_path: &Path) -> AstraResult<PathBuf> {
+        let relative = self.remote_to_relative(remote_path)?;
+        Ok(self.relative_to_local(&relative))
+    }
+}
+
+/// Normalize a remote path: `\` becomes `/`, repeated separators and `.` segments are dropped,
+/// `..` is resolved lexically and any trailing separator is removed.
+pub fn normalize_remote_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let absolute = path.starts_with('/');
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if segments.last().is_some_and(|last| *last != "..") {
+                    segments.pop();
+                } else if !absolute {
+                    segments.push("..");
+                }
+            }
+            _ => segments.push(segment),
+        }
+    }
+
+    let joined = segments.join("/");
+    if absolute {
+        format!("/{}", joined)
+    } else if joined.is_empty() {
+        ".".to_string()
+    } else {
+        joined
+    }
+}
+
+/// `[sync] trash_dir` relative to the remote root, or `None` if it is unset, absolute, under
+/// `~` or outside the root
+pub fn relative_trash_dir(options: &SyncOptions) -> Option<String> {
+    let trash_dir = options.trash_dir.as_deref()?.trim();
+    if trash_dir.starts_with(['/', '\\', '~']) {
+        return None;
+    }
+
+    let trash_dir = normalize_remote_path(trash_dir);
+    if trash_dir == "." || trash_dir == ".." || trash_dir.starts_with("../") {
+        return None;
+    }
+    Some(trash_dir)
+}
+
+/// Absolute remote directory that deleted files are moved into, if `[sync] trash_dir` is set
+pub fn remote_trash_dir(config: &SftpConfig) -> Option<String> {
+    let trash_dir = config.sync.trash_dir.as_deref()?.trim();
+    if trash_dir.is_empty() {
+        return None;
+    }
+
+    let trash_dir = if trash_dir.starts_with('~') {
+        ConfigReader::expand_tilde_remote(trash_dir, &config.username)
+    } else if trash_dir.starts_with(['/', '\\']) {
+        trash_dir.to_string()
+    } else {
+        format!("{}/{}", config.remote_path, trash_dir)
+    };
+    Some(normalize_remote_path(&trash_dir))
+}
+
+/// Suffix of the temporary file a transfer writes before renaming it over the target
+pub const TEMP_SUFFIX: &str = ".astra-part";
+
+/// Hidden temporary file next to `target` (`dir/.name.<pid>-<n>.astra-part`) that a transfer
+/// writes into, so the target is only ever replaced by a complete file. Every call gives a new
+/// name, so concurrent transfers of one target, in this process or another, never share one.
+pub fn temp_path(target: &Path) -> PathBuf {
+    static NEXT_TEMP: AtomicU64 = AtomicU64::new(0);
+
+    let name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    target.with_file_name(format!(
+        ".{}.{}-{}{}",
+        name,
+        std::process::id(),
+        NEXT_TEMP.fetch_add(1, Ordering::Relaxed),
+        TEMP_SUFFIX
+    ))
+}
+
+/// Whether `path` is a temporary file [`temp_path`] made for `target`
+pub fn is_temp_of(path: &Path, target: &Path) -> bool {
+    let (Some(name), Some(target_name)) = (path.file_name(), target.file_name()) else {
+        return false;
+    };
+    let name = name.to_string_lossy();
+    let token = name
+        .strip_prefix(&format!(".{}.", target_name.to_string_lossy()))
+        .and_then(|rest| rest.strip_suffix(TEMP_SUFFIX));
+    // The `<pid>-<n>` in between, so `.a.b.1-2.astra-part` is not taken for a temp file of `a`
+    token
+        .and_then(|token| token.split_once('-'))
+        .is_some_and(|(pid, n)| {
+            !pid.is_empty()
+                && !n.is_empty()
+                && pid.bytes().chain(n.bytes()).all(|b| b.is_ascii_digit())
+        })
+}
+
+/// Whether `path` names a temporary file made by [`temp_path`]
+pub fn is_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy())
+        .is_some_and(|name| name.starts_with('.') && name.ends_with(TEMP_SUFFIX))
+}
+
+/// Join the components of a relative path with `/`
+pub fn to_posix(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            Component::ParentDir => Some("..".to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Make a local path absolute (relative to the current directory) and resolve `.`/`..`
+/// lexically, without touching the filesystem
+pub fn normalize_local_path(path: &Path) -> PathBuf {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .map(|cwd| cwd.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    };
+
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
@@ -0,0 +1,137 @@
+#[cfg(test)]
+mod tests {
+    use crate::paths::{
+        is_temp_file, is_temp_of, normalize_remote_path, relative_trash_dir, remote_trash_dir,
+        temp_path, PathMapper,
+    };
+    use crate::types::{SftpConfig, SyncOptions};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_normalize_remote_path() {
+        assert_eq!(normalize_remote_path("/var/www/"), "/var/www");
+        assert_eq!(normalize_remote_path("/var//www/./site"), "/var/www/site");
+        assert_eq!(normalize_remote_path("\\var\\www\\site"), "/var/www/site");
+        assert_eq!(normalize_remote_path("/var/www/../tmp"), "/var/tmp");
+        assert_eq!(normalize_remote_path("/"), "/");
+        assert_eq!(normalize_remote_path("/.."), "/");
+        assert_eq!(normalize_remote_path("project/src"), "project/src");
+    }
+
+    #[test]
+    fn test_local_to_remote_keeps_nested_structure() {
+        let mapper = PathMapper::new("/home/user/project", "/var/www/site/");
+
+        assert_eq!(
+            mapper
+                .local_to_remote(Path::new("/home/user/project/src/lib/mod.rs"))
+                .unwrap(),
+            PathBuf::from("/var/www/site/src/lib/mod.rs")
+        );
+        assert_eq!(
+            mapper
+                .local_to_remote(Path::new("/home/user/project/./src/../README.md"))
+                .unwrap(),
+            PathBuf::from("/var/www/site/README.md")
+        );
+    }
+
+    #[test]
+    fn test_remote_to_local() {
+        let mapper = PathMapper::new("/home/user/project/", "/var/www/site");
+
+        assert_eq!(
+            mapper
+                .remote_to_local(Path::new("/var/www/site/assets/app.js"))
+                .unwrap(),
+            PathBuf::from("/home/user/project/assets/app.js")
+        );
+        assert_eq!(
+            mapper
+                .remote_to_relative(Path::new("/var/www/site//assets/"))
+                .unwrap(),
+            PathBuf::from("assets")
+        );
+    }
+
+    #[test]
+    fn test_paths_outside_roots_are_rejected() {
+        let mapper = PathMapper::new("/home/user/project", "/var/www/site");
+
+        assert!(mapper
+            .local_to_remote(Path::new("/home/user/other/file.txt"))
+            .is_err());
+        assert!(mapper
+            .remote_to_local(Path::new("/var/www/site-old/file.txt"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_relative_to_remote_uses_posix_separators() {
+        let mapper = PathMapper::new("/home/user/project", "/");
+
+        assert_eq!(
+            mapper.relative_to_remote(Path::new("src/main.rs")),
+            PathBuf::from("/src/main.rs")
+        );
+        assert_eq!(mapper.relative_to_remote(Path::new("")), PathBuf::from("/"));
+    }
+
+    #[test]
+    fn test_trash_dir_resolution() {
+        let config = |trash_dir: Option<&str>| SftpConfig {
+            username: "deploy".to_string(),
+            remote_path: "/srv/site".to_string(),
+            sync: SyncOptions {
+                trash_dir: trash_dir.map(str::to_string),
+                ..SyncOptions::default()
+            },
+            ..SftpConfig::for_test("/home/deploy/project")
+        };
+
+        assert_eq!(remote_trash_dir(&config(None)), None);
+        assert_eq!(
+            remote_trash_dir(&config(Some(".trash/"))).as_deref(),
+            Some("/srv/site/.trash")
+        );
+        assert_eq!(
+            remote_trash_dir(&config(Some("~/trash"))).as_deref(),
+            Some("/home/deploy/trash")
+        );
+        assert_eq!(
+            remote_trash_dir(&config(Some("/var/trash"))).as_deref(),
+            Some("/var/trash")
+        );
+
+        // Only a trash directory inside the sync root needs to be kept out of the sync
+        assert_eq!(
+            relative_trash_dir(&config(Some("./.trash/")).sync).as_deref(),
+            Some(".trash")
+        );
+        assert_eq!(relative_trash_dir(&config(Some("../trash")).sync), None);
+        assert_eq!(relative_trash_dir(&config(Some("/var/trash")).sync), None);
+    }
+
+    #[test]
+    fn test_temp_file_sits_next_to_its_target() {
+        let target = Path::new("/var/www/site/index.php");
+        let temp = temp_path(target);
+
+        assert_eq!(temp.parent(), target.parent());
+        let name = temp.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name.starts_with(".index.php."), "{}", name);
+        assert!(is_temp_file(&temp));
+        // Two writers of the same target never share a temp file
+        assert_ne!(temp_path(target), temp);
+
+        assert!(is_temp_of(&temp, target));
+        assert!(is_temp_of(Path::new(".index.php.123-4.astra-part"), target));
+        assert!(!is_temp_of(&temp, Path::new("/var/www/site/index")));
+        assert!(!is_temp_of(
+            Path::new(".index.php.old.1-2.astra-part"),
+            target
+        ));
+        assert!(!is_temp_file(Path::new("/var/www/site/index.php")));
+        assert!(!is_temp_file(Path::new("notes.astra-part")));
+    }
+}
@@ -0,0 +1,569 @@
+use crate::error::{AstraError, AstraResult};
+use crate::paths::{to_posix, PathMapper};
+use crate::state::SyncState;
+use crate::transfer::parent_directories;
+use crate::types::{
+    ConflictPolicy, FileStatus, OperationType, SyncConflict, SyncMode, SyncOperation, SyncReason,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Which side of a file pair holds the newer content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Newer {
+    Local,
+    Remote,
+    Same,
+}
+
+/// Compare a local file with its remote counterpart.
+///
+/// Remote mtimes only have second precision, so both sides are compared at that resolution.
+/// When the timestamps agree but the size (or a known checksum) differs, the local copy wins.
+pub fn compare_files(local: &FileStatus, remote: &FileStatus) -> Newer {
+    let local_secs = local.modified.timestamp();
+    let remote_secs = remote.modified.timestamp();
+
+    if local_secs > remote_secs {
+        Newer::Local
+    } else if remote_secs > local_secs {
+        Newer::Remote
+    } else if content_differs(local, remote) {
+        Newer::Local
+    } else {
+        Newer::Same
+    }
+}
+
+fn content_differs(local: &FileStatus, remote: &FileStatus) -> bool {
+    if local.size != remote.size {
+        return true;
+    }
+    match (&local.checksum, &remote.checksum) {
+        (Some(local_sum), Some(remote_sum)) => local_sum != remote_sum,
+        _ => false,
+    }
+}
+
+/// How a path changed since the last sync, judged against the baseline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathChange {
+    Unchanged,
+    LocalChanged,
+    RemoteChanged,
+    BothChanged,
+    /// Removed locally since the last sync
+    LocalDeleted,
+    /// Removed on the server since the last sync
+    RemoteDeleted,
+    /// Removed on both sides since the last sync
+    BothDeleted,
+    /// Not in the baseline, so there is nothing to compare against
+    Untracked,
+}
+
+/// Classify a path by comparing whatever exists on each side with its baseline entry
+pub fn classify(
+    baseline: &SyncState, relative_path: &Path, local: Option<&FileStatus>,
+    remote: Option<&FileStatus>,
+) -> PathChange {
+    let Some(entry) = baseline.get(relative_path) else {
+        return PathChange::Untracked;
+    };
+
+    match (local, remote) {
+        (Some(local), Some(remote)) => {
+            match (!entry.local.matches(local), !entry.remote.matches(remote)) {
+                (false, false) => PathChange::Unchanged,
+                (true, false) => PathChange::LocalChanged,
+                (false, true) => PathChange::RemoteChanged,
+                (true, true) => PathChange::BothChanged,
+            }
+        }
+        (None, Some(_)) => PathChange::LocalDeleted,
+        (Some(_), None) => PathChange::RemoteDeleted,
+        (None, None) => PathChange::BothDeleted,
+    }
+}
+
+/// Operations for one sync run, plus the conflicts found while planning it
+#[derive(Debug, Clone, Default)]
+pub struct SyncPlan {
+    pub operations: Vec<SyncOperation>,
+    pub conflicts: Vec<SyncConflict>,
+}
+
+/// Which tree a planned change applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Local,
+    Remote,
+}
+
+/// A file transfer in a [`PlanReport`]
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedTransfer {
+    /// POSIX path relative to the sync roots
+    pub path: String,
+    pub local_path: PathBuf,
+    pub remote_path: PathBuf,
+    pub bytes: u64,
+    pub reason: Option<SyncReason>,
+}
+
+/// A file deletion in a [`PlanReport`]
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedDeletion {
+    pub path: String,
+    pub side: Side,
+    /// The file that would be removed
+    pub target: PathBuf,
+    pub bytes: u64,
+    pub reason: Option<SyncReason>,
+}
+
+/// A directory that would be created before files are transferred into it
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedDirectory {
+    pub side: Side,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PlanTotals {
+    pub uploads: usize,
+    pub downloads: usize,
+    pub deletes: usize,
+    pub mkdirs: usize,
+    pub conflicts: usize,
+    pub upload_bytes: u64,
+    pub download_bytes: u64,
+    pub delete_bytes: u64,
+}
+
+/// A sync plan laid out for review, as printed by `astra sync --dry-run`.
+///
+/// Conflicts resolved with `keep-both` show up as both a download (of the conflict copy) and an
+/// upload; unresolved conflicts only appear under `conflicts`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanReport {
+    pub dry_run: bool,
+    pub mode: SyncMode,
+    pub local_root: PathBuf,
+    pub remote_root: PathBuf,
+    pub max_deletions: usize,
+    pub exceeds_deletion_limit: bool,
+    pub uploads: Vec<PlannedTransfer>,
+    pub downloads: Vec<PlannedTransfer>,
+    pub deletes: Vec<PlannedDeletion>,
+    pub mkdirs: Vec<PlannedDirectory>,
+    pub conflicts: Vec<SyncConflict>,
+    pub totals: PlanTotals,
+}
+
+impl PlanReport {
+    /// Lay out `plan` for review. `remote_dir_exists` is asked about the remote parents of
+    /// uploads so that only directories that are actually missing are listed.
+    pub fn new<F>(
+        mode: SyncMode, mapper: &PathMapper, plan: &SyncPlan, max_deletions: usize,
+        remote_dir_exists: F,
+    ) -> Self
+    where
+        F: Fn(&Path) -> bool,
+    {
+        let mut report = Self {
+            dry_run: true,
+            mode,
+            local_root: mapper.local_root().to_path_buf(),
+            remote_root: mapper.remote_root().to_path_buf(),
+            max_deletions,
+            exceeds_deletion_limit: check_deletion_limit(&plan.operations, max_deletions).is_err(),
+            uploads: Vec::new(),
+            downloads: Vec::new(),
+            deletes: Vec::new(),
+            mkdirs: Vec::new(),
+            conflicts: plan.conflicts.clone(),
+            totals: PlanTotals::default(),
+        };
+
+        for operation in &plan.operations {
+            let path = mapper
+                .remote_to_relative(&operation.remote_path)
+                .map(|relative_path| to_posix(&relative_path))
+                .unwrap_or_else(|_| operation.remote_path.display().to_string());
+            let transfer = |local_path: PathBuf, bytes| PlannedTransfer {
+                path: path.clone(),
+                local_path,
+                remote_path: operation.remote_path.clone(),
+                bytes,
+                reason: operation.reason,
+            };
+            let deletion = |side, target: &Path| PlannedDeletion {
+                path: path.clone(),
+                side,
+                target: target.to_path_buf(),
+                bytes: operation.bytes,
+                reason: operation.reason,
+            };
+
+            match operation.operation_type {
+                OperationType::Upload => report
+                    .uploads
+                    .push(transfer(operation.local_path.clone(), operation.bytes)),
+                OperationType::Download => report
+                    .downloads
+                    .push(transfer(operation.local_path.clone(), operation.bytes)),
+                OperationType::KeepBoth => {
+                    let (local_size, remote_size) = plan
+                        .conflicts
+                        .iter()
+                        .find(|conflict| conflict.path == path)
+                        .map_or((operation.bytes, 0), |conflict| {
+                            (conflict.local_size, conflict.remote_size)
+                        });
+                    let copy_path = conflict_copy_path(&operation.local_path, operation.timestamp);
+                    report.downloads.push(transfer(copy_path, remote_size));
+                    report
+                        .uploads
+                        .push(transfer(operation.local_path.clone(), local_size));
+                }
+                OperationType::Delete => report
+                    .deletes
+                    .push(deletion(Side::Remote, &operation.remote_path)),
+                OperationType::DeleteLocal => report
+                    .deletes
+                    .push(deletion(Side::Local, &operation.local_path)),
+                OperationType::CreateDirectory => report.mkdirs.push(PlannedDirectory {
+                    side: Side::Remote,
+                    path: operation.remote_path.clone(),
+                }),
+                OperationType::Conflict => {}
+            }
+        }
+
+        let (remote_parents, local_parents) = parent_directories(&plan.operations);
+        let remote_dirs = missing_directories(&remote_parents, mapper.remote_root(), |dir| {
+            remote_dir_exists(dir)
+        });
+        let local_dirs =
+            missing_directories(&local_parents, mapper.local_root(), |dir| dir.is_dir());
+        report
+            .mkdirs
+            .extend(remote_dirs.into_iter().map(|path| PlannedDirectory {
+                side: Side::Remote,
+                path,
+            }));
+        report
+            .mkdirs
+            .extend(local_dirs.into_iter().map(|path| PlannedDirectory {
+                side: Side::Local,
+                path,
+            }));
+
+        report.totals = PlanTotals {
+            uploads: report.uploads.len(),
+            downloads: report.downloads.len(),
+            deletes: report.deletes.len(),
+            mkdirs: report.mkdirs.len(),
+            conflicts: report.conflicts.len(),
+            upload_bytes: report.uploads.iter().map(|entry| entry.bytes).sum(),
+            download_bytes: report.downloads.iter().map(|entry| entry.bytes).sum(),
+            delete_bytes: report.deletes.iter().map(|entry| entry.bytes).sum(),
+        };
+        report
+    }
+}
+
+/// Every directory in `dirs` (and its ancestors below `root`) that `exists` says is missing,
+/// parents before children
+fn missing_directories<F>(dirs: &BTreeSet<PathBuf>, root: &Path, exists: F) -> BTreeSet<PathBuf>
+where
+    F: Fn(&Path) -> bool,
+{
+    let mut missing = BTreeSet::new();
+    for dir in dirs {
+        let mut current = Some(dir.as_path());
+        while let Some(dir) = current {
+            if dir == root || !dir.starts_with(root) || missing.contains(dir) || exists(dir) {
+                break;
+            }
+            missing.insert(dir.to_path_buf());
+            current = dir.parent();
+        }
+    }
+    missing
+}
+
+/// Build the operations that bring both trees in line for `mode`.
+///
+/// Both maps are keyed by path relative to the configured roots and must only contain files.
+/// Paths with a baseline entry are only transferred when a side changed since the last sync;
+/// paths without one fall back to comparing the two sides directly. In bidirectional mode a
+/// path that changed on both sides is a conflict and is handled according to `conflict_policy`.
+///
+/// A file deleted on one side since the last sync is deleted on the other side too, as long as
+/// the mode moves files in that direction and the surviving copy was not edited in the
+/// meantime; an edited copy is transferred back instead. Mirror mode deletes every remote file
+/// that does not exist locally.
+pub fn plan_operations(
+    mode: SyncMode, mapper: &PathMapper, local_files: &HashMap<PathBuf, FileStatus>,
+    remote_files: &HashMap<PathBuf, FileStatus>, baseline: &SyncState,
+    conflict_policy: ConflictPolicy,
+) -> SyncPlan {
+    let paths: BTreeSet<&PathBuf> = local_files.keys().chain(remote_files.keys()).collect();
+    let mut plan = SyncPlan::default();
+
+    for relative_path in paths {
+        let local = local_files.get(relative_path);
+        let remote = remote_files.get(relative_path);
+        let planned = match (local, remote) {
+            (Some(local), None) => {
+                let deleted_remotely =
+                    deletion_to_propagate(baseline, relative_path, Some(local), None);
+                match mode {
+                    SyncMode::Download | SyncMode::Bidirectional if deleted_remotely => {
+                        Some((OperationType::DeleteLocal, SyncReason::DeletedRemotely))
+                    }
+                    SyncMode::Upload | SyncMode::Bidirectional | SyncMode::Mirror => {
+                        Some((OperationType::Upload, SyncReason::MissingRemote))
+                    }
+                    SyncMode::Download => None,
+                }
+            }
+            (None, Some(remote)) => {
+                let deleted_locally =
+                    deletion_to_propagate(baseline, relative_path, None, Some(remote));
+                match mode {
+                    SyncMode::Upload | SyncMode::Bidirectional if deleted_locally => {
+                        Some((OperationType::Delete, SyncReason::DeletedLocally))
+                    }
+                    SyncMode::Mirror => Some((OperationType::Delete, SyncReason::MissingLocal)),
+                    SyncMode::Download | SyncMode::Bidirectional => {
+                        Some((OperationType::Download, SyncReason::MissingLocal))
+                    }
+                    SyncMode::Upload => None,
+                }
+            }
+            (Some(local), Some(remote)) => {
+                let newer = compare_files(local, remote);
+                let change = classify(baseline, relative_path, Some(local), Some(remote));
+                let reason = transfer_reason(change, local, remote);
+                let operation_type = match (mode, change) {
+                    // Identical content needs no transfer, even if both sides were edited
+                    _ if newer == Newer::Same => None,
+                    (_, PathChange::Unchanged) => None,
+                    (SyncMode::Upload | SyncMode::Mirror, _) => Some(OperationType::Upload),
+                    (SyncMode::Download, _) => Some(OperationType::Download),
+                    (SyncMode::Bidirectional, PathChange::LocalChanged) => {
+                        Some(OperationType::Upload)
+                    }
+                    (SyncMode::Bidirectional, PathChange::RemoteChanged) => {
+                        Some(OperationType::Download)
+                    }
+                    (SyncMode::Bidirectional, PathChange::BothChanged) => {
+                        let resolution = resolve_conflict(conflict_policy, newer);
+                        plan.conflicts.push(SyncConflict {
+                            path: to_posix(relative_path),
+                            local_path: mapper.relative_to_local(relative_path),
+                            remote_path: mapper.relative_to_remote(relative_path),
+                            local_size: local.size,
+                            remote_size: remote.size,
+                            local_modified: local.modified,
+                            remote_modified: remote.modified,
+                            resolution: (conflict_policy != ConflictPolicy::Ask)
+                                .then_some(conflict_policy),
+                        });
+                        resolution
+                    }
+                    (SyncMode::Bidirectional, _) => newer_side(newer),
+                };
+                operation_type.map(|operation_type| (operation_type, reason))
+            }
+            (None, None) => None,
+        };
+
+        if let Some((operation_type, reason)) = planned {
+            let bytes = planned_bytes(operation_type, local, remote);
+            plan.operations.push(build_operation(
+                operation_type,
+                mapper,
+                relative_path,
+                reason,
+                bytes,
+            ));
+        }
+    }
+
+    plan
+}
+
+/// Make a plan safe to run after a remote scan that could not list `unlisted` (paths relative
+/// to the roots, `""` for the whole tree).
+///
+/// A file below such a path only looks missing on the remote side, so every operation on one is
+/// dropped, and so is every deletion: the run cannot tell a deleted file from an unlisted one.
+/// Returns how many operations were dropped.
+pub fn restrict_to_listed(plan: &mut SyncPlan, mapper: &PathMapper, unlisted: &[PathBuf]) -> usize {
+    if unlisted.is_empty() {
+        return 0;
+    }
+
+    let before = plan.operations.len();
+    plan.operations.retain(|operation| {
+        if matches!(
+            operation.operation_type,
+            OperationType::Delete | OperationType::DeleteLocal
+        ) {
+            return false;
+        }
+        match mapper.remote_to_relative(&operation.remote_path) {
+            Ok(relative_path) => !unlisted.iter().any(|path| relative_path.starts_with(path)),
+            Err(_) => false,
+        }
+    });
+    before - plan.operations.len()
+}
+
+/// Refuse to plan against a remote root that does not exist while the baseline still tracks
+/// files below it.
+///
+/// A mistyped `remote_path`, a renamed directory and an unmounted volume all look as if every
+/// remote file was deleted, which would delete each tracked file locally. A root that was never
+/// synced to is fine: the first upload creates it.
+pub fn check_remote_root(
+    root_missing: bool, mapper: &PathMapper, baseline: &SyncState,
+) -> AstraResult<()> {
+    if root_missing && !baseline.files.is_empty() {
+        return Err(AstraError::ConfigurationError(format!(
+            "remote root {} does not exist, but {} files were synced to it before; check \
+             remote_path, or remove the sync state under .astra-settings to start over",
+            mapper.remote_root().display(),
+            baseline.files.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Whether one side of a tracked path is gone while the other still matches the baseline, so
+/// the deletion can be carried over without losing an edit
+fn deletion_to_propagate(
+    baseline: &SyncState, relative_path: &Path, local: Option<&FileStatus>,
+    remote: Option<&FileStatus>,
+) -> bool {
+    let Some(entry) = baseline.get(relative_path) else {
+        return false;
+    };
+
+    match (local, remote) {
+        (Some(local), None) => entry.local.matches(local),
+        (None, Some(remote)) => entry.remote.matches(remote),
+        _ => false,
+    }
+}
+
+/// Refuse a plan that deletes more files than `max_deletions` (0 disables the check).
+///
+/// An emptied or wrongly configured root looks exactly like a mass deletion, so the whole run is
+/// aborted before anything is touched.
+pub fn check_deletion_limit(operations: &[SyncOperation], max_deletions: usize) -> AstraResult<()> {
+    let deletions = operations
+        .iter()
+        .filter(|operation| {
+            matches!(
+                operation.operation_type,
+                OperationType::Delete | OperationType::DeleteLocal
+            )
+        })
+        .count();
+
+    if max_deletions > 0 && deletions > max_deletions {
+        return Err(AstraError::DeletionLimitExceeded(format!(
+            "this sync would delete {} files but max_deletions is {}; check the plan with \
+             `astra status`, then raise [sync] max_deletions or pass --max-deletions",
+            deletions, max_deletions
+        )));
+    }
+    Ok(())
+}
+
+/// What sets the two copies of a path apart: the baseline if it knows which side changed,
+/// otherwise the files themselves
+fn transfer_reason(change: PathChange, local: &FileStatus, remote: &FileStatus) -> SyncReason {
+    match change {
+        PathChange::LocalChanged => SyncReason::ChangedLocally,
+        PathChange::RemoteChanged => SyncReason::ChangedRemotely,
+        PathChange::BothChanged => SyncReason::ChangedOnBothSides,
+        _ => match local.modified.timestamp().cmp(&remote.modified.timestamp()) {
+            Ordering::Greater => SyncReason::NewerLocally,
+            Ordering::Less => SyncReason::NewerRemotely,
+            Ordering::Equal if local.size != remote.size => SyncReason::SizeDiffers,
+            Ordering::Equal => SyncReason::ChecksumDiffers,
+        },
+    }
+}
+
+/// Bytes an operation moves: the source side for transfers, the removed side for deletions and
+/// both sides for [`OperationType::KeepBoth`]
+fn planned_bytes(
+    operation_type: OperationType, local: Option<&FileStatus>, remote: Option<&FileStatus>,
+) -> u64 {
+    let local = local.map_or(0, |file| file.size);
+    let remote = remote.map_or(0, |file| file.size);
+    match operation_type {
+        OperationType::Upload | OperationType::DeleteLocal => local,
+        OperationType::Download | OperationType::Delete => remote,
+        OperationType::KeepBoth => local + remote,
+        OperationType::CreateDirectory | OperationType::Conflict => 0,
+    }
+}
+
+fn resolve_conflict(policy: ConflictPolicy, newer: Newer) -> Option<OperationType> {
+    match policy {
+        ConflictPolicy::PreferLocal => Some(OperationType::Upload),
+        ConflictPolicy::PreferRemote => Some(OperationType::Download),
+        ConflictPolicy::NewestWins => newer_side(newer),
+        ConflictPolicy::KeepBoth => Some(OperationType::KeepBoth),
+        ConflictPolicy::Ask => Some(OperationType::Conflict),
+    }
+}
+
+/// Where [`OperationType::KeepBoth`] saves the remote copy of a conflicting file:
+/// `index.php` becomes `index.remote-conflict-20240101-120000.php`
+pub fn conflict_copy_path(local_path: &Path, timestamp: DateTime<Utc>) -> PathBuf {
+    let suffix = format!("remote-conflict-{}", timestamp.format("%Y%m%d-%H%M%S"));
+    let stem = local_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match local_path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, suffix),
+    };
+    local_path.with_file_name(name)
+}
+
+fn newer_side(newer: Newer) -> Option<OperationType> {
+    match newer {
+        Newer::Local => Some(OperationType::Upload),
+        Newer::Remote => Some(OperationType::Download),
+        Newer::Same => None,
+    }
+}
+
+fn build_operation(
+    operation_type: OperationType, mapper: &PathMapper, relative_path: &Path, reason: SyncReason,
+    bytes: u64,
+) -> SyncOperation {
+    SyncOperation {
+        operation_type,
+        local_path: mapper.relative_to_local(relative_path),
+        remote_path: mapper.relative_to_remote(relative_path),
+        timestamp: Utc::now(),
+        reason: Some(reason),
+        bytes,
+    }
+}
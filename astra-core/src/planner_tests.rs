@@ -0,0 +1,606 @@
+#[cfg(test)]
+mod tests {
+    use crate::paths::PathMapper;
+    use crate::planner::{
+        check_deletion_limit, check_remote_root, classify, compare_files, conflict_copy_path,
+        plan_operations, restrict_to_listed, Newer, PathChange, PlanReport, Side,
+    };
+    use crate::state::{FileSnapshot, SyncState};
+    use crate::types::{
+        ConflictPolicy, FileStatus, OperationType, SyncMode, SyncOperation, SyncReason,
+    };
+    use chrono::{DateTime, Utc};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn file(path: &str, size: u64, modified: i64) -> (PathBuf, FileStatus) {
+        (
+            PathBuf::from(path),
+            FileStatus {
+                path: PathBuf::from(path),
+                size,
+                modified: DateTime::<Utc>::from_timestamp(modified, 0).unwrap(),
+                is_directory: false,
+                checksum: None,
+            },
+        )
+    }
+
+    fn fixture() -> (HashMap<PathBuf, FileStatus>, HashMap<PathBuf, FileStatus>) {
+        let local = HashMap::from([
+            file("same.txt", 10, 1_000),
+            file("local_only.txt", 5, 1_000),
+            file("src/newer_local.rs", 20, 2_000),
+            file("src/newer_remote.rs", 20, 1_000),
+        ]);
+        let remote = HashMap::from([
+            file("same.txt", 10, 1_000),
+            file("remote_only.txt", 7, 1_000),
+            file("src/newer_local.rs", 18, 1_000),
+            file("src/newer_remote.rs", 22, 2_000),
+        ]);
+        (local, remote)
+    }
+
+    fn summary(operations: &[SyncOperation]) -> Vec<(String, String)> {
+        operations
+            .iter()
+            .map(|op| {
+                let kind = match op.operation_type {
+                    OperationType::Upload => "upload",
+                    OperationType::Download => "download",
+                    OperationType::Delete => "delete",
+                    OperationType::DeleteLocal => "delete-local",
+                    OperationType::CreateDirectory => "mkdir",
+                    OperationType::Conflict => "conflict",
+                    OperationType::KeepBoth => "keep-both",
+                };
+                (kind.to_string(), op.remote_path.display().to_string())
+            })
+            .collect()
+    }
+
+    fn plan(mode: SyncMode) -> Vec<(String, String)> {
+        let (local, remote) = fixture();
+        let mapper = PathMapper::new("/home/user/project", "/srv/site");
+        summary(
+            &plan_operations(
+                mode,
+                &mapper,
+                &local,
+                &remote,
+                &SyncState::default(),
+                ConflictPolicy::Ask,
+            )
+            .operations,
+        )
+    }
+
+    fn op(kind: &str, path: &str) -> (String, String) {
+        (kind.to_string(), format!("/srv/site/{}", path))
+    }
+
+    #[test]
+    fn test_sync_mode_parsing() {
+        assert_eq!("upload".parse::<SyncMode>().unwrap(), SyncMode::Upload);
+        assert_eq!("Download".parse::<SyncMode>().unwrap(), SyncMode::Download);
+        assert_eq!(
+            "incremental".parse::<SyncMode>().unwrap(),
+            SyncMode::Bidirectional
+        );
+        assert_eq!("mirror".parse::<SyncMode>().unwrap(), SyncMode::Mirror);
+        assert!("sideways".parse::<SyncMode>().is_err());
+    }
+
+    #[test]
+    fn test_compare_files_uses_second_precision() {
+        let (_, local) = file("a", 10, 1_000);
+        let (_, mut remote) = file("a", 10, 1_000);
+        remote.modified = local.modified + chrono::Duration::milliseconds(400);
+
+        assert_eq!(compare_files(&local, &remote), Newer::Same);
+
+        remote.size = 11;
+        assert_eq!(compare_files(&local, &remote), Newer::Local);
+    }
+
+    #[test]
+    fn test_upload_mode_never_downloads() {
+        assert_eq!(
+            plan(SyncMode::Upload),
+            vec![
+                op("upload", "local_only.txt"),
+                op("upload", "src/newer_local.rs"),
+                op("upload", "src/newer_remote.rs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_download_mode_never_uploads() {
+        assert_eq!(
+            plan(SyncMode::Download),
+            vec![
+                op("download", "remote_only.txt"),
+                op("download", "src/newer_local.rs"),
+                op("download", "src/newer_remote.rs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bidirectional_mode_moves_newer_side() {
+        assert_eq!(
+            plan(SyncMode::Bidirectional),
+            vec![
+                op("upload", "local_only.txt"),
+                op("download", "remote_only.txt"),
+                op("upload", "src/newer_local.rs"),
+                op("download", "src/newer_remote.rs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mirror_mode_deletes_remote_only_files() {
+        assert_eq!(
+            plan(SyncMode::Mirror),
+            vec![
+                op("upload", "local_only.txt"),
+                op("delete", "remote_only.txt"),
+                op("upload", "src/newer_local.rs"),
+                op("upload", "src/newer_remote.rs"),
+            ]
+        );
+    }
+
+    fn snapshot(size: u64, mtime: i64) -> FileSnapshot {
+        FileSnapshot {
+            size,
+            mtime,
+            hash: None,
+        }
+    }
+
+    #[test]
+    fn test_unchanged_baseline_is_not_transferred() {
+        // The two sides never had matching mtimes, but neither changed since the last sync
+        let local = HashMap::from([file("index.php", 10, 2_000)]);
+        let remote = HashMap::from([file("index.php", 10, 1_000)]);
+        let mut baseline = SyncState::default();
+        baseline.record(
+            &PathBuf::from("index.php"),
+            snapshot(10, 2_000),
+            snapshot(10, 1_000),
+        );
+
+        let mapper = PathMapper::new("/home/user/project", "/srv/site");
+        for mode in [
+            SyncMode::Upload,
+            SyncMode::Bidirectional,
+            SyncMode::Download,
+        ] {
+            let plan = plan_operations(
+                mode,
+                &mapper,
+                &local,
+                &remote,
+                &baseline,
+                ConflictPolicy::Ask,
+            );
+            assert!(plan.operations.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_baseline_decides_which_side_changed() {
+        let mut baseline = SyncState::default();
+        baseline.record(
+            &PathBuf::from("local_edit.rs"),
+            snapshot(10, 1_000),
+            snapshot(10, 5_000),
+        );
+        baseline.record(
+            &PathBuf::from("remote_edit.rs"),
+            snapshot(10, 5_000),
+            snapshot(10, 1_000),
+        );
+
+        // Each changed side has an older mtime than the untouched side, so comparing mtimes
+        // alone would move the files the wrong way
+        let local = HashMap::from([
+            file("local_edit.rs", 12, 2_000),
+            file("remote_edit.rs", 10, 5_000),
+        ]);
+        let remote = HashMap::from([
+            file("local_edit.rs", 10, 5_000),
+            file("remote_edit.rs", 14, 2_000),
+        ]);
+
+        let mapper = PathMapper::new("/home/user/project", "/srv/site");
+        assert_eq!(
+            summary(
+                &plan_operations(
+                    SyncMode::Bidirectional,
+                    &mapper,
+                    &local,
+                    &remote,
+                    &baseline,
+                    ConflictPolicy::Ask,
+                )
+                .operations
+            ),
+            vec![
+                op("upload", "local_edit.rs"),
+                op("download", "remote_edit.rs")
+            ]
+        );
+    }
+
+    /// `both.rs` changed on both sides since the baseline; the remote edit is the newer one
+    fn conflict_fixture() -> (
+        HashMap<PathBuf, FileStatus>,
+        HashMap<PathBuf, FileStatus>,
+        SyncState,
+    ) {
+        let mut baseline = SyncState::default();
+        baseline.record(
+            &PathBuf::from("both.rs"),
+            snapshot(10, 1_000),
+            snapshot(10, 1_000),
+        );
+        let local = HashMap::from([file("both.rs", 11, 2_000)]);
+        let remote = HashMap::from([file("both.rs", 12, 3_000)]);
+        (local, remote, baseline)
+    }
+
+    #[test]
+    fn test_classify_paths_against_baseline() {
+        let (local, remote, baseline) = conflict_fixture();
+        let path = PathBuf::from("both.rs");
+        let unchanged = file("both.rs", 10, 1_000).1;
+
+        assert_eq!(
+            classify(&baseline, &path, local.get(&path), remote.get(&path)),
+            PathChange::BothChanged
+        );
+        assert_eq!(
+            classify(&baseline, &path, Some(&unchanged), Some(&unchanged)),
+            PathChange::Unchanged
+        );
+        assert_eq!(
+            classify(&baseline, &path, None, Some(&unchanged)),
+            PathChange::LocalDeleted
+        );
+        assert_eq!(
+            classify(&baseline, &path, Some(&unchanged), None),
+            PathChange::RemoteDeleted
+        );
+        assert_eq!(
+            classify(&baseline, &PathBuf::from("new.rs"), Some(&unchanged), None),
+            PathChange::Untracked
+        );
+    }
+
+    #[test]
+    fn test_conflict_policies() {
+        let (local, remote, baseline) = conflict_fixture();
+        let mapper = PathMapper::new("/home/user/project", "/srv/site");
+
+        let cases = [
+            (ConflictPolicy::PreferLocal, "upload"),
+            (ConflictPolicy::PreferRemote, "download"),
+            (ConflictPolicy::NewestWins, "download"),
+            (ConflictPolicy::KeepBoth, "keep-both"),
+            (ConflictPolicy::Ask, "conflict"),
+        ];
+        for (policy, expected) in cases {
+            let plan = plan_operations(
+                SyncMode::Bidirectional,
+                &mapper,
+                &local,
+                &remote,
+                &baseline,
+                policy,
+            );
+            assert_eq!(summary(&plan.operations), vec![op(expected, "both.rs")]);
+
+            assert_eq!(plan.conflicts.len(), 1);
+            let conflict = &plan.conflicts[0];
+            assert_eq!(conflict.path, "both.rs");
+            assert_eq!(conflict.local_size, 11);
+            assert_eq!(conflict.remote_size, 12);
+            let expected_resolution = (policy != ConflictPolicy::Ask).then_some(policy);
+            assert_eq!(conflict.resolution, expected_resolution);
+        }
+    }
+
+    #[test]
+    fn test_one_way_modes_never_report_conflicts() {
+        let (local, remote, baseline) = conflict_fixture();
+        let mapper = PathMapper::new("/home/user/project", "/srv/site");
+
+        let plan = plan_operations(
+            SyncMode::Upload,
+            &mapper,
+            &local,
+            &remote,
+            &baseline,
+            ConflictPolicy::Ask,
+        );
+        assert_eq!(summary(&plan.operations), vec![op("upload", "both.rs")]);
+        assert!(plan.conflicts.is_empty());
+    }
+
+    /// Four tracked files, each of which lost one side since the last sync
+    fn deletion_fixture() -> (
+        HashMap<PathBuf, FileStatus>,
+        HashMap<PathBuf, FileStatus>,
+        SyncState,
+    ) {
+        let mut baseline = SyncState::default();
+        for path in [
+            "deleted_locally.rs",
+            "deleted_remotely.rs",
+            "edited_after_remote_delete.rs",
+            "edited_after_local_delete.rs",
+        ] {
+            baseline.record(
+                &PathBuf::from(path),
+                snapshot(10, 1_000),
+                snapshot(10, 1_000),
+            );
+        }
+
+        let local = HashMap::from([
+            file("deleted_remotely.rs", 10, 1_000),
+            file("edited_after_remote_delete.rs", 12, 2_000),
+            file("new_local.rs", 5, 2_000),
+        ]);
+        let remote = HashMap::from([
+            file("deleted_locally.rs", 10, 1_000),
+            file("edited_after_local_delete.rs", 14, 2_000),
+        ]);
+        (local, remote, baseline)
+    }
+
+    #[test]
+    fn test_deletions_propagate_from_baseline() {
+        let (local, remote, baseline) = deletion_fixture();
+        let mapper = PathMapper::new("/home/user/project", "/srv/site");
+        let plan = |mode| {
+            summary(
+                &plan_operations(
+                    mode,
+                    &mapper,
+                    &local,
+                    &remote,
+                    &baseline,
+                    ConflictPolicy::Ask,
+                )
+                .operations,
+            )
+        };
+
+        // Edits win over deletions on the other side
+        assert_eq!(
+            plan(SyncMode::Bidirectional),
+            vec![
+                op("delete", "deleted_locally.rs"),
+                op("delete-local", "deleted_remotely.rs"),
+                op("download", "edited_after_local_delete.rs"),
+                op("upload", "edited_after_remote_delete.rs"),
+                op("upload", "new_local.rs"),
+            ]
+        );
+        // One-way modes only carry deletions in their own direction
+        assert_eq!(
+            plan(SyncMode::Upload),
+            vec![
+                op("delete", "deleted_locally.rs"),
+                op("upload", "deleted_remotely.rs"),
+                op("upload", "edited_after_remote_delete.rs"),
+                op("upload", "new_local.rs"),
+            ]
+        );
+        assert_eq!(
+            plan(SyncMode::Download),
+            vec![
+                op("download", "deleted_locally.rs"),
+                op("delete-local", "deleted_remotely.rs"),
+                op("download", "edited_after_local_delete.rs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_partial_remote_scan_deletes_nothing() {
+        let mut baseline = SyncState::default();
+        for path in ["src/a.rs", "src/b.rs", "deleted_locally.rs", "index.php"] {
+            baseline.record(
+                &PathBuf::from(path),
+                snapshot(10, 1_000),
+                snapshot(10, 1_000),
+            );
+        }
+        // Reading src/ failed, so its files are absent from the remote scan
+        let local = HashMap::from([
+            file("src/a.rs", 10, 1_000),
+            file("src/b.rs", 10, 1_000),
+            file("src/new.rs", 5, 2_000),
+            file("index.php", 12, 2_000),
+        ]);
+        let remote = HashMap::from([
+            file("deleted_locally.rs", 10, 1_000),
+            file("index.php", 10, 1_000),
+        ]);
+        let mapper = PathMapper::new("/home/user/project", "/srv/site");
+        let mut plan = plan_operations(
+            SyncMode::Bidirectional,
+            &mapper,
+            &local,
+            &remote,
+            &baseline,
+            ConflictPolicy::Ask,
+        );
+        assert!(summary(&plan.operations).contains(&op("delete-local", "src/a.rs")));
+
+        let dropped = restrict_to_listed(&mut plan, &mapper, &[PathBuf::from("src")]);
+
+        assert_eq!(dropped, 4);
+        assert_eq!(summary(&plan.operations), vec![op("upload", "index.php")]);
+        // A complete scan keeps the plan as it is
+        assert_eq!(restrict_to_listed(&mut plan, &mapper, &[]), 0);
+    }
+
+    #[test]
+    fn test_missing_remote_root_is_refused_once_synced() {
+        let (local, _, baseline) = deletion_fixture();
+        let mapper = PathMapper::new("/home/user/project", "/srv/site");
+        // Without the check, an empty remote listing deletes every unedited tracked file
+        let plan = plan_operations(
+            SyncMode::Bidirectional,
+            &mapper,
+            &local,
+            &HashMap::new(),
+            &baseline,
+            ConflictPolicy::Ask,
+        );
+        assert!(summary(&plan.operations).contains(&op("delete-local", "deleted_remotely.rs")));
+
+        assert!(check_remote_root(true, &mapper, &baseline).is_err());
+        assert!(check_remote_root(false, &mapper, &baseline).is_ok());
+        // The first sync creates the root
+        assert!(check_remote_root(true, &mapper, &SyncState::default()).is_ok());
+    }
+
+    #[test]
+    fn test_deletion_limit() {
+        let (local, remote, baseline) = deletion_fixture();
+        let mapper = PathMapper::new("/home/user/project", "/srv/site");
+        let plan = plan_operations(
+            SyncMode::Bidirectional,
+            &mapper,
+            &local,
+            &remote,
+            &baseline,
+            ConflictPolicy::Ask,
+        );
+
+        assert!(check_deletion_limit(&plan.operations, 2).is_ok());
+        assert!(check_deletion_limit(&plan.operations, 0).is_ok());
+        let error = check_deletion_limit(&plan.operations, 1).unwrap_err();
+        assert!(error.to_string().contains("would delete 2 files"));
+    }
+
+    #[test]
+    fn test_operations_carry_reasons_and_sizes() {
+        let (local, remote) = fixture();
+        let mapper = PathMapper::new("/home/user/project", "/srv/site");
+        let plan = plan_operations(
+            SyncMode::Bidirectional,
+            &mapper,
+            &local,
+            &remote,
+            &SyncState::default(),
+            ConflictPolicy::Ask,
+        );
+
+        let reasons: Vec<_> = plan
+            .operations
+            .iter()
+            .map(|op| (op.reason.unwrap(), op.bytes))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (SyncReason::MissingRemote, 5),
+                (SyncReason::MissingLocal, 7),
+                (SyncReason::NewerLocally, 20),
+                (SyncReason::NewerRemotely, 22),
+            ]
+        );
+
+        let (changed, _, baseline) = conflict_fixture();
+        let same_mtime = HashMap::from([file("both.rs", 10, 2_000)]);
+        let plan = plan_operations(
+            SyncMode::Upload,
+            &mapper,
+            &same_mtime,
+            &HashMap::from([file("both.rs", 12, 2_000)]),
+            &SyncState::default(),
+            ConflictPolicy::Ask,
+        );
+        assert_eq!(plan.operations[0].reason, Some(SyncReason::SizeDiffers));
+
+        let plan = plan_operations(
+            SyncMode::Bidirectional,
+            &mapper,
+            &changed,
+            &HashMap::from([file("both.rs", 10, 1_000)]),
+            &baseline,
+            ConflictPolicy::Ask,
+        );
+        assert_eq!(plan.operations[0].reason, Some(SyncReason::ChangedLocally));
+    }
+
+    #[test]
+    fn test_plan_report_groups_operations() {
+        let (local, remote, baseline) = deletion_fixture();
+        let mapper = PathMapper::new("/home/user/project", "/srv/site");
+        let mut local = local;
+        local.extend([file("src/new/module.rs", 9, 2_000)]);
+        let plan = plan_operations(
+            SyncMode::Bidirectional,
+            &mapper,
+            &local,
+            &remote,
+            &baseline,
+            ConflictPolicy::Ask,
+        );
+
+        // Only /srv/site/src exists on the server
+        let report = PlanReport::new(SyncMode::Bidirectional, &mapper, &plan, 1, |dir| {
+            dir == std::path::Path::new("/srv/site/src")
+        });
+
+        assert!(report.dry_run);
+        assert!(report.exceeds_deletion_limit);
+        assert_eq!(report.totals.uploads, 3);
+        assert_eq!(report.totals.upload_bytes, 12 + 5 + 9);
+        assert_eq!(report.totals.downloads, 1);
+        assert_eq!(report.totals.download_bytes, 14);
+        assert_eq!(report.totals.deletes, 2);
+        assert_eq!(report.totals.delete_bytes, 20);
+        assert_eq!(report.deletes[0].side, Side::Remote);
+        assert_eq!(report.deletes[0].path, "deleted_locally.rs");
+        assert_eq!(report.deletes[1].side, Side::Local);
+
+        let mkdirs: Vec<_> = report
+            .mkdirs
+            .iter()
+            .map(|dir| (dir.side, dir.path.display().to_string()))
+            .collect();
+        assert_eq!(
+            mkdirs,
+            vec![(Side::Remote, "/srv/site/src/new".to_string())]
+        );
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["uploads"][0]["reason"], "missing-remote");
+        assert_eq!(json["deletes"][0]["side"], "remote");
+    }
+
+    #[test]
+    fn test_conflict_copy_path() {
+        let timestamp = DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap();
+        assert_eq!(
+            conflict_copy_path(&PathBuf::from("/p/src/index.php"), timestamp),
+            PathBuf::from("/p/src/index.remote-conflict-20231114-221320.php")
+        );
+        assert_eq!(
+            conflict_copy_path(&PathBuf::from("/p/Makefile"), timestamp),
+            PathBuf::from("/p/Makefile.remote-conflict-20231114-221320")
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use crate::background::TaskStatus;
+use crate::error::AstraResult;
+use serde::Serialize;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Minimum time between two [`ProgressEvent::BytesTransferred`] events of one file
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What a transfer reports while it runs.
+///
+/// File events name the local path of the file in both directions. Events of a background task
+/// carry its id; events of a foreground command have none.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    FileStarted {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        task_id: Option<String>,
+        path: PathBuf,
+        /// Size of the source file, when it could be determined up front
+        total_bytes: Option<u64>,
+    },
+    BytesTransferred {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        task_id: Option<String>,
+        path: PathBuf,
+        /// Bytes at the target so far, including any resumed prefix
+        bytes: u64,
+        total_bytes: Option<u64>,
+        /// Time since the file started, for rates and ETAs
+        elapsed_ms: u64,
+        /// Bytes a resumed transfer found already at the target (not sent in `elapsed_ms`)
+        #[serde(skip_serializing_if = "is_zero")]
+        resumed_from: u64,
+    },
+    FileFinished {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        task_id: Option<String>,
+        path: PathBuf,
+        bytes: u64,
+        success: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    TaskState {
+        task_id: String,
+        status: TaskStatus,
+    },
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+type Sink = Arc<dyn Fn(ProgressEvent) + Send + Sync>;
+
+/// Where a session sends its [`ProgressEvent`]s.
+///
+/// Cheap to clone and shared like a [`CancelToken`](crate::transfer::CancelToken) by every
+/// session working on one command or task. The default reporter drops all events.
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    sink: Option<Sink>,
+    task_id: Option<String>,
+}
+
+impl fmt::Debug for ProgressReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressReporter")
+            .field("enabled", &self.is_enabled())
+            .field("task_id", &self.task_id)
+            .finish()
+    }
+}
+
+impl ProgressReporter {
+    pub fn new(sink: impl Fn(ProgressEvent) + Send + Sync + 'static) -> Self {
+        Self {
+            sink: Some(Arc::new(sink)),
+            task_id: None,
+        }
+    }
+
+    /// Write every event as one JSON line on stdout
+    pub fn ndjson() -> Self {
+        Self::new(|event| {
+            if let Ok(line) = serde_json::to_string(&event) {
+                let mut stdout = std::io::stdout().lock();
+                let _ = writeln!(stdout, "{}", line);
+                let _ = stdout.flush();
+            }
+        })
+    }
+
+    /// The same reporter, tagging file events with `task_id`
+    pub fn for_task(mut self, task_id: &str) -> Self {
+        self.task_id = Some(task_id.to_string());
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    pub fn report(&self, event: ProgressEvent) {
+        if let Some(sink) = &self.sink {
+            sink(event);
+        }
+    }
+
+    /// Start tracking the transfer of the file at `path`
+    pub fn file(&self, path: &Path) -> FileProgress<'_> {
+        FileProgress {
+            reporter: self,
+            path: path.to_path_buf(),
+            total_bytes: None,
+            bytes: 0,
+            resumed_from: 0,
+            started: Instant::now(),
+            last_report: None,
+        }
+    }
+}
+
+/// Progress of one file, reporting transferred bytes at most every [`PROGRESS_INTERVAL`]
+pub struct FileProgress<'a> {
+    reporter: &'a ProgressReporter,
+    path: PathBuf,
+    total_bytes: Option<u64>,
+    bytes: u64,
+    resumed_from: u64,
+    started: Instant,
+    last_report: Option<Instant>,
+}
+
+impl FileProgress<'_> {
+    /// The source is open and its size known (if at all); announces the file
+    pub fn start(&mut self, total_bytes: Option<u64>) {
+        self.total_bytes = total_bytes;
+        self.started = Instant::now();
+        self.reporter.report(ProgressEvent::FileStarted {
+            task_id: self.reporter.task_id.clone(),
+            path: self.path.clone(),
+            total_bytes,
+        });
+    }
+
+    /// The transfer continues after `offset` bytes already at the target
+    pub fn resume(&mut self, offset: u64) {
+        self.bytes = offset;
+        self.resumed_from = offset;
+    }
+
+    /// Count `bytes` more as written to the target
+    pub fn advance(&mut self, bytes: u64) {
+        self.bytes += bytes;
+        if !self.reporter.is_enabled()
+            || self
+                .last_report
+                .is_some_and(|last| last.elapsed() < PROGRESS_INTERVAL)
+        {
+            return;
+        }
+
+        self.last_report = Some(Instant::now());
+        self.reporter.report(ProgressEvent::BytesTransferred {
+            task_id: self.reporter.task_id.clone(),
+            path: self.path.clone(),
+            bytes: self.bytes,
+            total_bytes: self.total_bytes,
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            resumed_from: self.resumed_from,
+        });
+    }
+
+    /// Report how the transfer ended
+    pub fn finish<T>(self, outcome: &AstraResult<T>) {
+        self.reporter.report(ProgressEvent::FileFinished {
+            task_id: self.reporter.task_id.clone(),
+            path: self.path,
+            bytes: self.bytes,
+            success: outcome.is_ok(),
+            error: outcome.as_ref().err().map(|e| e.to_string()),
+        });
+    }
+}
@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests {
+    use crate::background::TaskStatus;
+    use crate::error::{AstraError, AstraResult};
+    use crate::progress::{ProgressEvent, ProgressReporter};
+    use serde_json::json;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+
+    fn recording() -> (ProgressReporter, Arc<Mutex<Vec<ProgressEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let reporter = ProgressReporter::new(move |event| sink.lock().unwrap().push(event));
+        (reporter, events)
+    }
+
+    #[test]
+    fn test_events_serialize_as_tagged_lines() {
+        let started = ProgressEvent::FileStarted {
+            task_id: None,
+            path: PathBuf::from("src/app.lua"),
+            total_bytes: Some(42),
+        };
+        assert_eq!(
+            serde_json::to_value(&started).unwrap(),
+            json!({ "event": "file_started", "path": "src/app.lua", "total_bytes": 42 })
+        );
+
+        let state = ProgressEvent::TaskState {
+            task_id: "t1".to_string(),
+            status: TaskStatus::Running,
+        };
+        assert_eq!(
+            serde_json::to_value(&state).unwrap(),
+            json!({ "event": "task_state", "task_id": "t1", "status": "Running" })
+        );
+    }
+
+    #[test]
+    fn test_file_progress_is_throttled_and_finished() {
+        let (reporter, events) = recording();
+        let reporter = reporter.for_task("t1");
+
+        let mut progress = reporter.file(Path::new("big.bin"));
+        progress.start(Some(3000));
+        for _ in 0..3 {
+            progress.advance(1000);
+        }
+        progress.finish(&AstraResult::Ok(3000u64));
+
+        let events = events.lock().unwrap();
+        // Started, one throttled byte count, finished
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            &events[1],
+            ProgressEvent::BytesTransferred {
+                bytes: 1000,
+                total_bytes: Some(3000),
+                ..
+            }
+        ));
+        assert_eq!(
+            events[2],
+            ProgressEvent::FileFinished {
+                task_id: Some("t1".to_string()),
+                path: PathBuf::from("big.bin"),
+                bytes: 3000,
+                success: true,
+                error: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_failed_file_reports_its_error() {
+        let (reporter, events) = recording();
+
+        let progress = reporter.file(Path::new("gone.txt"));
+        progress.finish::<u64>(&Err(AstraError::Cancelled));
+
+        let events = events.lock().unwrap();
+        assert!(matches!(
+            &events[..],
+            [ProgressEvent::FileFinished { success: false, error: Some(error), .. }]
+                if error == "Operation cancelled"
+        ));
+        assert!(!ProgressReporter::default().is_enabled());
+    }
+}
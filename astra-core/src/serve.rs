@@ -0,0 +1,663 @@
+use crate::background::{self, BackgroundTask, TaskManager, TaskType};
+use crate::cli::{self, RootOverrides, SyncOverrides};
+use crate::error::{AstraError, AstraResult};
+use crate::output::Output;
+use crate::progress::ProgressReporter;
+use crate::sftp::SftpClient;
+use crate::types::{SftpConfig, SyncMode};
+use crate::version;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+/// JSON-RPC 2.0 error codes
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// The command itself failed; `data` carries the error `kind` and the CLI `exit_code`
+pub const COMMAND_FAILED: i64 = -32000;
+
+/// How long a warm session may sit unused before it is closed
+pub const IDLE_SESSION_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// One JSON-RPC request. Requests without an `id` are notifications and get no response.
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    #[serde(default)]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Response {
+    pub jsonrpc: &'static str,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl Response {
+    pub fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+/// A message the daemon sends on its own, without a request to answer
+#[derive(Debug, Serialize)]
+pub struct Notification<T: Serialize> {
+    pub jsonrpc: &'static str,
+    pub method: &'static str,
+    pub params: T,
+}
+
+impl<T: Serialize> Notification<T> {
+    pub fn new(method: &'static str, params: T) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            method,
+            params,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<AstraError> for RpcError {
+    fn from(error: AstraError) -> Self {
+        Self {
+            code: COMMAND_FAILED,
+            message: error.to_string(),
+            data: Some(json!({
+                "kind": error.kind(),
+                "exit_code": error.exit_code(),
+            })),
+        }
+    }
+}
+
+/// Result of `upload`, `download` and `sync`: the same `data` as `--output json`, plus the exit
+/// code the CLI would have finished with
+#[derive(Debug, Serialize)]
+struct CommandOutcome<T: Serialize> {
+    ok: bool,
+    exit_code: i32,
+    data: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransferParams {
+    #[serde(default)]
+    config: Option<String>,
+    local: String,
+    remote: String,
+    #[serde(default)]
+    recursive: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SyncParams {
+    config: Option<String>,
+    mode: Option<String>,
+    local: Option<String>,
+    remote: Option<String>,
+    conflict_policy: Option<String>,
+    max_deletions: Option<usize>,
+    on_error: Option<String>,
+    dry_run: bool,
+    files: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct StatusParams {
+    config: Option<String>,
+    local: Option<String>,
+    remote: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmitTaskParams {
+    task_type: TaskType,
+    #[serde(default)]
+    config: Option<String>,
+    #[serde(default)]
+    local: Option<String>,
+    #[serde(default)]
+    remote: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TaskIdParams {
+    id: String,
+}
+
+/// A direct `upload`, `download` or `sync` request's place in line.
+///
+/// Requests are handled concurrently, so two saves sent back to back could otherwise reach their
+/// project lock in either order. Each one waits for `previous` before asking for the lock and
+/// fires `admitted` once it holds it, so the lock is taken in the order the requests arrived.
+pub struct Turn {
+    previous: Option<oneshot::Receiver<()>>,
+    admitted: oneshot::Sender<()>,
+}
+
+impl Turn {
+    /// The turn after `last`, which becomes the one the next request waits for
+    pub fn next(last: &mut Option<oneshot::Receiver<()>>) -> Self {
+        let (admitted, receiver) = oneshot::channel();
+        Self {
+            previous: last.replace(receiver),
+            admitted,
+        }
+    }
+}
+
+/// Authenticated sessions kept open between requests.
+///
+/// Sessions are keyed by the full configuration they were opened with, so editing the config
+/// (host, credentials, roots, filters) naturally opens fresh ones. A session unused for
+/// [`IDLE_SESSION_TTL`] is closed.
+#[derive(Default)]
+struct SessionCache {
+    idle: Mutex<HashMap<String, Vec<(SftpClient, Instant)>>>,
+}
+
+impl SessionCache {
+    fn key(config: &SftpConfig) -> String {
+        serde_json::to_string(config).unwrap_or_default()
+    }
+
+    /// Idle sessions for `config` that still answer, or a newly connected one
+    fn checkout(&self, config: &SftpConfig) -> AstraResult<Vec<SftpClient>> {
+        let idle = self
+            .idle
+            .lock()
+            .ok()
+            .and_then(|mut idle| idle.remove(&Self::key(config)))
+            .unwrap_or_default();
+
+        let warm: Vec<SftpClient> = idle
+            .into_iter()
+            .filter(|(_, since)| since.elapsed() < IDLE_SESSION_TTL)
+            .map(|(client, _)| client)
+            .filter(SftpClient::is_alive)
+            .collect();
+        if !warm.is_empty() {
+            debug!("Reusing {} warm session(s) for {}", warm.len(), config.host);
+            return Ok(warm);
+        }
+
+        info!("Opening a new session to {}", config.host);
+        Ok(vec![SftpClient::new(config.clone())?])
+    }
+
+    fn checkin(&self, config: &SftpConfig, sessions: Vec<SftpClient>) {
+        let now = Instant::now();
+        if let Ok(mut idle) = self.idle.lock() {
+            idle.entry(Self::key(config))
+                .or_default()
+                .extend(sessions.into_iter().map(|client| (client, now)));
+        }
+    }
+
+    /// Close the sessions idle for longer than [`IDLE_SESSION_TTL`]
+    fn evict_expired(&self) {
+        let expired: Vec<SftpClient> = match self.idle.lock() {
+            Ok(mut idle) => {
+                let mut expired = Vec::new();
+                for sessions in idle.values_mut() {
+                    let (stale, fresh) = std::mem::take(sessions)
+                        .into_iter()
+                        .partition(|(_, since)| since.elapsed() >= IDLE_SESSION_TTL);
+                    *sessions = fresh;
+                    expired.extend(stale.into_iter().map(|(client, _)| client));
+                }
+                idle.retain(|_, sessions| !sessions.is_empty());
+                expired
+            }
+            Err(_) => return,
+        };
+        if !expired.is_empty() {
+            debug!("Closing {} idle session(s)", expired.len());
+        }
+        // Disconnecting happens outside the lock
+        drop(expired);
+    }
+}
+
+/// Serves the CLI commands over JSON-RPC, keeping sessions warm between requests
+pub struct Server {
+    sessions: SessionCache,
+    tasks: TaskManager,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Server {
+    /// Must be called from within the tokio runtime, which runs the task manager
+    pub fn new() -> Self {
+        Self {
+            sessions: SessionCache::default(),
+            tasks: TaskManager::new(),
+        }
+    }
+
+    /// Answer one request. SFTP work runs on the blocking thread pool since ssh2 is synchronous.
+    pub async fn handle(self: &Arc<Self>, method: &str, params: Value) -> Result<Value, RpcError> {
+        self.handle_in_turn(method, params, None).await
+    }
+
+    /// [`handle`](Self::handle), taking the project lock of a direct command in `turn`.
+    ///
+    /// `upload`, `download` and `sync` hold the lock of their project while they run, like a
+    /// queued task, so they never overlap a task or each other on the same project.
+    pub async fn handle_in_turn(
+        self: &Arc<Self>, method: &str, params: Value, turn: Option<Turn>,
+    ) -> Result<Value, RpcError> {
+        match method {
+            "ping" => Ok(json!({ "version": version::VERSION })),
+            "upload" | "download" | "sync" => {
+                let (previous, admitted) = match turn {
+                    Some(turn) => (turn.previous, Some(turn.admitted)),
+                    None => (None, None),
+                };
+                if let Some(previous) = previous {
+                    // Dropped without firing means that request failed early; go ahead anyway
+                    let _ = previous.await;
+                }
+
+                let project = {
+                    let method = method.to_string();
+                    let params = params.clone();
+                    tokio::task::spawn_blocking(move || command_project(&method, &params))
+                        .await
+                        .map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))??
+                };
+                let running = self.tasks.lock_project(&project).await;
+                if let Some(admitted) = admitted {
+                    let _ = admitted.send(());
+                }
+
+                let server = Arc::clone(self);
+                let method = method.to_string();
+                tokio::task::spawn_blocking(move || {
+                    let _running = running;
+                    server.run_command(&method, params)
+                })
+                .await
+                .map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))?
+            }
+            "status" => {
+                let server = Arc::clone(self);
+                let method = method.to_string();
+                tokio::task::spawn_blocking(move || server.run_command(&method, params))
+                    .await
+                    .map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))?
+            }
+            "task.submit" => {
+                let params: SubmitTaskParams = parse_params(params)?;
+                let (config_path, local, remote) = (
+                    params.config.clone(),
+                    params.local.clone(),
+                    params.remote.clone(),
+                );
+                let config = tokio::task::spawn_blocking(move || {
+                    cli::load_config(
+                        config_path.as_deref(),
+                        RootOverrides {
+                            local: local.as_deref(),
+                            remote: remote.as_deref(),
+                        },
+                    )
+                })
+                .await
+                .map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))??;
+                let task = BackgroundTask::new(params.task_type, config, params.config)
+                    .with_roots(params.local, params.remote);
+                let id = self.tasks.submit_task(task).await?;
+                Ok(json!({ "id": id }))
+            }
+            "task.get" => {
+                let params: TaskIdParams = parse_params(params)?;
+                match self.tasks.get_task_status(&params.id).await {
+                    Some(task) => to_value(&task),
+                    None => {
+                        Err(AstraError::TaskError(format!("Task not found: {}", params.id)).into())
+                    }
+                }
+            }
+            "task.list" => to_value(&self.tasks.get_all_tasks().await),
+            "task.cancel" => {
+                let params: TaskIdParams = parse_params(params)?;
+                let status = self.tasks.cancel_task(&params.id).await?;
+                Ok(json!({ "id": params.id, "status": status }))
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method: {}", method),
+            )),
+        }
+    }
+
+    fn run_command(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let output = Output::quiet();
+
+        match method {
+            "upload" | "download" => {
+                let params: TransferParams = parse_params(params)?;
+                let config = cli::load_config(params.config.as_deref(), RootOverrides::default())?;
+                let (report, exit_code) = self.with_sessions(&config, |sessions| {
+                    if method == "upload" {
+                        cli::upload_path(
+                            sessions,
+                            &params.local,
+                            &params.remote,
+                            params.recursive,
+                            output,
+                        )
+                    } else {
+                        cli::download_path(
+                            sessions,
+                            &params.remote,
+                            &params.local,
+                            params.recursive,
+                            output,
+                        )
+                    }
+                })?;
+                to_value(&CommandOutcome {
+                    ok: report.ok(),
+                    exit_code,
+                    data: report,
+                })
+            }
+            "sync" => {
+                let params: SyncParams = parse_params(params)?;
+                let mode: SyncMode = params.mode.as_deref().unwrap_or("upload").parse()?;
+                let config = cli::load_sync_config(
+                    params.config.as_deref(),
+                    RootOverrides {
+                        local: params.local.as_deref(),
+                        remote: params.remote.as_deref(),
+                    },
+                    SyncOverrides {
+                        conflict_policy: params.conflict_policy.as_deref(),
+                        max_deletions: params.max_deletions,
+                        on_error: params.on_error.as_deref(),
+                        dry_run: params.dry_run,
+                    },
+                )?;
+                let (report, exit_code) = self.with_sessions(&config, |sessions| {
+                    cli::run_sync(sessions, mode, &params.files, params.dry_run, output)
+                })?;
+                to_value(&CommandOutcome {
+                    ok: report.ok(),
+                    exit_code,
+                    data: report,
+                })
+            }
+            "status" => {
+                let params: StatusParams = parse_params(params)?;
+                let config = cli::load_config(
+                    params.config.as_deref(),
+                    RootOverrides {
+                        local: params.local.as_deref(),
+                        remote: params.remote.as_deref(),
+                    },
+                )?;
+                let report =
+                    self.with_sessions(&config, |sessions| cli::status_report(&sessions[0]))?;
+                to_value(&report)
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method: {}", method),
+            )),
+        }
+    }
+
+    /// Run `command` on warm sessions for `config` and keep them for the next request, unless
+    /// the command failed in a way that may have broken the connection
+    fn with_sessions<T>(
+        &self, config: &SftpConfig, command: impl FnOnce(&mut Vec<SftpClient>) -> AstraResult<T>,
+    ) -> AstraResult<T> {
+        cli::apply_verbose_logging(config);
+        let mut sessions = self.sessions.checkout(config)?;
+        // File progress goes out as `progress` notifications, like that of a task
+        for session in &mut sessions {
+            session.set_progress(self.tasks.reporter());
+        }
+        let result = command(&mut sessions);
+        for session in &mut sessions {
+            session.set_progress(ProgressReporter::default());
+        }
+
+        match &result {
+            Err(
+                e @ (AstraError::SftpConnectionError(_)
+                | AstraError::ConnectionInterrupted(_)
+                | AstraError::Ssh2Error(_)),
+            ) => {
+                warn!("Dropping {} session(s) after error: {}", sessions.len(), e);
+            }
+            _ => self.sessions.checkin(config, sessions),
+        }
+        result
+    }
+}
+
+/// The project a direct command works on, as [`background::task_project`] names it
+fn command_project(method: &str, params: &Value) -> Result<String, RpcError> {
+    // Only `sync` takes root overrides; the `local` of a transfer is the file itself
+    let (config_path, local, remote) = match method {
+        "sync" => {
+            let params: SyncParams = parse_params(params.clone())?;
+            (params.config, params.local, params.remote)
+        }
+        _ => {
+            let params: TransferParams = parse_params(params.clone())?;
+            (params.config, None, None)
+        }
+    };
+    let config = cli::load_config(
+        config_path.as_deref(),
+        RootOverrides {
+            local: local.as_deref(),
+            remote: remote.as_deref(),
+        },
+    )?;
+    Ok(background::task_project(&config))
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() {
+        Value::Object(Default::default())
+    } else {
+        params
+    };
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn to_value<T: Serialize>(value: &T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+}
+
+/// Parse one line of input into a request, or the error response to send back
+pub fn parse_request(line: &str) -> Result<Request, Box<Response>> {
+    let value: Value = serde_json::from_str(line).map_err(|e| {
+        Box::new(Response::new(
+            Value::Null,
+            Err(RpcError::new(PARSE_ERROR, e.to_string())),
+        ))
+    })?;
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    serde_json::from_value(value).map_err(|e| {
+        Box::new(Response::new(
+            id,
+            Err(RpcError::new(INVALID_REQUEST, e.to_string())),
+        ))
+    })
+}
+
+/// Run the daemon: newline-delimited JSON-RPC 2.0 on stdin/stdout until `shutdown` or EOF.
+///
+/// Requests are handled concurrently and answered as they finish, so responses may arrive out
+/// of order; match them by `id`. Logs go to stderr as usual.
+pub async fn serve() -> AstraResult<()> {
+    let server = Arc::new(Server::new());
+    let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(line) = receiver.recv().await {
+            let written = async {
+                stdout.write_all(line.as_bytes()).await?;
+                stdout.write_all(b"\n").await?;
+                stdout.flush().await
+            };
+            if let Err(e) = written.await {
+                warn!("Failed to write response: {}", e);
+                break;
+            }
+        }
+    });
+
+    // Pick up the tasks a previous daemon left behind in the project it was started in
+    if let Ok(Ok(config)) =
+        tokio::task::spawn_blocking(|| cli::load_config(None, RootOverrides::default())).await
+    {
+        let restored = server
+            .tasks
+            .load_project(&background::task_project(&config), true)
+            .await;
+        if restored > 0 {
+            info!("Restored {} journaled task(s)", restored);
+        }
+    }
+
+    // Close warm sessions nobody has used for a while
+    let sweeper = {
+        let server = Arc::clone(&server);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(IDLE_SESSION_TTL / 5);
+            loop {
+                interval.tick().await;
+                let server = Arc::clone(&server);
+                let _ = tokio::task::spawn_blocking(move || server.sessions.evict_expired()).await;
+            }
+        })
+    };
+
+    // Background task state changes and file progress, as `progress` notifications
+    let mut events = server.tasks.subscribe();
+    let progress_sender = sender.clone();
+    let forwarder = tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => send(&progress_sender, &Notification::new("progress", event)),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    debug!("Skipped {} progress events", skipped)
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+
+    info!("astra serve {} ready", version::VERSION);
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut last_turn = None;
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request = match parse_request(&line) {
+            Ok(request) => request,
+            Err(response) => {
+                send(&sender, &response);
+                continue;
+            }
+        };
+
+        if request.method == "shutdown" {
+            if let Some(id) = request.id {
+                send(&sender, &Response::new(id, Ok(Value::Null)));
+            }
+            break;
+        }
+
+        let turn = match request.method.as_str() {
+            "upload" | "download" | "sync" => Some(Turn::next(&mut last_turn)),
+            _ => None,
+        };
+        let server = Arc::clone(&server);
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            let outcome = server
+                .handle_in_turn(&request.method, request.params, turn)
+                .await;
+            if let Some(id) = request.id {
+                send(&sender, &Response::new(id, outcome));
+            }
+        });
+    }
+
+    // The writer finishes once every in-flight request has sent its response
+    forwarder.abort();
+    sweeper.abort();
+    drop(sender);
+    let _ = writer.await;
+    info!("astra serve stopped");
+    Ok(())
+}
+
+fn send(sender: &mpsc::UnboundedSender<String>, message: &impl Serialize) {
+    match serde_json::to_string(message) {
+        Ok(line) => {
+            let _ = sender.send(line);
+        }
+        Err(e) => warn!("Failed to encode message: {}", e),
+    }
+}
@@ -0,0 +1,121 @@
+#[cfg(test)]
+mod tests {
+    use crate::error::AstraError;
+    use crate::serve::{
+        parse_request, Response, RpcError, Server, COMMAND_FAILED, INVALID_PARAMS, INVALID_REQUEST,
+        METHOD_NOT_FOUND, PARSE_ERROR,
+    };
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_request() {
+        let request =
+            parse_request(r#"{"jsonrpc":"2.0","id":7,"method":"status","params":{}}"#).unwrap();
+        assert_eq!(request.id, Some(json!(7)));
+        assert_eq!(request.method, "status");
+
+        let notification = parse_request(r#"{"jsonrpc":"2.0","method":"ping"}"#).unwrap();
+        assert!(notification.id.is_none());
+        assert!(notification.params.is_null());
+
+        let response = parse_request("{not json").unwrap_err();
+        assert_eq!(response.error.unwrap().code, PARSE_ERROR);
+
+        let response = parse_request(r#"{"jsonrpc":"2.0","id":3}"#).unwrap_err();
+        assert_eq!(response.id, json!(3));
+        assert_eq!(response.error.unwrap().code, INVALID_REQUEST);
+    }
+
+    #[test]
+    fn test_response_shape() {
+        let ok: Value = serde_json::to_value(Response::new(json!(1), Ok(Value::Null))).unwrap();
+        assert_eq!(ok, json!({ "jsonrpc": "2.0", "id": 1, "result": null }));
+
+        let error = RpcError::from(AstraError::ConfigurationError("missing host".to_string()));
+        let failed: Value = serde_json::to_value(Response::new(json!(2), Err(error))).unwrap();
+        assert_eq!(failed["error"]["code"], COMMAND_FAILED);
+        assert_eq!(failed["error"]["data"]["kind"], "ConfigurationError");
+        assert_eq!(failed["error"]["data"]["exit_code"], 3);
+        assert!(failed.get("result").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_server_dispatch() {
+        let server = Arc::new(Server::new());
+
+        let pong = server.handle("ping", Value::Null).await.unwrap();
+        assert_eq!(pong["version"], crate::version::VERSION);
+
+        let unknown = server.handle("reboot", Value::Null).await.unwrap_err();
+        assert_eq!(unknown.code, METHOD_NOT_FOUND);
+
+        let missing_paths = server.handle("upload", json!({})).await.unwrap_err();
+        assert_eq!(missing_paths.code, INVALID_PARAMS);
+
+        let missing_task = server
+            .handle("task.get", json!({ "id": "nope" }))
+            .await
+            .unwrap_err();
+        assert_eq!(missing_task.code, COMMAND_FAILED);
+        assert_eq!(missing_task.data.unwrap()["kind"], "TaskError");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_task_records_real_outcome() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join(".astra-settings")).unwrap();
+        fs::write(
+            temp_dir.path().join(".astra-settings/settings.toml"),
+            format!(
+                r#"
+[sftp]
+host = "127.0.0.1"
+port = 1
+username = "deploy"
+password = "secret"
+local_path = "{}"
+remote_path = "/srv/site"
+"#,
+                temp_dir.path().display()
+            ),
+        )
+        .unwrap();
+        let server = Arc::new(Server::new());
+
+        let submitted = server
+            .handle(
+                "task.submit",
+                json!({
+                    "config": temp_dir.path().to_string_lossy(),
+                    "task_type": {
+                        "FileUpload": { "local_path": "a.txt", "remote_path": "/srv/site/a.txt" }
+                    },
+                }),
+            )
+            .await
+            .unwrap();
+        let id = submitted["id"].as_str().unwrap().to_string();
+
+        // Nothing listens on port 1, so the task must fail instead of reporting a fake success
+        let mut task = Value::Null;
+        for _ in 0..100 {
+            task = server
+                .handle("task.get", json!({ "id": id }))
+                .await
+                .unwrap();
+            if task["status"].get("Failed").is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert!(task["status"].get("Failed").is_some(), "task: {}", task);
+        assert!(task.get("config").is_none());
+        assert!(!task.to_string().contains("secret"));
+
+        let tasks = server.handle("task.list", Value::Null).await.unwrap();
+        assert_eq!(tasks.as_array().unwrap().len(), 1);
+    }
+}
@@ -1,87 +1,428 @@
-use crate::error::{AstraError, AstraResult};
-use crate::types::{FileStatus, OperationType, SftpConfig, SyncOperation};
+use crate::auth;
+use crate::error::{is_transient_io, AstraError, AstraResult};
+use crate::filter::PathFilter;
+use crate::hostkey;
+use crate::paths::{
+    is_temp_file, is_temp_of, remote_trash_dir, temp_path, to_posix, PathMapper, TEMP_SUFFIX,
+};
+use crate::planner::{self, SyncPlan};
+use crate::progress::{FileProgress, ProgressReporter};
+use crate::state::SyncState;
+use crate::transfer::{
+    range_hash, verify_window, CancelToken, ResumePolicy, RetryPolicy, TempCandidate,
+};
+use crate::types::{
+    ErrorPolicy, FileResult, FileStatus, OperationType, SftpConfig, SyncMode, SyncOperation,
+};
 use chrono::{DateTime, Utc};
-use ssh2::Session;
+use libssh2_sys as raw;
+use ssh2::{ErrorCode, FileStat, OpenFlags, OpenType, RenameFlags, Session, Sftp};
 use std::collections::HashMap;
+use std::ffi::{c_char, c_int};
 use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{TcpStream, ToSocketAddrs};
 use std::path::{Path, PathBuf};
-use tracing::info;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
 use walkdir::WalkDir;
 
+/// Maximum directory depth followed when walking the remote tree
+pub const MAX_REMOTE_DEPTH: usize = 64;
+/// How long [`SftpClient::is_alive`] waits for an answer, however long `operation_timeout` is
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// SFTP status codes for a path that does not exist or may not be read
+const SFTP_NO_SUCH_FILE: i32 = 2;
+const SFTP_PERMISSION_DENIED: i32 = 3;
+
+/// Files found on both sides, keyed by path relative to the sync roots
+#[derive(Debug, Default)]
+pub struct TreeScan {
+    pub local: HashMap<PathBuf, FileStatus>,
+    pub remote: HashMap<PathBuf, FileStatus>,
+    /// Remote paths whose contents could not be listed, see [`RemoteListing::unlisted`]
+    pub unlisted: Vec<PathBuf>,
+    /// The remote root does not exist
+    pub root_missing: bool,
+}
+
+/// What a walk of the remote tree found
+#[derive(Debug, Default)]
+pub struct RemoteListing {
+    pub files: Vec<FileStatus>,
+    /// Paths relative to the walked root whose contents are unknown: directories the server
+    /// refused to read or that lie beyond [`MAX_REMOTE_DEPTH`], and unreadable symlinks. Their
+    /// files are missing from `files` without having been deleted.
+    pub unlisted: Vec<PathBuf>,
+    /// The walked root does not exist, so `files` is empty. See
+    /// [`planner::check_remote_root`] for why that is not simply an empty tree.
+    pub root_missing: bool,
+}
+
 pub struct SftpClient {
     session: Session,
+    sftp: Sftp,
     config: SftpConfig,
+    filter: PathFilter,
+    cancel: CancelToken,
+    progress: ProgressReporter,
+    /// Temp files failed transfers of this session left, by direction and target, so a retry
+    /// continues its own earlier attempt
+    partial: Mutex<HashMap<(OperationType, PathBuf), PathBuf>>,
+    /// Channel for [`posix_rename`](Self::posix_rename), opened on first use
+    raw_sftp: Mutex<Option<RawSftp>>,
 }
 
+/// Size of the chunks files are copied in; cancellation is checked between chunks
+const COPY_CHUNK_SIZE: usize = 256 * 1024;
+
 impl SftpClient {
     pub fn new(config: SftpConfig) -> AstraResult<Self> {
-        let tcp = std::net::TcpStream::connect(format!("{}:{}", config.host, config.port))
-            .map_err(|e| AstraError::SftpConnectionError(e.to_string()))?;
+        let tcp = connect_tcp(&config)?;
 
         let mut session =
             Session::new().map_err(|e| AstraError::SftpConnectionError(e.to_string()))?;
 
-        session.set_tcp_stream(tcp);
-        session
-            .handshake()
-            .map_err(|e| AstraError::SftpConnectionError(e.to_string()))?;
-
-        if let Some(private_key_path) = &config.private_key_path {
-            session
-                .userauth_pubkey_file(&config.username, None, Path::new(private_key_path), None)
-                .map_err(|e| AstraError::AuthenticationError(e.to_string()))?;
-        } else if let Some(password) = &config.password {
-            session
-                .userauth_password(&config.username, password)
-                .map_err(|e| AstraError::AuthenticationError(e.to_string()))?;
-        } else {
-            return Err(AstraError::AuthenticationError(
-                "Either password or private key must be provided".to_string(),
-            ));
+        // Set before the handshake so a server that stops responding cannot block us forever
+        let operation_timeout = config.advanced.operation_timeout;
+        if operation_timeout > 0 {
+            let millis = operation_timeout.saturating_mul(1000).min(u32::MAX as u64) as u32;
+            session.set_timeout(millis);
         }
 
-        if !session.authenticated() {
-            return Err(AstraError::AuthenticationError(
-                "Authentication failed".to_string(),
-            ));
+        session.set_tcp_stream(tcp);
+        session.handshake().map_err(AstraError::from_session)?;
+
+        let keepalive_interval = config.advanced.keepalive_interval;
+        if keepalive_interval > 0 {
+            session.set_keepalive(false, keepalive_interval.min(u32::MAX as u64) as u32);
         }
 
+        hostkey::verify_host_key(&session, &config)?;
+
+        auth::authenticate(&session, &config)?;
+
+        let filter = PathFilter::new(&config.sync, Path::new(&config.local_path))?;
+
+        // One SFTP channel per session, reused for every operation
+        let sftp = session.sftp().map_err(AstraError::from_session)?;
+
         Ok(Self {
             session,
+            sftp,
             config,
+            filter,
+            cancel: CancelToken::default(),
+            progress: ProgressReporter::default(),
+            partial: Mutex::default(),
+            raw_sftp: Mutex::default(),
         })
     }
 
+    /// List the full remote tree below `remote_path`.
+    ///
+    /// Returned paths are relative to `remote_path`. Symlinks to files are reported with the
+    /// target's metadata, symlinked directories are listed but not descended into (matching the
+    /// local walk), and recursion stops at [`MAX_REMOTE_DEPTH`]. Paths rejected by the sync
+    /// filter are skipped, as are the contents of excluded directories.
     pub fn get_remote_files(&self, remote_path: &Path) -> AstraResult<Vec<FileStatus>> {
-        let sftp = self
-            .session
-            .sftp()
-            .map_err(|e| AstraError::SftpConnectionError(e.to_string()))?;
+        self.list_remote_files(remote_path, Path::new(""))
+    }
 
-        let mut files = Vec::new();
+    /// Like [`get_remote_files`](Self::get_remote_files), but matches each path against the
+    /// filter as `filter_prefix/<relative path>`, so rules anchored at the sync root still apply
+    /// when listing one of its subdirectories. Parts of the tree that could not be listed are
+    /// only logged.
+    pub fn list_remote_files(
+        &self, remote_path: &Path, filter_prefix: &Path,
+    ) -> AstraResult<Vec<FileStatus>> {
+        let listing = self.walk_remote_tree(remote_path, filter_prefix)?;
+        for path in &listing.unlisted {
+            warn!(
+                "Skipped {} which could not be listed",
+                remote_path.join(path).display()
+            );
+        }
+        Ok(listing.files)
+    }
+
+    /// Walk the remote tree below `remote_path` like
+    /// [`list_remote_files`](Self::list_remote_files), reporting what could not be listed.
+    ///
+    /// A missing root lists nothing and sets [`RemoteListing::root_missing`]. Directories the
+    /// server refuses to read are recorded in
+    /// [`RemoteListing::unlisted`]; any other failure, such as a lost connection, fails the walk
+    /// so a partial listing is never mistaken for the whole tree.
+    pub fn walk_remote_tree(
+        &self, remote_path: &Path, filter_prefix: &Path,
+    ) -> AstraResult<RemoteListing> {
+        let sftp = self.sftp()?;
+
+        let mut listing = RemoteListing::default();
+
+        match sftp.stat(remote_path) {
+            Ok(stat) if stat.is_dir() => {}
+            Ok(_) => {
+                return Err(AstraError::FileOperationError(format!(
+                    "Remote root {} is not a directory",
+                    remote_path.display()
+                )))
+            }
+            Err(e) if sftp_status(&e) == Some(SFTP_NO_SUCH_FILE) => {
+                listing.root_missing = true;
+                return Ok(listing);
+            }
+            Err(e) => return Err(listing_error(remote_path, e)),
+        }
+
+        Self::walk_remote_dir(
+            sftp,
+            &self.filter,
+            filter_prefix,
+            remote_path,
+            Path::new(""),
+            0,
+            &mut listing,
+        )?;
 
-        let readdir = match sftp.readdir(remote_path) {
-            Ok(files) => files,
-            Err(_) => return Ok(vec![]),
+        Ok(listing)
+    }
+
+    fn walk_remote_dir(
+        sftp: &Sftp, filter: &PathFilter, filter_prefix: &Path, root: &Path, relative_dir: &Path,
+        depth: usize, listing: &mut RemoteListing,
+    ) -> AstraResult<()> {
+        if depth > MAX_REMOTE_DEPTH {
+            warn!(
+                "Remote directory {} exceeds max depth {}, skipping",
+                root.join(relative_dir).display(),
+                MAX_REMOTE_DEPTH
+            );
+            listing.unlisted.push(relative_dir.to_path_buf());
+            return Ok(());
+        }
+
+        let dir = root.join(relative_dir);
+        let entries = match sftp.readdir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if is_unreadable(&e) => {
+                warn!("Failed to read remote directory {}: {}", dir.display(), e);
+                listing.unlisted.push(relative_dir.to_path_buf());
+                return Ok(());
+            }
+            Err(e) => return Err(listing_error(&dir, e)),
         };
 
-        for (path, stat) in readdir {
-            let file_status = FileStatus {
-                path: path.to_path_buf(),
-                size: stat.size.unwrap_or(0),
+        for (path, stat) in entries {
+            let name = match path.file_name() {
+                Some(name) => name,
+                None => continue,
+            };
+            let relative_path = relative_dir.join(name);
+
+            let (stat, is_symlink) = if stat.file_type().is_symlink() {
+                match sftp.stat(&path) {
+                    Ok(target) => (target, true),
+                    Err(e) if sftp_status(&e) == Some(SFTP_NO_SUCH_FILE) => {
+                        warn!("Skipping dangling remote symlink {}: {}", path.display(), e);
+                        continue;
+                    }
+                    Err(e) if is_unreadable(&e) => {
+                        warn!("Failed to follow remote symlink {}: {}", path.display(), e);
+                        listing.unlisted.push(relative_path);
+                        continue;
+                    }
+                    Err(e) => return Err(listing_error(&path, e)),
+                }
+            } else {
+                (stat, false)
+            };
+
+            if filter.is_excluded(&filter_prefix.join(&relative_path), stat.is_dir()) {
+                continue;
+            }
+
+            listing.files.push(FileStatus {
+                path: relative_path.clone(),
+                size: if stat.is_dir() {
+                    0
+                } else {
+                    stat.size.unwrap_or(0)
+                },
                 modified: DateTime::from_timestamp(stat.mtime.unwrap_or(0) as i64, 0)
                     .unwrap_or_else(Utc::now),
                 is_directory: stat.is_dir(),
                 checksum: None,
+            });
+
+            if stat.is_dir() && !is_symlink {
+                Self::walk_remote_dir(
+                    sftp,
+                    filter,
+                    filter_prefix,
+                    root,
+                    &relative_path,
+                    depth + 1,
+                    listing,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The session's SFTP channel, sending a keepalive first if one is due
+    fn sftp(&self) -> AstraResult<&Sftp> {
+        if self.config.advanced.keepalive_interval > 0 {
+            self.session
+                .keepalive_send()
+                .map_err(AstraError::from_session)?;
+        }
+
+        Ok(&self.sftp)
+    }
+
+    /// Whether the session still answers, checked with one SFTP round trip that may take at
+    /// most [`PROBE_TIMEOUT`], so a half-dead link is given up on quickly
+    pub fn is_alive(&self) -> bool {
+        let timeout = self.session.timeout();
+        self.session.set_timeout(PROBE_TIMEOUT.as_millis() as u32);
+        let alive = self.sftp.realpath(Path::new(".")).is_ok();
+        self.session.set_timeout(timeout);
+        alive
+    }
+
+    /// Make transfers on this session stop at the next chunk once `token` is cancelled
+    pub fn set_cancel_token(&mut self, token: CancelToken) {
+        self.cancel = token;
+    }
+
+    pub fn cancel_token(&self) -> &CancelToken {
+        &self.cancel
+    }
+
+    /// Replace the session with a new connection to the same server, keeping the cancel token
+    /// and the progress reporter
+    pub fn reconnect(&mut self) -> AstraResult<()> {
+        let fresh = SftpClient::new(self.config.clone())?;
+        // The old channel belongs to the old session
+        self.raw_sftp = Mutex::default();
+        self.session = fresh.session;
+        self.sftp = fresh.sftp;
+        Ok(())
+    }
+
+    /// Run `attempt`, retrying transient failures with exponential backoff on a reconnected
+    /// session, as configured by `max_retries` and `retry_delay` in `[advanced]`.
+    ///
+    /// Returns the last outcome and the number of attempts made (0 if cancelled before the
+    /// first). A failure is transient if [`AstraError::is_transient`] says so, or if the session
+    /// stopped answering while it happened.
+    pub fn retry<T>(
+        &mut self, what: &Path, mut attempt: impl FnMut(&Self) -> AstraResult<T>,
+    ) -> (AstraResult<T>, u32) {
+        let policy = RetryPolicy::from_config(&self.config.advanced);
+        let mut attempts = 0;
+
+        loop {
+            if let Err(e) = self.cancel.check() {
+                return (Err(e), attempts);
+            }
+            attempts += 1;
+            let outcome = attempt(self);
+            let error = match &outcome {
+                Err(error)
+                    if attempts <= policy.max_retries && self.is_transient_failure(error) =>
+                {
+                    error
+                }
+                _ => return (outcome, attempts),
             };
 
-            files.push(file_status);
+            let delay = policy.delay(attempts);
+            warn!(
+                "{} failed ({}), retry {} of {} in {:?}",
+                what.display(),
+                error,
+                attempts,
+                policy.max_retries,
+                delay
+            );
+            if !self.cancel.sleep(delay) {
+                return (outcome, attempts);
+            }
+            if let Err(e) = self.reconnect() {
+                warn!("Failed to reconnect to {}: {}", self.config.host, e);
+            }
         }
+    }
 
-        Ok(files)
+    fn is_transient_failure(&self, error: &AstraError) -> bool {
+        error.is_transient()
+            // ssh2 reports a connection that broke mid-read or mid-write as a plain I/O error
+            || (matches!(
+                error,
+                AstraError::IoError(_) | AstraError::FileOperationError(_)
+            ) && !self.is_alive())
     }
 
+    /// Report the progress of file transfers on this session to `progress`
+    pub fn set_progress(&mut self, progress: ProgressReporter) {
+        self.progress = progress;
+    }
+
+    pub fn progress(&self) -> &ProgressReporter {
+        &self.progress
+    }
+
+    /// `std::io::copy` in [`COPY_CHUNK_SIZE`] chunks, stopping early if the transfer is cancelled
+    fn copy_chunks(
+        &self, reader: &mut impl Read, writer: &mut impl Write, progress: &mut FileProgress<'_>,
+    ) -> AstraResult<u64> {
+        let mut buffer = vec![0u8; COPY_CHUNK_SIZE];
+        let mut copied = 0u64;
+
+        loop {
+            self.cancel.check()?;
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => return Ok(copied),
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(AstraError::IoError(e)),
+            };
+            writer
+                .write_all(&buffer[..read])
+                .map_err(AstraError::IoError)?;
+            copied += read as u64;
+            progress.advance(read as u64);
+        }
+    }
+
+    pub fn config(&self) -> &SftpConfig {
+        &self.config
+    }
+
+    /// Include/exclude rules this client applies to its walks
+    pub fn filter(&self) -> &PathFilter {
+        &self.filter
+    }
+
+    /// List the files below `local_path`, skipping those rejected by the sync filter.
+    ///
+    /// Returned paths are relative to `local_path`, like those of
+    /// [`get_remote_files`](Self::get_remote_files).
     pub fn get_local_files(&self, local_path: &Path) -> AstraResult<Vec<FileStatus>> {
+        self.list_local_files(local_path, Path::new(""))
+    }
+
+    /// Like [`get_local_files`](Self::get_local_files), but matches each path against the filter
+    /// as `filter_prefix/<relative path>`, so rules anchored at the sync root still apply when
+    /// listing one of its subdirectories.
+    pub fn list_local_files(
+        &self, local_path: &Path, filter_prefix: &Path,
+    ) -> AstraResult<Vec<FileStatus>> {
         let mut files = Vec::new();
 
         if !local_path.exists() {
@@ -89,13 +430,20 @@ impl SftpClient {
         }
 
         for entry in WalkDir::new(local_path)
+            .min_depth(1)
             .into_iter()
-            .filter_entry(|e| e.path() != local_path)
+            .filter_entry(|e| {
+                let relative_path = e.path().strip_prefix(local_path).unwrap_or(e.path());
+                !self
+                    .filter
+                    .is_excluded(&filter_prefix.join(relative_path), e.file_type().is_dir())
+            })
         {
             let entry = entry.map_err(|e| AstraError::IoError(e.into()))?;
             let path = entry.path();
 
             if path.is_file() {
+                let relative_path = path.strip_prefix(local_path).unwrap_or(path);
                 let metadata = fs::metadata(path).map_err(AstraError::IoError)?;
 
                 let modified: DateTime<Utc> =
@@ -104,7 +452,7 @@ impl SftpClient {
                 let checksum = self.calculate_file_checksum(path)?;
 
                 let file_status = FileStatus {
-                    path: path.to_path_buf(),
+                    path: relative_path.to_path_buf(),
                     size: metadata.len(),
                     modified,
                     is_directory: false,
@@ -129,171 +477,986 @@ impl SftpClient {
         Ok(Some(format!("{:x}", result)))
     }
 
-    pub fn upload_file(&self, local_path: &Path, remote_path: &Path) -> AstraResult<()> {
+    /// Upload one file, returning the number of bytes written
+    pub fn upload_file(&self, local_path: &Path, remote_path: &Path) -> AstraResult<u64> {
         info!(
             "Uploading {} to {}",
             local_path.display(),
             remote_path.display()
         );
 
-        let sftp = self
-            .session
-            .sftp()
-            .map_err(|e| AstraError::SftpConnectionError(e.to_string()))?;
+        let mut progress = self.progress.file(local_path);
+        let outcome = self.write_remote_file(local_path, remote_path, &mut progress);
+        progress.finish(&outcome);
+        outcome
+    }
 
+    /// Write `local_path` into a temp file next to `remote_path` and rename it over the target
+    /// once complete, so the server never sees a half-written file. A failed transfer leaves the
+    /// temp file behind for a retry on this session to resume from.
+    fn write_remote_file(
+        &self, local_path: &Path, remote_path: &Path, progress: &mut FileProgress<'_>,
+    ) -> AstraResult<u64> {
         if let Some(parent) = remote_path.parent() {
-            match sftp.stat(parent) {
-                Ok(_) => {}
-                Err(_) => {
-                    self.create_remote_directory(parent)?;
+            self.create_remote_dir_all(parent)?;
+        }
+
+        let mut local_file = fs::File::open(local_path).map_err(AstraError::IoError)?;
+        let source_len = local_file.metadata().map_err(AstraError::IoError)?.len();
+        progress.start(Some(source_len));
+
+        let temp = self
+            .take_partial(OperationType::Upload, remote_path)
+            .unwrap_or_else(|| self.claim_remote_temp(remote_path, source_len));
+        let outcome = self
+            .write_remote_temp(&mut local_file, local_path, &temp, remote_path, progress)
+            .and_then(|bytes| {
+                self.replace_remote_file(&temp, remote_path)?;
+                Ok(bytes)
+            });
+        if outcome.is_err() {
+            self.keep_partial(OperationType::Upload, remote_path, temp);
+        }
+        outcome
+    }
+
+    fn write_remote_temp(
+        &self, local_file: &mut fs::File, local_path: &Path, temp: &Path, remote_path: &Path,
+        progress: &mut FileProgress<'_>,
+    ) -> AstraResult<u64> {
+        let sftp = self.sftp()?;
+        let source_len = local_file.metadata().map_err(AstraError::IoError)?.len();
+
+        let temp_len = sftp
+            .stat(temp)
+            .ok()
+            .filter(|stat| stat.is_file())
+            .and_then(|stat| stat.size);
+        let offset = ResumePolicy::from_config(&self.config.advanced).resume_offset(
+            source_len,
+            temp_len,
+            |len| self.prefixes_match(local_path, temp, len),
+        )?;
+
+        let mut remote_file = if offset > 0 {
+            info!(
+                "Resuming upload of {} at byte {} of {}",
+                local_path.display(),
+                offset,
+                source_len
+            );
+            let mut file = sftp
+                .open_mode(temp, OpenFlags::WRITE, 0o644, OpenType::File)
+                .map_err(AstraError::from_sftp)?;
+            file.seek(SeekFrom::Start(offset))?;
+            local_file.seek(SeekFrom::Start(offset))?;
+            progress.resume(offset);
+            file
+        } else {
+            sftp.create(temp).map_err(AstraError::from_sftp)?
+        };
+
+        let bytes = self.copy_chunks(local_file, &mut remote_file, progress)?;
+
+        // Keep the local mtime so later runs can tell which side changed, and the permissions of
+        // the file being replaced so e.g. an executable script stays executable
+        let mtime = local_file
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .map(|modified| DateTime::<Utc>::from(modified).timestamp() as u64);
+        let perm = sftp
+            .stat(remote_path)
+            .ok()
+            .and_then(|stat| stat.perm)
+            .map(|perm| perm & 0o7777);
+        if mtime.is_some() || perm.is_some() {
+            let attributes = FileStat {
+                size: None,
+                uid: None,
+                gid: None,
+                perm,
+                atime: mtime,
+                mtime,
+            };
+            if let Err(e) = remote_file.setstat(attributes) {
+                warn!(
+                    "Failed to set attributes on {}: {}",
+                    remote_path.display(),
+                    e
+                );
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    /// A new temp file for uploading `source_len` bytes to `target`, taking over the longest
+    /// temp file an earlier run abandoned next to it so the upload can resume from it
+    fn claim_remote_temp(&self, target: &Path, source_len: u64) -> PathBuf {
+        let temp = temp_path(target);
+        let policy = ResumePolicy::from_config(&self.config.advanced);
+        let (Some(dir), Ok(sftp)) = (target.parent(), self.sftp()) else {
+            return temp;
+        };
+        if !policy.applies_to(source_len) {
+            return temp;
+        }
+
+        let candidates = match sftp.readdir(dir) {
+            Ok(entries) => entries
+                .into_iter()
+                .filter(|(path, stat)| stat.is_file() && is_temp_of(path, target))
+                .map(|(path, stat)| TempCandidate {
+                    path,
+                    len: stat.size.unwrap_or(0),
+                    modified: UNIX_EPOCH + Duration::from_secs(stat.mtime.unwrap_or(0)),
+                })
+                .collect(),
+            Err(_) => return temp,
+        };
+        if let Some(abandoned) = policy.pick_abandoned(candidates, SystemTime::now()) {
+            // Renaming is the claim: of two transfers finding the same file only one succeeds.
+            // Touching it right away keeps others from taking it for abandoned in turn.
+            match sftp.rename(&abandoned.path, &temp, None) {
+                Ok(()) => {
+                    debug!("Taking over {}", abandoned.path.display());
+                    let now = Utc::now().timestamp() as u64;
+                    let times = FileStat {
+                        size: None,
+                        uid: None,
+                        gid: None,
+                        perm: None,
+                        atime: Some(now),
+                        mtime: Some(now),
+                    };
+                    if let Err(e) = sftp.setstat(&temp, times) {
+                        debug!("Failed to touch {}: {}", temp.display(), e);
+                    }
                 }
+                Err(e) => debug!("Could not take over {}: {}", abandoned.path.display(), e),
             }
         }
+        temp
+    }
 
-        let mut local_file = fs::File::open(local_path).map_err(AstraError::IoError)?;
+    /// The temp file a failed earlier attempt at `target` left on this session, if any
+    fn take_partial(&self, direction: OperationType, target: &Path) -> Option<PathBuf> {
+        self.partial
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&(direction, target.to_path_buf()))
+    }
 
-        let mut remote_file = sftp
-            .create(remote_path)
-            .map_err(|e| AstraError::FileOperationError(e.to_string()))?;
+    fn keep_partial(&self, direction: OperationType, target: &Path, temp: PathBuf) {
+        self.partial
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert((direction, target.to_path_buf()), temp);
+    }
 
-        std::io::copy(&mut local_file, &mut remote_file).map_err(AstraError::IoError)?;
+    /// Rename the finished temp file `temp` over `target`.
+    ///
+    /// Servers speaking SFTP v5 or later honour the overwrite flag, and any server renames onto
+    /// a missing target. OpenSSH speaks v3, where renaming onto an existing file fails, and
+    /// offers the `posix-rename@openssh.com` extension instead. With `remote_shell`, `mv` is
+    /// tried last. The target is never removed to make room: if nothing can replace it, the
+    /// old file stays and the temp file is kept for the next attempt.
+    fn replace_remote_file(&self, temp: &Path, target: &Path) -> AstraResult<()> {
+        let sftp = self.sftp()?;
 
-        Ok(())
+        let flags = RenameFlags::ATOMIC | RenameFlags::OVERWRITE | RenameFlags::NATIVE;
+        let error = match sftp.rename(temp, target, Some(flags)) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        debug!(
+            "SFTP rename of {} over {} failed ({}), using posix-rename",
+            temp.display(),
+            target.display(),
+            error
+        );
+        let error = match self.posix_rename(temp, target) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+
+        if self.config.advanced.remote_shell {
+            let command = format!(
+                "mv -f -- {} {}",
+                shell_quote(&temp.to_string_lossy()),
+                shell_quote(&target.to_string_lossy())
+            );
+            match self.run_remote_command(&command) {
+                Ok(_) => return Ok(()),
+                Err(e) => debug!("{} failed: {}", command, e),
+            }
+        }
+
+        // Never unlink the target to make room: a failure in between would lose both copies
+        Err(AstraError::FileOperationError(format!(
+            "the server cannot replace {} ({}); the new contents are kept in {}",
+            target.display(),
+            error,
+            temp.display()
+        )))
     }
 
-    pub fn download_file(&self, remote_path: &Path, local_path: &Path) -> AstraResult<()> {
+    /// Rename `from` over `to` with the `posix-rename@openssh.com` extension, which replaces an
+    /// existing file atomically on servers that speak SFTP v3 only, where a plain rename
+    /// refuses to overwrite
+    pub fn posix_rename(&self, from: &Path, to: &Path) -> AstraResult<()> {
+        let mut raw_sftp = self
+            .raw_sftp
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let raw_sftp = match &mut *raw_sftp {
+            Some(raw_sftp) => raw_sftp,
+            slot @ None => {
+                slot.insert(RawSftp::open(&self.session).map_err(AstraError::from_session)?)
+            }
+        };
+        raw_sftp
+            .posix_rename(from, to)
+            .map_err(AstraError::from_sftp)
+    }
+
+    /// Download one file, returning the number of bytes written
+    pub fn download_file(&self, remote_path: &Path, local_path: &Path) -> AstraResult<u64> {
         info!(
             "Downloading {} to {}",
             remote_path.display(),
             local_path.display()
         );
 
-        let sftp = self
-            .session
-            .sftp()
-            .map_err(|e| AstraError::SftpConnectionError(e.to_string()))?;
+        let mut progress = self.progress.file(local_path);
+        let outcome = self.read_remote_file(remote_path, local_path, &mut progress);
+        progress.finish(&outcome);
+        outcome
+    }
 
+    /// Download into a temp file next to `local_path` and rename it over the target once
+    /// complete, so an editor never loads a half-written file. A failed transfer leaves the temp
+    /// file behind for a retry on this session to resume from.
+    fn read_remote_file(
+        &self, remote_path: &Path, local_path: &Path, progress: &mut FileProgress<'_>,
+    ) -> AstraResult<u64> {
         if let Some(parent) = local_path.parent() {
             fs::create_dir_all(parent).map_err(AstraError::IoError)?;
         }
 
-        let mut remote_file = sftp
+        let mut remote_file = self
+            .sftp()?
             .open(remote_path)
-            .map_err(|e| AstraError::FileOperationError(e.to_string()))?;
+            .map_err(AstraError::from_sftp)?;
+        let stat = remote_file.stat().ok();
+        let source_len = stat.as_ref().and_then(|stat| stat.size);
+        progress.start(source_len);
+
+        let temp = self
+            .take_partial(OperationType::Download, local_path)
+            .unwrap_or_else(|| {
+                let policy = ResumePolicy::from_config(&self.config.advanced);
+                match source_len {
+                    Some(source_len) => claim_local_temp(local_path, &policy, source_len),
+                    None => temp_path(local_path),
+                }
+            });
+        let outcome = self
+            .read_into_local_temp(
+                &mut remote_file,
+                stat.as_ref(),
+                remote_path,
+                &temp,
+                progress,
+            )
+            .and_then(|bytes| {
+                // Keep the permissions of the file being replaced
+                if let Ok(metadata) = fs::metadata(local_path) {
+                    if let Err(e) = fs::set_permissions(&temp, metadata.permissions()) {
+                        warn!(
+                            "Failed to set permissions on {}: {}",
+                            local_path.display(),
+                            e
+                        );
+                    }
+                }
+                fs::rename(&temp, local_path).map_err(AstraError::IoError)?;
+                Ok(bytes)
+            });
+        if outcome.is_err() {
+            self.keep_partial(OperationType::Download, local_path, temp);
+        }
+        outcome
+    }
+
+    fn read_into_local_temp(
+        &self, remote_file: &mut ssh2::File, stat: Option<&FileStat>, remote_path: &Path,
+        temp: &Path, progress: &mut FileProgress<'_>,
+    ) -> AstraResult<u64> {
+        let source_len = stat.and_then(|stat| stat.size);
+        let temp_len = fs::metadata(temp)
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len());
+        let offset = match source_len {
+            Some(source_len) => ResumePolicy::from_config(&self.config.advanced).resume_offset(
+                source_len,
+                temp_len,
+                |len| self.prefixes_match(temp, remote_path, len),
+            )?,
+            None => 0,
+        };
 
-        let mut local_file = fs::File::create(local_path).map_err(AstraError::IoError)?;
+        let mut local_file = if offset > 0 {
+            info!(
+                "Resuming download of {} at byte {}",
+                remote_path.display(),
+                offset
+            );
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .open(temp)
+                .map_err(AstraError::IoError)?;
+            file.seek(SeekFrom::Start(offset))?;
+            remote_file.seek(SeekFrom::Start(offset))?;
+            progress.resume(offset);
+            file
+        } else {
+            fs::File::create(temp).map_err(AstraError::IoError)?
+        };
 
-        std::io::copy(&mut remote_file, &mut local_file).map_err(AstraError::IoError)?;
+        let bytes = self.copy_chunks(remote_file, &mut local_file, progress)?;
 
-        Ok(())
+        // Keep the remote mtime so later runs can tell which side changed
+        if let Some(mtime) = stat.and_then(|stat| stat.mtime) {
+            let modified = UNIX_EPOCH + Duration::from_secs(mtime);
+            if let Err(e) = local_file.set_modified(modified) {
+                warn!("Failed to set mtime on {}: {}", temp.display(), e);
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    /// Whether the first `len` bytes of the local and the remote file agree, judged by the
+    /// [`verify_window`] at their end
+    fn prefixes_match(&self, local_path: &Path, remote_path: &Path, len: u64) -> AstraResult<bool> {
+        let (offset, len) = verify_window(len);
+        let local_hash = range_hash(fs::File::open(local_path)?, offset, len)?;
+        Ok(local_hash == self.remote_range_hash(remote_path, offset, len)?)
+    }
+
+    /// SHA-256 of `len` bytes of a remote file at `offset`, read over SFTP. With `remote_shell`
+    /// it is computed on the server with `tail -c | head -c | sha256sum` instead, so the bytes
+    /// do not cross the network, falling back to SFTP if that fails.
+    fn remote_range_hash(&self, remote_path: &Path, offset: u64, len: u64) -> AstraResult<String> {
+        if self.config.advanced.remote_shell {
+            match self.exec_range_hash(remote_path, offset, len) {
+                Ok(hash) => return Ok(hash),
+                Err(e) => debug!(
+                    "Hashing {} on the server failed ({}), reading it instead",
+                    remote_path.display(),
+                    e
+                ),
+            }
+        }
+
+        let file = self
+            .sftp()?
+            .open(remote_path)
+            .map_err(AstraError::from_sftp)?;
+        range_hash(file, offset, len)
+    }
+
+    fn exec_range_hash(&self, remote_path: &Path, offset: u64, len: u64) -> AstraResult<String> {
+        let command = format!(
+            "tail -c +{} < {} | head -c {} | sha256sum",
+            offset + 1,
+            shell_quote(&remote_path.to_string_lossy()),
+            len
+        );
+        let output = self.run_remote_command(&command)?;
+
+        let hash = output.split_whitespace().next().unwrap_or_default();
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(AstraError::SystemError(format!(
+                "unexpected output from `{}`: {}",
+                command,
+                output.trim()
+            )));
+        }
+        Ok(hash.to_ascii_lowercase())
+    }
+
+    /// Run a shell command on the server, returning its stdout if it exits with status 0
+    fn run_remote_command(&self, command: &str) -> AstraResult<String> {
+        let mut channel = self.session.channel_session()?;
+        channel.exec(command)?;
+        let mut output = String::new();
+        channel.read_to_string(&mut output)?;
+        channel.wait_close()?;
+
+        let status = channel.exit_status()?;
+        if status != 0 {
+            return Err(AstraError::SystemError(format!(
+                "`{}` exited with status {}: {}",
+                command,
+                status,
+                output.trim()
+            )));
+        }
+        Ok(output)
     }
 
     pub fn create_remote_directory(&self, remote_path: &Path) -> AstraResult<()> {
-        let sftp = self
-            .session
-            .sftp()
-            .map_err(|e| AstraError::SftpConnectionError(e.to_string()))?;
+        let sftp = self.sftp()?;
 
         sftp.mkdir(remote_path, 0o755)
-            .map_err(|e| AstraError::FileOperationError(e.to_string()))?;
+            .map_err(AstraError::from_sftp)?;
+
+        Ok(())
+    }
+
+    /// Create a remote directory and any missing parents, like `mkdir -p`.
+    ///
+    /// A directory that appears between the check and the `mkdir` (e.g. created by another
+    /// transfer session) is not an error.
+    pub fn create_remote_dir_all(&self, remote_path: &Path) -> AstraResult<()> {
+        let sftp = self.sftp()?;
+
+        let mut missing = Vec::new();
+        let mut current = Some(remote_path);
+        while let Some(dir) = current {
+            if dir.as_os_str().is_empty() || sftp.stat(dir).is_ok() {
+                break;
+            }
+            missing.push(dir);
+            current = dir.parent();
+        }
+
+        for dir in missing.into_iter().rev() {
+            if let Err(e) = sftp.mkdir(dir, 0o755) {
+                let exists = sftp.stat(dir).map(|stat| stat.is_dir()).unwrap_or(false);
+                if !exists {
+                    return Err(AstraError::FileOperationError(format!(
+                        "Failed to create {}: {}",
+                        dir.display(),
+                        e
+                    )));
+                }
+            }
+        }
 
         Ok(())
     }
 
     pub fn delete_remote_file(&self, remote_path: &Path) -> AstraResult<()> {
-        let sftp = self
-            .session
-            .sftp()
-            .map_err(|e| AstraError::SftpConnectionError(e.to_string()))?;
+        let sftp = self.sftp()?;
 
-        sftp.unlink(remote_path)
-            .map_err(|e| AstraError::FileOperationError(e.to_string()))?;
+        sftp.unlink(remote_path).map_err(AstraError::from_sftp)?;
 
         Ok(())
     }
 
-    pub fn sync_incremental(&self) -> AstraResult<Vec<SyncOperation>> {
-        info!("Starting incremental sync");
+    /// Move a remote file into `trash_dir`, keeping its path relative to the remote root under a
+    /// directory named after `timestamp` (`<trash>/20240101-120000/src/index.php`)
+    pub fn trash_remote_file(
+        &self, remote_path: &Path, trash_dir: &str, timestamp: DateTime<Utc>,
+    ) -> AstraResult<()> {
+        let relative_path = PathMapper::from_config(&self.config)
+            .remote_to_relative(remote_path)
+            .map(|relative_path| to_posix(&relative_path))
+            .unwrap_or_else(|_| {
+                remote_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            });
+        let target = PathBuf::from(format!(
+            "{}/{}/{}",
+            trash_dir,
+            timestamp.format("%Y%m%d-%H%M%S"),
+            relative_path
+        ));
 
-        let local_path = Path::new(&self.config.local_path);
-        let remote_path = Path::new(&self.config.remote_path);
+        if let Some(parent) = target.parent() {
+            self.create_remote_dir_all(parent)?;
+        }
+        self.sftp()?
+            .rename(remote_path, &target, None)
+            .map_err(|e| {
+                AstraError::FileOperationError(format!(
+                    "Failed to move {} to {}: {}",
+                    remote_path.display(),
+                    target.display(),
+                    e
+                ))
+            })?;
 
-        let local_files = self.get_local_files(local_path)?;
-        let remote_files = self.get_remote_files(remote_path)?;
+        Ok(())
+    }
 
-        let mut operations = Vec::new();
-        let local_map: HashMap<PathBuf, FileStatus> = local_files
+    /// Remove the temp files that interrupted transfers left on either side more than
+    /// `stale_temp_age` seconds ago, returning how many were removed
+    pub fn clean_stale_temp_files(&self) -> usize {
+        let max_age = self.config.advanced.stale_temp_age;
+        if max_age == 0 {
+            return 0;
+        }
+        // Never remove what a live transfer may still be writing
+        let max_age = Duration::from_secs(max_age)
+            .max(ResumePolicy::from_config(&self.config.advanced).abandoned_after);
+
+        let mapper = PathMapper::from_config(&self.config);
+        let local = clean_local_temp_files(mapper.local_root(), &self.filter, max_age);
+        let remote = self.clean_remote_temp_files(mapper.remote_root(), max_age);
+        local + remote
+    }
+
+    /// Stale remote temp files are found by walking the tree over SFTP. With `remote_shell`,
+    /// `find` on the server does it in one round trip instead
+    fn clean_remote_temp_files(&self, remote_root: &Path, max_age: Duration) -> usize {
+        let sftp = match self.sftp() {
+            Ok(sftp) => sftp,
+            Err(e) => {
+                warn!("Failed to clean remote temp files: {}", e);
+                return 0;
+            }
+        };
+        match sftp.stat(remote_root) {
+            Ok(stat) if stat.is_dir() => {}
+            _ => return 0,
+        }
+
+        if self.config.advanced.remote_shell {
+            let command = format!(
+                "find {} -type f -name {} -mmin +{} -print -delete",
+                shell_quote(&remote_root.to_string_lossy()),
+                shell_quote(&format!(".*{}", TEMP_SUFFIX)),
+                max_age.as_secs().div_ceil(60)
+            );
+            match self.run_remote_command(&command) {
+                Ok(output) => {
+                    let removed: Vec<&str> = output.lines().collect();
+                    for path in &removed {
+                        info!("Removed stale temp file {}", path);
+                    }
+                    return removed.len();
+                }
+                Err(e) => debug!(
+                    "Cleaning temp files on the server failed ({}), walking the tree instead",
+                    e
+                ),
+            }
+        }
+
+        let cutoff = (Utc::now().timestamp() as u64).saturating_sub(max_age.as_secs());
+        self.clean_remote_dir(sftp, remote_root, Path::new(""), cutoff, 0)
+    }
+
+    fn clean_remote_dir(
+        &self, sftp: &Sftp, root: &Path, relative_dir: &Path, cutoff: u64, depth: usize,
+    ) -> usize {
+        let dir = root.join(relative_dir);
+        let entries = match sftp.readdir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Failed to read remote directory {}: {}", dir.display(), e);
+                return 0;
+            }
+        };
+
+        let mut removed = 0;
+        for (path, stat) in entries {
+            let Some(name) = path.file_name() else {
+                continue;
+            };
+            let relative_path = relative_dir.join(name);
+
+            // readdir does not follow symlinks, so symlinked directories are not descended into
+            if stat.is_dir() {
+                if depth < MAX_REMOTE_DEPTH && !self.filter.is_excluded(&relative_path, true) {
+                    removed += self.clean_remote_dir(sftp, root, &relative_path, cutoff, depth + 1);
+                }
+            } else if stat.is_file()
+                && is_temp_file(&path)
+                && stat.mtime.is_some_and(|mtime| mtime < cutoff)
+            {
+                match sftp.unlink(&path) {
+                    Ok(()) => {
+                        info!("Removed stale temp file {}", path.display());
+                        removed += 1;
+                    }
+                    Err(e) => warn!("Failed to remove stale temp file {}: {}", path.display(), e),
+                }
+            }
+        }
+        removed
+    }
+
+    /// Walk both trees and key every file by its path relative to the sync roots
+    pub fn scan_trees(&self) -> AstraResult<TreeScan> {
+        let mapper = PathMapper::from_config(&self.config);
+
+        let local_files = self.get_local_files(mapper.local_root())?;
+        let remote_listing = self.walk_remote_tree(mapper.remote_root(), Path::new(""))?;
+
+        let local = local_files
             .into_iter()
             .map(|f| (f.path.clone(), f))
             .collect();
 
-        let remote_map: HashMap<PathBuf, FileStatus> = remote_files
+        let remote = remote_listing
+            .files
             .into_iter()
+            .filter(|f| !f.is_directory)
             .map(|f| (f.path.clone(), f))
             .collect();
 
-        for (local_path, local_file) in local_map.iter() {
-            let relative_path = local_path.strip_prefix(local_path).unwrap_or(local_path);
-            let remote_file_path = remote_path.join(relative_path);
-
-            match remote_map.get(&remote_file_path) {
-                Some(remote_file) => {
-                    if local_file.modified > remote_file.modified
-                        || local_file.checksum != remote_file.checksum
-                    {
-                        operations.push(SyncOperation {
-                            operation_type: OperationType::Upload,
-                            local_path: local_path.clone(),
-                            remote_path: remote_file_path,
-                            timestamp: Utc::now(),
-                        });
-                    }
+        Ok(TreeScan {
+            local,
+            remote,
+            unlisted: remote_listing.unlisted,
+            root_missing: remote_listing.root_missing,
+        })
+    }
+
+    pub fn sync_incremental(&self, mode: SyncMode, baseline: &SyncState) -> AstraResult<SyncPlan> {
+        info!("Starting incremental sync ({:?})", mode);
+
+        let mapper = PathMapper::from_config(&self.config);
+        let scan = self.scan_trees()?;
+        planner::check_remote_root(scan.root_missing, &mapper, baseline)?;
+
+        let mut plan = planner::plan_operations(
+            mode,
+            &mapper,
+            &scan.local,
+            &scan.remote,
+            baseline,
+            self.config.sync.conflict_policy,
+        );
+        planner::restrict_to_listed(&mut plan, &mapper, &scan.unlisted);
+        Ok(plan)
+    }
+
+    /// Current state of a single remote file, if it exists
+    pub fn stat_remote_file(&self, remote_path: &Path) -> Option<FileStatus> {
+        let stat = self.sftp().ok()?.stat(remote_path).ok()?;
+        Some(FileStatus {
+            path: remote_path.to_path_buf(),
+            size: stat.size.unwrap_or(0),
+            modified: DateTime::from_timestamp(stat.mtime.unwrap_or(0) as i64, 0)
+                .unwrap_or_else(Utc::now),
+            is_directory: stat.is_dir(),
+            checksum: None,
+        })
+    }
+
+    /// Run a single operation, returning the number of bytes transferred
+    pub fn execute_operation(&self, operation: &SyncOperation) -> AstraResult<u64> {
+        match operation.operation_type {
+            OperationType::Upload => {
+                self.upload_file(&operation.local_path, &operation.remote_path)
+            }
+            OperationType::Download => {
+                self.download_file(&operation.remote_path, &operation.local_path)
+            }
+            OperationType::Delete => match remote_trash_dir(&self.config) {
+                Some(trash_dir) => self
+                    .trash_remote_file(&operation.remote_path, &trash_dir, operation.timestamp)
+                    .map(|_| 0),
+                None => self.delete_remote_file(&operation.remote_path).map(|_| 0),
+            },
+            OperationType::DeleteLocal => fs::remove_file(&operation.local_path)
+                .map(|_| 0)
+                .map_err(AstraError::from),
+            OperationType::CreateDirectory => self
+                .create_remote_dir_all(&operation.remote_path)
+                .map(|_| 0),
+            OperationType::KeepBoth => self.keep_both(operation),
+            // Unresolved conflicts are reported, never transferred
+            OperationType::Conflict => Ok(0),
+        }
+    }
+
+    /// Save the remote copy of a conflicting file next to the local one, then upload the local
+    /// copy, so neither version is lost
+    fn keep_both(&self, operation: &SyncOperation) -> AstraResult<u64> {
+        let copy_path = planner::conflict_copy_path(&operation.local_path, operation.timestamp);
+        let downloaded = self.download_file(&operation.remote_path, &copy_path)?;
+        let uploaded = self.upload_file(&operation.local_path, &operation.remote_path)?;
+        Ok(downloaded + uploaded)
+    }
+
+    /// [`execute_operation`](Self::execute_operation) with [retries](Self::retry), recording
+    /// the number of attempts
+    pub fn execute_with_retry(&mut self, operation: &SyncOperation) -> FileResult {
+        let (outcome, attempts) = self.retry(&operation.local_path, |client| {
+            client.execute_operation(operation)
+        });
+        FileResult::new(operation, outcome).with_attempts(attempts)
+    }
+
+    /// Run operations one after another on this session, recording the outcome of each. Under
+    /// `on_error = "stop"` the operations after a failure are reported as not attempted.
+    pub fn execute_operations(&mut self, operations: &[SyncOperation]) -> Vec<FileResult> {
+        let mut stopped = false;
+        operations
+            .iter()
+            .map(|operation| {
+                if stopped {
+                    return FileResult::not_attempted(operation);
                 }
-                None => {
-                    operations.push(SyncOperation {
-                        operation_type: OperationType::Upload,
-                        local_path: local_path.clone(),
-                        remote_path: remote_file_path,
-                        timestamp: Utc::now(),
-                    });
+                let result = self.execute_with_retry(operation);
+                stopped = !result.success
+                    && result.attempts > 0
+                    && self.config.sync.on_error == ErrorPolicy::Stop;
+                result
+            })
+            .collect()
+    }
+}
+
+/// A new temp file for downloading `source_len` bytes to `target`, taking over the longest temp
+/// file an earlier run abandoned next to it (see [`ResumePolicy::pick_abandoned`]) so the
+/// download can resume from it
+pub fn claim_local_temp(target: &Path, policy: &ResumePolicy, source_len: u64) -> PathBuf {
+    let temp = temp_path(target);
+    let Some(dir) = target.parent() else {
+        return temp;
+    };
+    if !policy.applies_to(source_len) {
+        return temp;
+    }
+
+    let candidates = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter(|entry| is_temp_of(&entry.path(), target))
+            .filter_map(|entry| {
+                let metadata = entry
+                    .metadata()
+                    .ok()
+                    .filter(|metadata| metadata.is_file())?;
+                Some(TempCandidate {
+                    path: entry.path(),
+                    len: metadata.len(),
+                    modified: metadata.modified().ok()?,
+                })
+            })
+            .collect(),
+        Err(_) => return temp,
+    };
+    if let Some(abandoned) = policy.pick_abandoned(candidates, SystemTime::now()) {
+        match fs::rename(&abandoned.path, &temp) {
+            Ok(()) => {
+                debug!("Taking over {}", abandoned.path.display());
+                let touched = fs::File::options()
+                    .write(true)
+                    .open(&temp)
+                    .and_then(|file| file.set_modified(SystemTime::now()));
+                if let Err(e) = touched {
+                    debug!("Failed to touch {}: {}", temp.display(), e);
                 }
             }
+            Err(e) => debug!("Could not take over {}: {}", abandoned.path.display(), e),
         }
+    }
+    temp
+}
 
-        for (remote_path, _remote_file) in remote_map.iter() {
-            let relative_path = remote_path.strip_prefix(remote_path).unwrap_or(remote_path);
-            let local_file_path = local_path.join(relative_path);
+/// Remove the temp files of interrupted transfers below `root` that were last written more than
+/// `max_age` ago, skipping directories the filter excludes. Returns how many were removed.
+pub fn clean_local_temp_files(root: &Path, filter: &PathFilter, max_age: Duration) -> usize {
+    let mut removed = 0;
+    for entry in WalkDir::new(root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| {
+            let relative_path = e.path().strip_prefix(root).unwrap_or(e.path());
+            !e.file_type().is_dir() || !filter.is_excluded(relative_path, true)
+        })
+        .filter_map(Result::ok)
+    {
+        let path = entry.path();
+        if !entry.file_type().is_file() || !is_temp_file(path) {
+            continue;
+        }
 
-            if !local_map.contains_key(&local_file_path) {
-                operations.push(SyncOperation {
-                    operation_type: OperationType::Download,
-                    local_path: local_file_path,
-                    remote_path: remote_path.clone(),
-                    timestamp: Utc::now(),
-                });
+        let stale = entry
+            .metadata()
+            .ok()
+            .and_then(|metadata| metadata.modified().ok())
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > max_age);
+        if stale {
+            match fs::remove_file(path) {
+                Ok(()) => {
+                    info!("Removed stale temp file {}", path.display());
+                    removed += 1;
+                }
+                Err(e) => warn!("Failed to remove stale temp file {}: {}", path.display(), e),
             }
         }
+    }
+    removed
+}
 
-        Ok(operations)
+fn sftp_status(error: &ssh2::Error) -> Option<i32> {
+    match error.code() {
+        ssh2::ErrorCode::SFTP(code) => Some(code),
+        ssh2::ErrorCode::Session(_) => None,
     }
+}
 
-    pub fn execute_operations(&self, operations: &[SyncOperation]) -> AstraResult<()> {
-        for operation in operations {
-            match operation.operation_type {
-                OperationType::Upload => {
-                    self.upload_file(&operation.local_path, &operation.remote_path)?;
-                }
-                OperationType::Download => {
-                    self.download_file(&operation.remote_path, &operation.local_path)?;
-                }
-                OperationType::Delete => {
-                    self.delete_remote_file(&operation.remote_path)?;
-                }
-                OperationType::CreateDirectory => {
-                    self.create_remote_directory(&operation.remote_path)?;
-                }
+/// A path the server will not read for us, as opposed to a failing connection
+fn is_unreadable(error: &ssh2::Error) -> bool {
+    matches!(
+        sftp_status(error),
+        Some(SFTP_NO_SUCH_FILE | SFTP_PERMISSION_DENIED)
+    )
+}
+
+fn listing_error(path: &Path, error: ssh2::Error) -> AstraError {
+    match AstraError::from_sftp(error) {
+        AstraError::FileOperationError(message) => AstraError::FileOperationError(format!(
+            "Failed to list remote {}: {}",
+            path.display(),
+            message
+        )),
+        transient => transient,
+    }
+}
+
+/// Quote `value` as a single POSIX shell word
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+extern "C" {
+    // Exported by libssh2 1.11 and later, which libssh2-sys builds from its bundled copy, but
+    // not bound by it. Linking a system libssh2 (LIBSSH2_SYS_USE_PKG_CONFIG) older than 1.11
+    // fails on this symbol.
+    fn libssh2_sftp_posix_rename_ex(
+        sftp: *mut raw::LIBSSH2_SFTP, source: *const c_char, source_len: usize,
+        dest: *const c_char, dest_len: usize,
+    ) -> c_int;
+}
+
+/// A second SFTP channel on a session, for the one call ssh2 has no binding for, since ssh2
+/// does not expose the handle of its own [`Sftp`]. Opened on first use and kept for the life
+/// of the session.
+///
+/// libssh2 is not thread-safe per session, so the handle is only ever used while holding the
+/// lock ssh2 keeps for the session ([`Session::raw`]), the same lock every ssh2 call on it
+/// takes. The clone of `session` keeps the session alive until the handle is shut down.
+struct RawSftp {
+    session: Session,
+    handle: *mut raw::LIBSSH2_SFTP,
+}
+
+// SAFETY: the handle is only dereferenced by libssh2 under the session lock (see above), which
+// serializes it with every other use of the session, whichever thread holds the client.
+unsafe impl Send for RawSftp {}
+
+impl RawSftp {
+    fn open(session: &Session) -> Result<Self, ssh2::Error> {
+        let mut guard = session.raw();
+        let sess: *mut raw::LIBSSH2_SESSION = &mut *guard;
+        // SAFETY: `sess` is the live session, locked by `guard` for the whole call
+        let handle = unsafe { raw::libssh2_sftp_init(sess) };
+        if handle.is_null() {
+            return Err(
+                ssh2::Error::last_session_error_raw(sess).unwrap_or_else(ssh2::Error::unknown)
+            );
+        }
+        Ok(Self {
+            session: session.clone(),
+            handle,
+        })
+    }
+
+    /// Rename `from` over `to` with `posix-rename@openssh.com`
+    fn posix_rename(&self, from: &Path, to: &Path) -> Result<(), ssh2::Error> {
+        let from = from.to_string_lossy();
+        let to = to.to_string_lossy();
+
+        let mut guard = self.session.raw();
+        let sess: *mut raw::LIBSSH2_SESSION = &mut *guard;
+        // SAFETY: `guard` holds the session lock for the whole block, so no other call uses the
+        // session or this handle meanwhile. Both path buffers outlive the call, which reads
+        // exactly their lengths and keeps no pointer to them.
+        unsafe {
+            match libssh2_sftp_posix_rename_ex(
+                self.handle,
+                from.as_ptr().cast(),
+                from.len(),
+                to.as_ptr().cast(),
+                to.len(),
+            ) {
+                0 => Ok(()),
+                raw::LIBSSH2_ERROR_SFTP_PROTOCOL => Err(ssh2::Error::from_errno(ErrorCode::SFTP(
+                    raw::libssh2_sftp_last_error(self.handle) as c_int,
+                ))),
+                rc => Err(ssh2::Error::from_session_error_raw(sess, rc)),
             }
         }
-        Ok(())
     }
 }
+
+impl Drop for RawSftp {
+    fn drop(&mut self) {
+        let _guard = self.session.raw();
+        // SAFETY: the handle was opened on this session, which the lock keeps to ourselves, and
+        // is not used after this
+        unsafe {
+            raw::libssh2_sftp_shutdown(self.handle);
+        }
+    }
+}
+
+/// Connect to the configured host, trying each resolved address within `connect_timeout`
+fn connect_tcp(config: &SftpConfig) -> AstraResult<TcpStream> {
+    let addresses = (config.host.as_str(), config.port)
+        .to_socket_addrs()
+        .map_err(|e| {
+            AstraError::SftpConnectionError(format!(
+                "Failed to resolve {}:{}: {}",
+                config.host, config.port, e
+            ))
+        })?;
+
+    let timeout = config.advanced.connect_timeout;
+    let mut last_error = None;
+    for address in addresses {
+        let result = if timeout > 0 {
+            TcpStream::connect_timeout(&address, Duration::from_secs(timeout))
+        } else {
+            TcpStream::connect(address)
+        };
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(match last_error {
+        Some(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+            AstraError::ConnectionInterrupted(format!(
+                "Connection to {}:{} timed out after {}s",
+                config.host, config.port, timeout
+            ))
+        }
+        Some(e) if is_transient_io(e.kind()) => AstraError::ConnectionInterrupted(format!(
+            "Failed to connect to {}:{}: {}",
+            config.host, config.port, e
+        )),
+        Some(e) => AstraError::SftpConnectionError(format!(
+            "Failed to connect to {}:{}: {}",
+            config.host, config.port, e
+        )),
+        None => AstraError::SftpConnectionError(format!("No addresses found for {}", config.host)),
+    })
+}
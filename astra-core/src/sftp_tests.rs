@@ -1,8 +1,15 @@
 #[cfg(test)]
 mod tests {
-    use crate::sftp::SftpClient;
-    use crate::types::SftpConfig;
+    use crate::config::ConfigReader;
+    use crate::filter::PathFilter;
+    use crate::sftp::{claim_local_temp, clean_local_temp_files, shell_quote, SftpClient};
+    use crate::transfer::ResumePolicy;
+    use crate::types::{AdvancedConfig, SftpConfig, SyncOptions};
     use std::fs;
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::thread;
+    use std::time::{Duration, Instant};
     use tempfile::TempDir;
 
     #[tokio::test]
@@ -13,15 +20,9 @@ mod tests {
         fs::write(temp_dir.path().join("file2.txt"), "content2").unwrap();
 
         let config = SftpConfig {
-            enabled: Some(true),
-            host: "test.com".to_string(),
-            port: 22,
-            username: "user".to_string(),
             password: Some("pass".to_string()),
-            private_key_path: None,
-            remote_path: "/remote".to_string(),
-            local_path: temp_dir.path().to_str().unwrap().to_string(),
             language: Some(crate::i18n::detect_language()),
+            ..SftpConfig::for_test(temp_dir.path().to_string_lossy())
         };
 
         let client = SftpClient::new(config);
@@ -33,6 +34,7 @@ mod tests {
             Ok(client) => {
                 let files = client.get_local_files(temp_dir.path()).unwrap();
                 assert_eq!(files.len(), 2);
+                assert!(files.iter().all(|f| f.path.is_relative()));
             }
         }
     }
@@ -44,15 +46,9 @@ mod tests {
         fs::write(&test_file, "test content").unwrap();
 
         let config = SftpConfig {
-            enabled: Some(true),
-            host: "test.com".to_string(),
-            port: 22,
-            username: "user".to_string(),
             password: Some("pass".to_string()),
-            private_key_path: None,
-            remote_path: "/remote".to_string(),
-            local_path: temp_dir.path().to_str().unwrap().to_string(),
             language: Some(crate::i18n::detect_language()),
+            ..SftpConfig::for_test(temp_dir.path().to_string_lossy())
         };
 
         let client = SftpClient::new(config);
@@ -67,4 +63,153 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_handshake_times_out_on_silent_server() {
+        // A server that accepts the connection but never sends an SSH banner
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            thread::sleep(Duration::from_secs(3));
+            drop(stream);
+        });
+
+        let config = SftpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            password: Some("pass".to_string()),
+            advanced: AdvancedConfig {
+                connect_timeout: 1,
+                operation_timeout: 1,
+                ..AdvancedConfig::default()
+            },
+            ..SftpConfig::for_test("/local")
+        };
+
+        let started = Instant::now();
+        assert!(SftpClient::new(config).is_err());
+        assert!(started.elapsed() < Duration::from_secs(3));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_shell_quote_survives_quotes_and_spaces() {
+        assert_eq!(shell_quote("/srv/site/a b.sql"), "'/srv/site/a b.sql'");
+        assert_eq!(shell_quote("/srv/it's.sql"), r"'/srv/it'\''s.sql'");
+    }
+
+    #[test]
+    fn test_only_stale_temp_files_are_cleaned_up() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("node_modules")).unwrap();
+
+        let stale = root.join("src/.app.js.astra-part");
+        let fresh = root.join("src/.style.css.astra-part");
+        let excluded = root.join("node_modules/.lib.js.astra-part");
+        for path in [&stale, &fresh, &excluded, &root.join("src/app.js")] {
+            fs::write(path, "partial").unwrap();
+        }
+        let two_days_ago = std::time::SystemTime::now() - Duration::from_secs(2 * 24 * 60 * 60);
+        for path in [&stale, &excluded, &root.join("src/app.js")] {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(two_days_ago)
+                .unwrap();
+        }
+
+        let options = SyncOptions {
+            exclude_patterns: vec!["node_modules/".to_string()],
+            ..SyncOptions::default()
+        };
+        let filter = PathFilter::new(&options, root).unwrap();
+        let removed = clean_local_temp_files(root, &filter, Duration::from_secs(24 * 60 * 60));
+
+        assert_eq!(removed, 1);
+        assert!(!stale.exists());
+        // Recent ones are kept for the next transfer to resume from
+        assert!(fresh.exists());
+        assert!(excluded.exists());
+        assert!(root.join("src/app.js").exists());
+    }
+
+    #[test]
+    fn test_download_leaves_a_temp_file_being_written_alone() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("video.mp4");
+        let policy = ResumePolicy {
+            min_size: 0,
+            ..ResumePolicy::from_config(&AdvancedConfig::default())
+        };
+
+        // Another download of the same file is writing this one right now
+        let live = temp_dir.path().join(".video.mp4.4242-0.astra-part");
+        fs::write(&live, "partial").unwrap();
+        let temp = claim_local_temp(&target, &policy, 100);
+        assert_ne!(temp, live);
+        assert!(!temp.exists());
+        assert_eq!(fs::read_to_string(&live).unwrap(), "partial");
+
+        // Once its writer is gone for good it is taken over, and only by one transfer
+        let idle_since = std::time::SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(&live)
+            .unwrap()
+            .set_modified(idle_since)
+            .unwrap();
+        let temp = claim_local_temp(&target, &policy, 100);
+        assert_eq!(fs::read_to_string(&temp).unwrap(), "partial");
+        assert!(!live.exists());
+        let other = claim_local_temp(&target, &policy, 100);
+        assert!(!other.exists());
+    }
+
+    /// Needs a real server: point `ASTRA_TEST_SFTP_PROJECT` at a project directory whose
+    /// config reaches one. Skipped otherwise
+    #[test]
+    fn test_posix_rename_replaces_an_existing_file() {
+        let Ok(project) = std::env::var("ASTRA_TEST_SFTP_PROJECT") else {
+            eprintln!("ASTRA_TEST_SFTP_PROJECT is not set, skipping");
+            return;
+        };
+        let config = ConfigReader::new(Some(project)).read_config().unwrap();
+        let client = SftpClient::new(config.clone()).unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let remote_dir =
+            Path::new(&config.remote_path).join(format!(".astra-test-{}", std::process::id()));
+        client.create_remote_dir_all(&remote_dir).unwrap();
+        for (name, content) in [("old.txt", "old"), ("new.txt", "new")] {
+            let local = temp_dir.path().join(name);
+            fs::write(&local, content).unwrap();
+            client.upload_file(&local, &remote_dir.join(name)).unwrap();
+        }
+
+        // Twice, so the second rename goes through the cached channel
+        client
+            .posix_rename(&remote_dir.join("new.txt"), &remote_dir.join("old.txt"))
+            .unwrap();
+        client
+            .posix_rename(&remote_dir.join("old.txt"), &remote_dir.join("moved.txt"))
+            .unwrap();
+
+        let downloaded = temp_dir.path().join("downloaded.txt");
+        client
+            .download_file(&remote_dir.join("moved.txt"), &downloaded)
+            .unwrap();
+        assert_eq!(fs::read_to_string(&downloaded).unwrap(), "new");
+        assert!(client
+            .stat_remote_file(&remote_dir.join("new.txt"))
+            .is_none());
+        assert!(client
+            .stat_remote_file(&remote_dir.join("old.txt"))
+            .is_none());
+        client
+            .delete_remote_file(&remote_dir.join("moved.txt"))
+            .unwrap();
+    }
 }
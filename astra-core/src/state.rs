@@ -0,0 +1,268 @@
+use crate::error::{AstraError, AstraResult};
+use crate::paths::{normalize_local_path, normalize_remote_path, to_posix, PathMapper};
+use crate::types::{FileResult, FileStatus, OperationType, SftpConfig};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// Directory holding per-project settings and sync state, relative to the local root
+pub const STATE_DIR: &str = ".astra-settings";
+/// Sync baseline file inside [`STATE_DIR`]
+pub const STATE_FILE: &str = "state.json";
+/// Bumped when the state format changes incompatibly; older files are discarded
+pub const STATE_VERSION: u32 = 1;
+
+/// Size, mtime (whole seconds) and content hash of one side of a file at the last sync
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileSnapshot {
+    pub size: u64,
+    pub mtime: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl FileSnapshot {
+    pub fn from_status(status: &FileStatus) -> Self {
+        Self {
+            size: status.size,
+            mtime: status.modified.timestamp(),
+            hash: status.checksum.clone(),
+        }
+    }
+
+    /// Snapshot of a local file as it is on disk now, including its SHA-256
+    pub fn from_local_file(path: &Path) -> AstraResult<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified: chrono::DateTime<chrono::Utc> = metadata.modified()?.into();
+        let hash = format!("{:x}", Sha256::digest(fs::read(path)?));
+
+        Ok(Self {
+            size: metadata.len(),
+            mtime: modified.timestamp(),
+            hash: Some(hash),
+        })
+    }
+
+    /// Whether `status` still describes the file this snapshot was taken of.
+    ///
+    /// When both sides carry a hash the content decides, so a file that was only touched is not
+    /// treated as changed; otherwise size and mtime must both match.
+    pub fn matches(&self, status: &FileStatus) -> bool {
+        if let (Some(hash), Some(checksum)) = (&self.hash, &status.checksum) {
+            return hash == checksum;
+        }
+        self.size == status.size && self.mtime == status.modified.timestamp()
+    }
+}
+
+/// The project a local root belongs to: the nearest directory at or above it holding project
+/// settings (`.astra-settings`, `astra.json` or `.vscode/sftp.json`), or the root itself
+pub fn project_root(local_root: &Path) -> PathBuf {
+    local_root
+        .ancestors()
+        .find(|dir| {
+            dir.join(STATE_DIR).is_dir()
+                || dir.join("astra.json").is_file()
+                || dir.join(".vscode").join("sftp.json").is_file()
+        })
+        .unwrap_or(local_root)
+        .to_path_buf()
+}
+
+/// Both sides of a path as they were after it was last synced
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathState {
+    pub local: FileSnapshot,
+    pub remote: FileSnapshot,
+}
+
+/// The last-synced baseline of a project, stored in `.astra-settings/state.json` under the
+/// project root.
+///
+/// A sync of a subtree (`--local <subdir>`) keeps its own baseline next to it, in
+/// `state-<key>.json` keyed by both roots, so no settings directory is ever created inside the
+/// tree.
+///
+/// Entries are keyed by POSIX path relative to the sync roots. The state records the host and
+/// remote root it was built against; if either changes the baseline is discarded rather than
+/// compared against an unrelated tree.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    pub version: u32,
+    pub host: String,
+    pub remote_root: String,
+    #[serde(default)]
+    pub files: BTreeMap<String, PathState>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl SyncState {
+    /// Location of the state file for `config`
+    pub fn state_path(config: &SftpConfig) -> PathBuf {
+        let local_root = normalize_local_path(Path::new(&config.local_path));
+        let project_root = project_root(&local_root);
+        if project_root == local_root {
+            return project_root.join(STATE_DIR).join(STATE_FILE);
+        }
+
+        let roots = format!(
+            "{}\n{}",
+            local_root.display(),
+            normalize_remote_path(&config.remote_path)
+        );
+        let key = format!("{:x}", Sha256::digest(roots.as_bytes()));
+        project_root
+            .join(STATE_DIR)
+            .join(format!("state-{}.json", &key[..16]))
+    }
+
+    /// An empty baseline for `config`, as used on the first sync
+    pub fn new(config: &SftpConfig) -> Self {
+        Self {
+            version: STATE_VERSION,
+            host: config.host.clone(),
+            remote_root: normalize_remote_path(&config.remote_path),
+            files: BTreeMap::new(),
+            path: Some(Self::state_path(config)),
+        }
+    }
+
+    /// Load the baseline for `config`, starting fresh if there is none or it does not apply.
+    ///
+    /// A damaged state file is not fatal: it is logged and the sync falls back to comparing the
+    /// two trees directly, as on a first run.
+    pub fn load(config: &SftpConfig) -> Self {
+        let path = Self::state_path(config);
+        let fresh = Self::new(config);
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => return fresh,
+        };
+
+        let mut state: SyncState = match serde_json::from_str(&content) {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Ignoring unreadable sync state {}: {}", path.display(), e);
+                return fresh;
+            }
+        };
+
+        if state.version != STATE_VERSION
+            || state.host != fresh.host
+            || state.remote_root != fresh.remote_root
+        {
+            debug!(
+                "Sync state {} belongs to a different target, starting fresh",
+                path.display()
+            );
+            return fresh;
+        }
+
+        state.path = Some(path);
+        state
+    }
+
+    /// Write the baseline back to disk, replacing the old file atomically
+    pub fn save(&self) -> AstraResult<()> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| AstraError::SystemError("Sync state has no file path".to_string()))?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    pub fn get(&self, relative_path: &Path) -> Option<&PathState> {
+        self.files.get(&to_posix(relative_path))
+    }
+
+    pub fn record(&mut self, relative_path: &Path, local: FileSnapshot, remote: FileSnapshot) {
+        self.files.insert(
+            to_posix(relative_path),
+            PathState {
+                local,
+                remote,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, relative_path: &Path) {
+        self.files.remove(&to_posix(relative_path));
+    }
+
+    /// Record every path that exists on both sides and needed no operation as in sync
+    pub fn record_unchanged(
+        &mut self, local_files: &HashMap<PathBuf, FileStatus>,
+        remote_files: &HashMap<PathBuf, FileStatus>, touched: &HashSet<PathBuf>,
+    ) {
+        for (relative_path, local) in local_files {
+            if touched.contains(relative_path) {
+                continue;
+            }
+            if let Some(remote) = remote_files.get(relative_path) {
+                self.record(
+                    relative_path,
+                    FileSnapshot::from_status(local),
+                    FileSnapshot::from_status(remote),
+                );
+            }
+        }
+    }
+
+    /// Drop entries for paths that no longer exist on either side
+    pub fn prune_missing(
+        &mut self, local_files: &HashMap<PathBuf, FileStatus>,
+        remote_files: &HashMap<PathBuf, FileStatus>,
+    ) {
+        let existing: HashSet<String> = local_files
+            .keys()
+            .chain(remote_files.keys())
+            .map(|relative_path| to_posix(relative_path))
+            .collect();
+        self.files.retain(|path, _| existing.contains(path));
+    }
+
+    /// Update the baseline from the outcome of executed operations.
+    ///
+    /// `remote_stat` returns the current state of a remote file; transferred files are
+    /// re-read on both sides so the baseline holds what actually landed. Failed operations
+    /// leave their entry untouched.
+    pub fn record_results<F>(&mut self, mapper: &PathMapper, results: &[FileResult], remote_stat: F)
+    where
+        F: Fn(&Path) -> Option<FileStatus>,
+    {
+        for result in results.iter().filter(|result| result.success) {
+            let relative_path = match mapper.local_to_relative(&result.local_path) {
+                Ok(relative_path) => relative_path,
+                Err(_) => continue,
+            };
+
+            match result.operation_type {
+                OperationType::Upload | OperationType::Download | OperationType::KeepBoth => {
+                    let local = FileSnapshot::from_local_file(&result.local_path).ok();
+                    let remote = remote_stat(&result.remote_path);
+                    match (local, remote) {
+                        (Some(local), Some(remote)) => {
+                            self.record(&relative_path, local, FileSnapshot::from_status(&remote))
+                        }
+                        _ => self.remove(&relative_path),
+                    }
+                }
+                OperationType::Delete | OperationType::DeleteLocal => self.remove(&relative_path),
+                OperationType::CreateDirectory | OperationType::Conflict => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+#[cfg(test)]
+mod tests {
+    use crate::paths::PathMapper;
+    use crate::state::{FileSnapshot, SyncState, STATE_DIR, STATE_FILE};
+    use crate::types::{FileResult, FileStatus, OperationType, SftpConfig, SyncOperation};
+    use chrono::{DateTime, Utc};
+    use std::collections::{HashMap, HashSet};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+
+    fn config(local_path: &Path) -> SftpConfig {
+        SftpConfig {
+            remote_path: "/srv/site".to_string(),
+            ..SftpConfig::for_test(local_path.to_string_lossy())
+        }
+    }
+
+    fn status(size: u64, mtime: i64, checksum: Option<&str>) -> FileStatus {
+        FileStatus {
+            path: PathBuf::from("a.txt"),
+            size,
+            modified: DateTime::<Utc>::from_timestamp(mtime, 0).unwrap(),
+            is_directory: false,
+            checksum: checksum.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_matching() {
+        let snapshot = FileSnapshot::from_status(&status(10, 1_000, None));
+        assert!(snapshot.matches(&status(10, 1_000, None)));
+        assert!(!snapshot.matches(&status(11, 1_000, None)));
+        assert!(!snapshot.matches(&status(10, 1_001, None)));
+
+        // With hashes on both sides only the content counts
+        let hashed = FileSnapshot::from_status(&status(10, 1_000, Some("abc")));
+        assert!(hashed.matches(&status(10, 9_999, Some("abc"))));
+        assert!(!hashed.matches(&status(10, 1_000, Some("def"))));
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = config(temp_dir.path());
+
+        let mut state = SyncState::load(&config);
+        assert!(state.files.is_empty());
+        state.record(
+            Path::new("src/main.rs"),
+            FileSnapshot::from_status(&status(10, 1_000, Some("abc"))),
+            FileSnapshot::from_status(&status(10, 1_000, None)),
+        );
+        state.save().unwrap();
+
+        assert!(temp_dir.path().join(STATE_DIR).join(STATE_FILE).is_file());
+        let loaded = SyncState::load(&config);
+        assert_eq!(loaded.files, state.files);
+        assert!(loaded.get(Path::new("src/main.rs")).is_some());
+    }
+
+    #[test]
+    fn test_subtree_state_stays_in_the_project_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join(STATE_DIR)).unwrap();
+        let subtree = temp_dir.path().join("src");
+        fs::create_dir_all(&subtree).unwrap();
+
+        let mut config = config(&subtree);
+        config.remote_path = "/srv/site/src".to_string();
+        let mut state = SyncState::new(&config);
+        state.record(
+            Path::new("main.rs"),
+            FileSnapshot::from_status(&status(1, 1, None)),
+            FileSnapshot::from_status(&status(1, 1, None)),
+        );
+        state.save().unwrap();
+
+        let path = SyncState::state_path(&config);
+        assert_eq!(path.parent().unwrap(), temp_dir.path().join(STATE_DIR));
+        assert_ne!(path.file_name().unwrap(), STATE_FILE);
+        assert!(path.is_file());
+        assert!(!subtree.join(STATE_DIR).exists());
+        assert_eq!(SyncState::load(&config).files, state.files);
+
+        // Another remote root for the same subtree gets its own baseline
+        config.remote_path = "/srv/staging/src".to_string();
+        assert_ne!(SyncState::state_path(&config), path);
+    }
+
+    #[test]
+    fn test_state_for_other_target_is_discarded() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut state = SyncState::new(&config(temp_dir.path()));
+        state.record(
+            Path::new("a.txt"),
+            FileSnapshot::from_status(&status(1, 1, None)),
+            FileSnapshot::from_status(&status(1, 1, None)),
+        );
+        state.save().unwrap();
+
+        let mut other = config(temp_dir.path());
+        other.remote_path = "/srv/other".to_string();
+        assert!(SyncState::load(&other).files.is_empty());
+
+        fs::write(temp_dir.path().join(STATE_DIR).join(STATE_FILE), "not json").unwrap();
+        assert!(SyncState::load(&config(temp_dir.path())).files.is_empty());
+    }
+
+    #[test]
+    fn test_record_results_and_unchanged() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("uploaded.txt"), "hello").unwrap();
+        let config = config(temp_dir.path());
+        let mapper = PathMapper::from_config(&config);
+        let mut state = SyncState::new(&config);
+
+        let operation = |operation_type, name: &str| SyncOperation {
+            operation_type,
+            local_path: temp_dir.path().join(name),
+            remote_path: PathBuf::from("/srv/site").join(name),
+            timestamp: Utc::now(),
+            reason: None,
+            bytes: 0,
+        };
+        state.record(
+            Path::new("deleted.txt"),
+            FileSnapshot::from_status(&status(1, 1, None)),
+            FileSnapshot::from_status(&status(1, 1, None)),
+        );
+
+        let results = vec![
+            FileResult::new(&operation(OperationType::Upload, "uploaded.txt"), Ok(5)),
+            FileResult::new(&operation(OperationType::Delete, "deleted.txt"), Ok(0)),
+            FileResult::new(
+                &operation(OperationType::Upload, "failed.txt"),
+                Err(crate::error::AstraError::FileOperationError(
+                    "denied".to_string(),
+                )),
+            ),
+        ];
+        state.record_results(&mapper, &results, |_| Some(status(5, 2_000, None)));
+
+        let uploaded = state.get(Path::new("uploaded.txt")).unwrap();
+        assert_eq!(uploaded.local.size, 5);
+        assert!(uploaded.local.hash.is_some());
+        assert_eq!(uploaded.remote.mtime, 2_000);
+        assert!(state.get(Path::new("deleted.txt")).is_none());
+        assert!(state.get(Path::new("failed.txt")).is_none());
+
+        let local = HashMap::from([
+            (PathBuf::from("same.txt"), status(3, 100, Some("x"))),
+            (PathBuf::from("local_only.txt"), status(3, 100, None)),
+        ]);
+        let remote = HashMap::from([(PathBuf::from("same.txt"), status(3, 100, None))]);
+        state.record_unchanged(&local, &remote, &HashSet::new());
+
+        assert!(state.get(Path::new("same.txt")).is_some());
+        assert!(state.get(Path::new("local_only.txt")).is_none());
+    }
+
+    #[test]
+    fn test_prune_missing_drops_paths_gone_from_both_sides() {
+        let mut state = SyncState::default();
+        for path in ["kept_local.txt", "kept_remote.txt", "gone.txt"] {
+            state.record(
+                Path::new(path),
+                FileSnapshot::from_status(&status(1, 1, None)),
+                FileSnapshot::from_status(&status(1, 1, None)),
+            );
+        }
+
+        let local = HashMap::from([(PathBuf::from("kept_local.txt"), status(1, 1, None))]);
+        let remote = HashMap::from([(PathBuf::from("kept_remote.txt"), status(1, 1, None))]);
+        state.prune_missing(&local, &remote);
+
+        assert!(state.get(Path::new("kept_local.txt")).is_some());
+        assert!(state.get(Path::new("kept_remote.txt")).is_some());
+        assert!(state.get(Path::new("gone.txt")).is_none());
+    }
+}
@@ -0,0 +1,402 @@
+use crate::error::{AstraError, AstraResult};
+use crate::paths::PathMapper;
+use crate::sftp::SftpClient;
+use crate::types::{
+    AdvancedConfig, ErrorPolicy, FileResult, FileStatus, OperationType, SyncOperation, SyncReason,
+};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+/// Cooperative cancellation flag shared by a task and every session working on it.
+///
+/// Transfers check it between files and between chunks of a file, so cancelling stops work at
+/// the next chunk boundary rather than killing a session mid-write.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// `Err(AstraError::Cancelled)` once the token has been cancelled
+    pub fn check(&self) -> AstraResult<()> {
+        if self.is_cancelled() {
+            Err(AstraError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sleep for `duration`, waking up early once cancelled. Returns whether it slept in full.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while !self.is_cancelled() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return true;
+            }
+            thread::sleep(left.min(CANCEL_POLL_INTERVAL));
+        }
+        false
+    }
+}
+
+/// How often a sleeping [`CancelToken::sleep`] looks at the token
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Upper bound for the delay between two retries
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// How transfers retry transient failures, from `max_retries` and `retry_delay` in `[advanced]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config(advanced: &AdvancedConfig) -> Self {
+        Self {
+            max_retries: advanced.max_retries,
+            initial_delay: Duration::from_secs(advanced.retry_delay),
+        }
+    }
+
+    /// Delay before retry number `retry` (counting from 1): exponential, capped at
+    /// [`MAX_RETRY_DELAY`]
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 1u32 << retry.saturating_sub(1).min(16);
+        self.initial_delay
+            .saturating_mul(factor)
+            .min(MAX_RETRY_DELAY)
+    }
+}
+
+/// How long a temp file must sit untouched before another transfer may take it over, when
+/// `operation_timeout` is disabled
+pub const ABANDONED_TEMP_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// When a transfer continues from the partial temp file an interrupted attempt left next to its
+/// target instead of starting over, from the `resume_*` settings in `[advanced]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResumePolicy {
+    pub enabled: bool,
+    pub min_size: u64,
+    pub verify_hash: bool,
+    /// Age after which a temp file is taken to belong to no live transfer: twice
+    /// `operation_timeout`, since a transfer blocked that long has failed
+    pub abandoned_after: Duration,
+}
+
+/// A temp file found next to a target, possibly left by an interrupted transfer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TempCandidate {
+    pub path: PathBuf,
+    pub len: u64,
+    pub modified: SystemTime,
+}
+
+impl ResumePolicy {
+    pub fn from_config(advanced: &AdvancedConfig) -> Self {
+        let abandoned_after = match advanced.operation_timeout {
+            0 => ABANDONED_TEMP_AGE,
+            timeout => Duration::from_secs(timeout.saturating_mul(2)),
+        };
+        Self {
+            enabled: advanced.resume_transfers,
+            min_size: advanced.resume_min_size,
+            verify_hash: advanced.resume_verify_hash,
+            abandoned_after,
+        }
+    }
+
+    /// Whether a transfer of `source_len` bytes may be resumed at all
+    pub fn applies_to(&self, source_len: u64) -> bool {
+        self.enabled && source_len >= self.min_size
+    }
+
+    /// The temp file among `candidates` to continue: the longest one nobody has written to for
+    /// `abandoned_after`. A file still being written is never picked, even if it has the
+    /// right prefix, since its writer would keep appending after we took it over.
+    pub fn pick_abandoned(
+        &self, candidates: Vec<TempCandidate>, now: SystemTime,
+    ) -> Option<TempCandidate> {
+        candidates
+            .into_iter()
+            .filter(|candidate| {
+                now.duration_since(candidate.modified)
+                    .is_ok_and(|age| age >= self.abandoned_after)
+            })
+            .max_by_key(|candidate| candidate.len)
+    }
+
+    /// Offset to continue a `source_len` byte transfer at, given the length of its temp file
+    /// (`None` if there is none), or 0 to start over.
+    ///
+    /// Only a target strictly shorter than the source can be a partial copy. With hash
+    /// verification `prefixes_match` is asked whether the first `target_len` bytes of both
+    /// sides agree, which callers check on the [`verify_window`] before `target_len`, so an
+    /// older, shorter version of the file is not mistaken for one.
+    pub fn resume_offset(
+        &self, source_len: u64, target_len: Option<u64>,
+        prefixes_match: impl FnOnce(u64) -> AstraResult<bool>,
+    ) -> AstraResult<u64> {
+        let target_len = match target_len {
+            Some(len) if self.applies_to(source_len) && len > 0 => len,
+            _ => return Ok(0),
+        };
+        if target_len >= source_len || (self.verify_hash && !prefixes_match(target_len)?) {
+            return Ok(0);
+        }
+        Ok(target_len)
+    }
+}
+
+/// Bytes at the end of a partial copy that resume verification compares. A stale or torn temp
+/// file differs from the source where it stops; reading back the whole prefix instead would cost
+/// about as much as sending the file again on the slow links resuming is for.
+pub const VERIFY_WINDOW: u64 = 4 * 1024 * 1024;
+
+/// The range `(offset, len)` of a `prefix_len` byte partial copy that is hashed to verify it:
+/// its last [`VERIFY_WINDOW`] bytes, or all of it if shorter
+pub fn verify_window(prefix_len: u64) -> (u64, u64) {
+    let len = prefix_len.min(VERIFY_WINDOW);
+    (prefix_len - len, len)
+}
+
+/// SHA-256 (lowercase hex) of the `len` bytes of `reader` at `offset`, or of fewer if it ends
+/// before that
+pub fn range_hash(mut reader: impl Read + Seek, offset: u64, len: u64) -> AstraResult<String> {
+    let mut hasher = Sha256::new();
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut prefix = reader.take(len);
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        match prefix.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => hasher.update(&buffer[..read]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(AstraError::IoError(e)),
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// One result per operation, reporting every slot no worker filled as failed so that no
+/// operation silently drops out of a sync result
+pub fn complete_results(
+    operations: &[SyncOperation], results: Vec<Option<FileResult>>,
+) -> Vec<FileResult> {
+    operations
+        .iter()
+        .zip(results)
+        .map(|(operation, result)| result.unwrap_or_else(|| FileResult::unreported(operation)))
+        .collect()
+}
+
+/// A set of independent SSH sessions that run [`SyncOperation`]s in parallel.
+///
+/// Every worker thread owns one session and its SFTP channel; sessions are never shared between
+/// threads, so a slow transfer only blocks its own worker.
+pub struct TransferPool {
+    clients: Vec<SftpClient>,
+}
+
+impl TransferPool {
+    /// Build a pool around an already connected client, opening up to `size - 1` extra sessions.
+    ///
+    /// Servers often limit concurrent sessions (`MaxSessions`, `MaxStartups`), so a session that
+    /// fails to connect only shrinks the pool instead of failing the sync.
+    pub fn new(primary: SftpClient, size: usize) -> Self {
+        Self::from_clients(vec![primary], size)
+    }
+
+    /// Build a pool from connected clients (at least one), opening more until it has `size`.
+    ///
+    /// The first client becomes the primary session; [`into_clients`](Self::into_clients) hands
+    /// all of them back so that a long-lived process can keep them for the next transfer.
+    pub fn from_clients(mut clients: Vec<SftpClient>, size: usize) -> Self {
+        let config = clients[0].config().clone();
+        let cancel = clients[0].cancel_token().clone();
+        let progress = clients[0].progress().clone();
+        for client in &mut clients[1..] {
+            client.set_cancel_token(cancel.clone());
+            client.set_progress(progress.clone());
+        }
+
+        while clients.len() < size {
+            match SftpClient::new(config.clone()) {
+                Ok(mut client) => {
+                    client.set_cancel_token(cancel.clone());
+                    client.set_progress(progress.clone());
+                    clients.push(client);
+                }
+                Err(e) => {
+                    warn!(
+                        "Opened {} of {} transfer sessions: {}",
+                        clients.len(),
+                        size,
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+
+        info!("Transfer pool ready with {} session(s)", clients.len());
+        Self {
+            clients,
+        }
+    }
+
+    pub fn into_clients(self) -> Vec<SftpClient> {
+        self.clients
+    }
+
+    pub fn size(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// The session the pool was built around
+    pub fn primary(&self) -> &SftpClient {
+        &self.clients[0]
+    }
+
+    /// Run all operations and return one result per operation, in the order given.
+    ///
+    /// Directories (explicit [`OperationType::CreateDirectory`] operations and the parents of
+    /// every file) are created first on a single session, parents before children, so no worker
+    /// writes a file before its directory exists. Files are then spread across all sessions.
+    ///
+    /// Transient failures are retried on a reconnected session (see
+    /// [`SftpClient::retry`]). With `on_error = "stop"` no further file is started once one has
+    /// failed for good; the files left are reported as not attempted.
+    pub fn execute(&mut self, operations: &[SyncOperation]) -> Vec<FileResult> {
+        let mut results: Vec<Option<FileResult>> = vec![None; operations.len()];
+
+        for (index, operation) in operations.iter().enumerate() {
+            if operation.operation_type == OperationType::CreateDirectory {
+                results[index] = Some(self.clients[0].execute_with_retry(operation));
+            }
+        }
+        self.prepare_parent_directories(operations);
+
+        let pending: Vec<usize> = (0..operations.len())
+            .filter(|index| results[*index].is_none())
+            .collect();
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(results);
+        let on_error = self.primary().config().sync.on_error;
+        let stopped = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            for client in &mut self.clients {
+                let (pending, next, results, stopped) = (&pending, &next, &results, &stopped);
+                scope.spawn(move || {
+                    while let Some(&index) = pending.get(next.fetch_add(1, Ordering::SeqCst)) {
+                        let operation = &operations[index];
+                        let result = if stopped.load(Ordering::SeqCst) {
+                            FileResult::not_attempted(operation)
+                        } else {
+                            client.execute_with_retry(operation)
+                        };
+                        if !result.success && result.attempts > 0 && on_error == ErrorPolicy::Stop {
+                            stopped.store(true, Ordering::SeqCst);
+                        }
+                        let mut results = results
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner());
+                        results[index] = Some(result);
+                    }
+                });
+            }
+        });
+
+        let results = results
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        complete_results(operations, results)
+    }
+
+    fn prepare_parent_directories(&self, operations: &[SyncOperation]) {
+        let (remote_dirs, local_dirs) = parent_directories(operations);
+
+        for dir in remote_dirs {
+            if let Err(e) = self.primary().create_remote_dir_all(&dir) {
+                warn!("Failed to create remote directory {}: {}", dir.display(), e);
+            }
+        }
+        for dir in local_dirs {
+            if let Err(e) = fs::create_dir_all(&dir) {
+                warn!("Failed to create local directory {}: {}", dir.display(), e);
+            }
+        }
+    }
+}
+
+/// Operations that copy every file of a directory tree to the other side.
+///
+/// `files` are relative to the tree being copied and `mapper` maps that tree's root onto the
+/// target directory; directory entries are skipped since their parents are created on demand.
+pub fn tree_operations(
+    operation_type: OperationType, mapper: &PathMapper, files: &[FileStatus],
+) -> Vec<SyncOperation> {
+    files
+        .iter()
+        .filter(|file| !file.is_directory)
+        .map(|file| SyncOperation {
+            operation_type,
+            local_path: mapper.relative_to_local(&file.path),
+            remote_path: mapper.relative_to_remote(&file.path),
+            timestamp: chrono::Utc::now(),
+            reason: Some(SyncReason::Requested),
+            bytes: file.size,
+        })
+        .collect()
+}
+
+/// Remote parents of uploaded files and local parents of downloaded files.
+///
+/// Both sets are ordered so that every directory comes before its subdirectories.
+pub fn parent_directories(operations: &[SyncOperation]) -> (BTreeSet<PathBuf>, BTreeSet<PathBuf>) {
+    let mut remote_dirs = BTreeSet::new();
+    let mut local_dirs = BTreeSet::new();
+
+    for operation in operations {
+        match operation.operation_type {
+            OperationType::Upload => {
+                if let Some(parent) = operation.remote_path.parent() {
+                    remote_dirs.insert(parent.to_path_buf());
+                }
+            }
+            OperationType::Download => {
+                if let Some(parent) = operation.local_path.parent() {
+                    local_dirs.insert(parent.to_path_buf());
+                }
+            }
+            OperationType::KeepBoth
+            | OperationType::Delete
+            | OperationType::DeleteLocal
+            | OperationType::CreateDirectory
+            | OperationType::Conflict => {}
+        }
+    }
+
+    (remote_dirs, local_dirs)
+}
@@ -0,0 +1,283 @@
+#[cfg(test)]
+mod tests {
+    use crate::error::AstraError;
+    use crate::paths::PathMapper;
+    use crate::transfer::{
+        complete_results, parent_directories, range_hash, tree_operations, verify_window,
+        CancelToken, ResumePolicy, RetryPolicy, TempCandidate, MAX_RETRY_DELAY, VERIFY_WINDOW,
+    };
+    use crate::types::{
+        AdvancedConfig, FileResult, FileStatus, OperationType, SyncOperation, SyncReason,
+    };
+    use chrono::Utc;
+    use std::cell::Cell;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    fn operation(operation_type: OperationType, relative: &str) -> SyncOperation {
+        SyncOperation {
+            operation_type,
+            local_path: PathBuf::from("/home/user/project").join(relative),
+            remote_path: PathBuf::from("/srv/site").join(relative),
+            timestamp: Utc::now(),
+            reason: None,
+            bytes: 0,
+        }
+    }
+
+    #[test]
+    fn test_parent_directories_come_before_children() {
+        let operations = vec![
+            operation(OperationType::Upload, "src/deep/nested/file.rs"),
+            operation(OperationType::Upload, "src/main.rs"),
+            operation(OperationType::Upload, "src/deep/mod.rs"),
+            operation(OperationType::Download, "assets/img/logo.png"),
+            operation(OperationType::Delete, "old/file.txt"),
+        ];
+
+        let (remote_dirs, local_dirs) = parent_directories(&operations);
+
+        assert_eq!(
+            remote_dirs.into_iter().collect::<Vec<_>>(),
+            vec![
+                PathBuf::from("/srv/site/src"),
+                PathBuf::from("/srv/site/src/deep"),
+                PathBuf::from("/srv/site/src/deep/nested"),
+            ]
+        );
+        assert_eq!(
+            local_dirs.into_iter().collect::<Vec<_>>(),
+            vec![PathBuf::from("/home/user/project/assets/img")]
+        );
+    }
+
+    #[test]
+    fn test_file_result_records_outcome() {
+        let upload = operation(OperationType::Upload, "index.php");
+
+        let ok = FileResult::new(&upload, Ok(42));
+        assert!(ok.success);
+        assert_eq!(ok.bytes, 42);
+        assert!(ok.error.is_none());
+
+        let failed = FileResult::new(
+            &upload,
+            Err(AstraError::FileOperationError(
+                "permission denied".to_string(),
+            )),
+        );
+        assert!(!failed.success);
+        assert_eq!(failed.bytes, 0);
+        assert!(failed.error.unwrap().contains("permission denied"));
+
+        let skipped = FileResult::not_attempted(&upload);
+        assert!(!skipped.success);
+        assert_eq!(skipped.attempts, 0);
+        assert_eq!(ok.with_attempts(3).attempts, 3);
+    }
+
+    #[test]
+    fn test_unreported_operations_are_failed_not_dropped() {
+        let operations = vec![
+            operation(OperationType::Upload, "a.txt"),
+            operation(OperationType::Upload, "b.txt"),
+        ];
+        let done = FileResult::new(&operations[0], Ok(3));
+
+        let results = complete_results(&operations, vec![Some(done), None]);
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].success);
+        assert!(!results[1].success);
+        assert_eq!(results[1].local_path, operations[1].local_path);
+        assert!(results[1].error.is_some());
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_the_cap() {
+        let policy = RetryPolicy::from_config(&AdvancedConfig::default());
+        assert_eq!(policy.max_retries, 3);
+
+        let delays: Vec<Duration> = (1..=3).map(|retry| policy.delay(retry)).collect();
+        assert_eq!(
+            delays,
+            vec![
+                Duration::from_secs(1),
+                Duration::from_secs(2),
+                Duration::from_secs(4)
+            ]
+        );
+        assert_eq!(policy.delay(40), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_cancelled_token_cuts_sleep_short() {
+        let token = CancelToken::new();
+        assert!(token.sleep(Duration::from_millis(10)));
+
+        token.cancel();
+        let started = std::time::Instant::now();
+        assert!(!token.sleep(Duration::from_secs(30)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_tree_operations_map_files_onto_target() {
+        let file = |path: &str, size: u64, is_directory: bool| FileStatus {
+            path: PathBuf::from(path),
+            size,
+            modified: Utc::now(),
+            is_directory,
+            checksum: None,
+        };
+        let files = vec![
+            file("css", 0, true),
+            file("css/site.css", 120, false),
+            file("index.html", 40, false),
+        ];
+        let mapper = PathMapper::new("/home/user/project/public", "/srv/site/public/");
+
+        let operations = tree_operations(OperationType::Download, &mapper, &files);
+
+        assert_eq!(operations.len(), 2);
+        assert_eq!(
+            operations[0].local_path,
+            PathBuf::from("/home/user/project/public/css/site.css")
+        );
+        assert_eq!(
+            operations[0].remote_path,
+            PathBuf::from("/srv/site/public/css/site.css")
+        );
+        assert_eq!(operations[0].bytes, 120);
+        assert_eq!(operations[1].reason, Some(SyncReason::Requested));
+        assert!(operations
+            .iter()
+            .all(|operation| operation.operation_type == OperationType::Download));
+    }
+
+    #[test]
+    fn test_cancel_token_is_shared_between_clones() {
+        let token = CancelToken::new();
+        let worker = token.clone();
+        assert!(worker.check().is_ok());
+
+        token.cancel();
+        assert!(worker.is_cancelled());
+        assert!(matches!(worker.check(), Err(AstraError::Cancelled)));
+    }
+
+    #[test]
+    fn test_resume_only_continues_matching_partial_copies() {
+        let policy = ResumePolicy::from_config(&AdvancedConfig::default());
+        let big = 8 * 1024 * 1024;
+        let checked = Cell::new(None);
+        let matching = |len| {
+            checked.set(Some(len));
+            Ok(true)
+        };
+
+        assert_eq!(
+            policy.resume_offset(big, Some(1000), matching).unwrap(),
+            1000
+        );
+        assert_eq!(checked.get(), Some(1000));
+
+        // Nothing there, nothing yet, the whole file already, or a different prefix
+        assert_eq!(policy.resume_offset(big, None, |_| Ok(true)).unwrap(), 0);
+        assert_eq!(policy.resume_offset(big, Some(0), |_| Ok(true)).unwrap(), 0);
+        assert_eq!(
+            policy.resume_offset(big, Some(big), |_| Ok(true)).unwrap(),
+            0
+        );
+        assert_eq!(
+            policy
+                .resume_offset(big, Some(1000), |_| Ok(false))
+                .unwrap(),
+            0
+        );
+
+        // Small files are sent again in full
+        assert_eq!(
+            policy
+                .resume_offset(4096, Some(1000), |_| Ok(true))
+                .unwrap(),
+            0
+        );
+
+        let trusting = ResumePolicy {
+            verify_hash: false,
+            ..policy
+        };
+        assert_eq!(
+            trusting
+                .resume_offset(big, Some(1000), |_| Ok(false))
+                .unwrap(),
+            1000
+        );
+
+        let disabled = ResumePolicy {
+            enabled: false,
+            ..policy
+        };
+        assert_eq!(
+            disabled
+                .resume_offset(big, Some(1000), |_| Ok(true))
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_only_abandoned_temp_files_are_taken_over() {
+        let policy = ResumePolicy::from_config(&AdvancedConfig::default());
+        assert_eq!(policy.abandoned_after, Duration::from_secs(600));
+        let now = SystemTime::now();
+        let candidate = |name: &str, len, age_secs| TempCandidate {
+            path: PathBuf::from(name),
+            len,
+            modified: now - Duration::from_secs(age_secs),
+        };
+
+        // Another transfer is still writing this one, however well its prefix would match
+        let being_written = candidate(".a.bin.1-1.astra-part", 5000, 2);
+        assert_eq!(
+            policy.pick_abandoned(vec![being_written.clone()], now),
+            None
+        );
+
+        let abandoned = candidate(".a.bin.2-1.astra-part", 3000, 3600);
+        let shorter = candidate(".a.bin.3-1.astra-part", 1000, 3600);
+        assert_eq!(
+            policy.pick_abandoned(vec![being_written, shorter, abandoned.clone()], now),
+            Some(abandoned)
+        );
+        // A clock running behind the file's mtime proves nothing either
+        assert_eq!(
+            policy.pick_abandoned(vec![candidate(".a.bin.4-1.astra-part", 1, 0)], now),
+            None
+        );
+    }
+
+    #[test]
+    fn test_range_hash_covers_only_the_range() {
+        let hello = range_hash(Cursor::new(b"hello world"), 0, 5).unwrap();
+        assert_eq!(hello, range_hash(Cursor::new(b"hello"), 0, 100).unwrap());
+        assert_eq!(
+            hello,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_ne!(hello, range_hash(Cursor::new(b"help!"), 0, 5).unwrap());
+        assert_eq!(hello, range_hash(Cursor::new(b"say hello!"), 4, 5).unwrap());
+    }
+
+    #[test]
+    fn test_verify_window_is_the_end_of_the_prefix() {
+        assert_eq!(verify_window(1_000), (0, 1_000));
+        assert_eq!(verify_window(VERIFY_WINDOW), (0, VERIFY_WINDOW));
+        assert_eq!(
+            verify_window(10 * VERIFY_WINDOW),
+            (9 * VERIFY_WINDOW, VERIFY_WINDOW)
+        );
+    }
+}
@@ -1,7 +1,10 @@
+use crate::error::AstraError;
 use crate::i18n::Language;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SftpConfig {
@@ -14,6 +17,260 @@ pub struct SftpConfig {
     pub local_path: String,
     pub language: Option<Language>,
     pub enabled: Option<bool>,
+    #[serde(default)]
+    pub known_hosts_path: Option<String>,
+    #[serde(default)]
+    pub host_key_policy: Option<HostKeyPolicy>,
+    #[serde(default)]
+    pub auth_methods: Option<Vec<AuthMethod>>,
+    #[serde(default)]
+    pub private_key_passphrase: Option<String>,
+    /// Environment variable holding the private key passphrase
+    #[serde(default)]
+    pub private_key_passphrase_env: Option<String>,
+    /// Shell command printing the private key passphrase, e.g. `pass show ssh/deploy`
+    #[serde(default)]
+    pub private_key_passphrase_command: Option<String>,
+    #[serde(default)]
+    pub sync: SyncOptions,
+    #[serde(default)]
+    pub advanced: AdvancedConfig,
+}
+
+#[cfg(test)]
+impl SftpConfig {
+    /// `user@test.com:/remote` with no credentials and default options, for tests to override
+    pub(crate) fn for_test(local_path: impl Into<String>) -> Self {
+        SftpConfig {
+            enabled: Some(true),
+            host: "test.com".to_string(),
+            port: 22,
+            username: "user".to_string(),
+            password: None,
+            private_key_path: None,
+            remote_path: "/remote".to_string(),
+            local_path: local_path.into(),
+            language: None,
+            known_hosts_path: None,
+            host_key_policy: None,
+            auth_methods: None,
+            private_key_passphrase: None,
+            private_key_passphrase_env: None,
+            private_key_passphrase_command: None,
+            sync: SyncOptions::default(),
+            advanced: AdvancedConfig::default(),
+        }
+    }
+}
+
+/// SSH authentication methods, tried in the order given by `auth_methods`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthMethod {
+    /// The key at `private_key_path`
+    #[serde(alias = "publickey")]
+    PublicKey,
+    /// The configured `password`
+    Password,
+    /// Identities offered by a running ssh-agent
+    Agent,
+    /// `~/.ssh/id_ed25519`, `~/.ssh/id_ecdsa` and `~/.ssh/id_rsa`
+    DefaultKeys,
+    /// Keyboard-interactive, answered with the configured password
+    KeyboardInteractive,
+}
+
+impl fmt::Display for AuthMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AuthMethod::PublicKey => "public-key",
+            AuthMethod::Password => "password",
+            AuthMethod::Agent => "agent",
+            AuthMethod::DefaultKeys => "default-keys",
+            AuthMethod::KeyboardInteractive => "keyboard-interactive",
+        };
+        f.write_str(name)
+    }
+}
+
+/// How to treat the server's host key during the SSH handshake
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HostKeyPolicy {
+    /// Only connect to hosts already listed in known_hosts
+    Strict,
+    /// Record unknown hosts on first connect, reject changed keys
+    #[default]
+    AcceptNew,
+    /// Skip verification entirely
+    Off,
+}
+
+/// Runtime sync options, read from `[sync]` in settings.toml or the `sync` object in astra.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncOptions {
+    /// Gitignore-style patterns left out of the sync
+    pub exclude_patterns: Vec<String>,
+    /// Gitignore-style patterns a file must match to be synced (empty means all files)
+    pub include_patterns: Vec<String>,
+    /// Also apply the project's `.gitignore`
+    pub respect_gitignore: bool,
+    /// How bidirectional sync resolves paths that changed on both sides
+    pub conflict_policy: ConflictPolicy,
+    /// Abort a sync run that would delete more files than this (0 means no limit)
+    pub max_deletions: usize,
+    /// Remote directory that deleted files are moved into instead of being unlinked. Relative
+    /// paths are resolved against the remote root and left out of the sync.
+    pub trash_dir: Option<String>,
+    /// Whether the remaining files are still transferred once one has failed for good
+    pub on_error: ErrorPolicy,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            exclude_patterns: Vec::new(),
+            include_patterns: Vec::new(),
+            respect_gitignore: false,
+            conflict_policy: ConflictPolicy::default(),
+            max_deletions: 50,
+            trash_dir: None,
+            on_error: ErrorPolicy::default(),
+        }
+    }
+}
+
+/// How bidirectional sync resolves a path that changed on both sides since the last sync
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// Upload the local copy over the remote one
+    PreferLocal,
+    /// Download the remote copy over the local one
+    PreferRemote,
+    /// Keep whichever copy has the later mtime
+    NewestWins,
+    /// Save the remote copy next to the local file with a suffix, then upload the local copy
+    KeepBoth,
+    /// Leave both copies alone and report the conflict
+    #[default]
+    Ask,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = AstraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "prefer-local" | "local" => Ok(ConflictPolicy::PreferLocal),
+            "prefer-remote" | "remote" => Ok(ConflictPolicy::PreferRemote),
+            "newest-wins" | "newest" => Ok(ConflictPolicy::NewestWins),
+            "keep-both" | "both" => Ok(ConflictPolicy::KeepBoth),
+            "ask" => Ok(ConflictPolicy::Ask),
+            _ => Err(AstraError::ConfigurationError(format!(
+                "Unknown conflict policy '{}', expected prefer-local, prefer-remote, newest-wins, \
+                 keep-both or ask",
+                s
+            ))),
+        }
+    }
+}
+
+/// What a transfer does with the remaining files once one has failed (after its retries)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorPolicy {
+    /// Transfer every other file and report the failures at the end
+    #[default]
+    Continue,
+    /// Start no further files; those left are reported as not attempted
+    Stop,
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = AstraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "continue" => Ok(ErrorPolicy::Continue),
+            "stop" | "stop-on-error" => Ok(ErrorPolicy::Stop),
+            _ => Err(AstraError::ConfigurationError(format!(
+                "Unknown error policy '{}', expected continue or stop",
+                s
+            ))),
+        }
+    }
+}
+
+/// A path that changed on both sides since the last sync
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConflict {
+    /// Path relative to the sync roots, with `/` separators
+    pub path: String,
+    pub local_path: PathBuf,
+    pub remote_path: PathBuf,
+    pub local_size: u64,
+    pub remote_size: u64,
+    pub local_modified: DateTime<Utc>,
+    pub remote_modified: DateTime<Utc>,
+    /// Policy that resolved the conflict, or `None` while it waits for the user
+    pub resolution: Option<ConflictPolicy>,
+}
+
+/// Connection tuning, read from `[advanced]` in settings.toml or the `advanced` object in
+/// astra.json. Timeouts are in seconds; 0 disables the timeout.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdvancedConfig {
+    /// Limit for establishing the TCP connection
+    pub connect_timeout: u64,
+    /// Limit for any single blocking SSH/SFTP call, including the handshake
+    pub operation_timeout: u64,
+    /// Upper bound on parallel file transfers
+    pub max_concurrent_transfers: usize,
+    /// Interval between SSH keepalive messages (0 disables keepalives)
+    pub keepalive_interval: u64,
+    /// Times a transfer that failed on a network or session error is retried on a new session
+    pub max_retries: u32,
+    /// Delay before the first retry; it doubles for every further retry
+    pub retry_delay: u64,
+    /// Continue a transfer from a shorter copy an interrupted run left at the target
+    pub resume_transfers: bool,
+    /// Smallest file, in bytes, whose transfer is resumed; smaller files are sent again in full
+    pub resume_min_size: u64,
+    /// Only resume if the last 4 MiB of the partial copy have the same SHA-256 as the same
+    /// bytes of the source, rather than trusting its size alone. The window keeps the check
+    /// cheap over SFTP, where the remote bytes have to be read back.
+    pub resume_verify_hash: bool,
+    /// Allow shell commands over the session to save round trips: `sha256sum` for the resume
+    /// check, `mv` when posix-rename is missing, `find` for temp cleanup. Off by default:
+    /// SFTP-only and chrooted accounts have no usable shell.
+    pub remote_shell: bool,
+    /// Age in seconds after which a temp file left by an interrupted transfer is removed when a
+    /// sync starts (0 keeps them all); younger ones are kept for the transfer to resume from
+    pub stale_temp_age: u64,
+    /// Log at debug level
+    pub verbose_logging: bool,
+}
+
+impl Default for AdvancedConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: 30,
+            operation_timeout: 300,
+            max_concurrent_transfers: 5,
+            keepalive_interval: 30,
+            max_retries: 3,
+            retry_delay: 1,
+            resume_transfers: true,
+            resume_min_size: 1024 * 1024,
+            resume_verify_hash: true,
+            remote_shell: false,
+            stale_temp_age: 24 * 60 * 60,
+            verbose_logging: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,6 +289,72 @@ pub struct SyncResult {
     pub files_transferred: Vec<String>,
     pub files_skipped: Vec<String>,
     pub errors: Vec<String>,
+    /// Outcome of every operation that was attempted
+    #[serde(default)]
+    pub file_results: Vec<FileResult>,
+    /// Paths that changed on both sides, resolved or waiting for the user
+    #[serde(default)]
+    pub conflicts: Vec<SyncConflict>,
+}
+
+/// Outcome of a single [`SyncOperation`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileResult {
+    pub operation_type: OperationType,
+    pub local_path: PathBuf,
+    pub remote_path: PathBuf,
+    pub success: bool,
+    /// Bytes transferred (0 for deletes and directories)
+    pub bytes: u64,
+    pub error: Option<String>,
+    /// Times the operation was tried, retries included (0 if it was never started)
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+impl FileResult {
+    pub fn new(operation: &SyncOperation, outcome: Result<u64, AstraError>) -> Self {
+        let (success, bytes, error) = match outcome {
+            Ok(bytes) => (true, bytes, None),
+            Err(e) => (false, 0, Some(e.to_string())),
+        };
+        Self {
+            operation_type: operation.operation_type,
+            local_path: operation.local_path.clone(),
+            remote_path: operation.remote_path.clone(),
+            success,
+            bytes,
+            error,
+            attempts: 1,
+        }
+    }
+
+    pub fn with_attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    /// An operation skipped because an earlier one failed under [`ErrorPolicy::Stop`]
+    pub fn not_attempted(operation: &SyncOperation) -> Self {
+        Self::new(
+            operation,
+            Err(AstraError::FileOperationError(
+                "Not attempted after an earlier failure (on_error = stop)".to_string(),
+            )),
+        )
+        .with_attempts(0)
+    }
+
+    /// An operation whose worker never reported back, so nothing is known about its outcome
+    pub fn unreported(operation: &SyncOperation) -> Self {
+        Self::new(
+            operation,
+            Err(AstraError::SystemError(
+                "The transfer worker stopped without reporting a result".to_string(),
+            )),
+        )
+        .with_attempts(0)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,20 +363,98 @@ pub struct SyncOperation {
     pub local_path: PathBuf,
     pub remote_path: PathBuf,
     pub timestamp: DateTime<Utc>,
+    /// Why the planner chose this operation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<SyncReason>,
+    /// Bytes the operation is expected to move (or remove), as seen when it was planned
+    #[serde(default)]
+    pub bytes: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Why a path needs an operation, as reported in sync plans
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncReason {
+    /// The local copy has the later mtime
+    NewerLocally,
+    /// The remote copy has the later mtime
+    NewerRemotely,
+    /// Same mtime, different size
+    SizeDiffers,
+    /// Same mtime and size, different content hash
+    ChecksumDiffers,
+    /// Only exists locally
+    MissingRemote,
+    /// Only exists on the server
+    MissingLocal,
+    /// Changed locally since the last sync
+    ChangedLocally,
+    /// Changed on the server since the last sync
+    ChangedRemotely,
+    /// Changed on both sides since the last sync
+    ChangedOnBothSides,
+    /// Deleted locally since the last sync
+    DeletedLocally,
+    /// Deleted on the server since the last sync
+    DeletedRemotely,
+    /// Named explicitly on the command line
+    Requested,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum OperationType {
     Upload,
     Download,
+    /// Remove the remote file (or move it to the configured trash directory)
     Delete,
+    /// Remove the local file
+    DeleteLocal,
     CreateDirectory,
+    /// Changed on both sides and left alone until the user decides
+    Conflict,
+    /// Changed on both sides; the remote copy is saved locally under a conflict name and the
+    /// local copy is uploaded
+    KeepBoth,
+}
+
+/// Direction(s) a sync run is allowed to move files in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncMode {
+    /// Push local changes to the remote, never touch local files
+    #[default]
+    Upload,
+    /// Pull remote changes, never touch remote files
+    Download,
+    /// Move the newer side of each file in either direction
+    Bidirectional,
+    /// Make the remote an exact copy of the local tree, deleting remote-only files
+    Mirror,
+}
+
+impl FromStr for SyncMode {
+    type Err = AstraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "upload" | "push" => Ok(SyncMode::Upload),
+            "download" | "pull" => Ok(SyncMode::Download),
+            // The Neovim plugin's incremental sync moves changes both ways
+            "bidirectional" | "both" | "incremental" => Ok(SyncMode::Bidirectional),
+            "mirror" => Ok(SyncMode::Mirror),
+            _ => Err(AstraError::ConfigurationError(format!(
+                "Unknown sync mode '{}', expected upload, download, bidirectional or mirror",
+                s
+            ))),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AstraTomlConfig {
     pub sftp: SftpTomlConfig,
     pub sync: Option<SyncTomlConfig>,
+    pub advanced: Option<AdvancedConfig>,
     pub language: Option<Language>,
     pub enabled: Option<bool>,
 }
@@ -67,6 +468,12 @@ pub struct SftpTomlConfig {
     pub private_key_path: Option<String>,
     pub remote_path: String,
     pub local_path: Option<String>,
+    pub known_hosts_path: Option<String>,
+    pub host_key_policy: Option<HostKeyPolicy>,
+    pub auth_methods: Option<Vec<AuthMethod>>,
+    pub private_key_passphrase: Option<String>,
+    pub private_key_passphrase_env: Option<String>,
+    pub private_key_passphrase_command: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -74,6 +481,28 @@ pub struct SyncTomlConfig {
     pub auto_sync: Option<bool>,
     pub sync_on_save: Option<bool>,
     pub sync_interval: Option<u64>,
+    pub exclude_patterns: Option<Vec<String>>,
+    pub include_patterns: Option<Vec<String>>,
+    pub respect_gitignore: Option<bool>,
+    pub conflict_policy: Option<ConflictPolicy>,
+    pub max_deletions: Option<usize>,
+    pub trash_dir: Option<String>,
+    pub on_error: Option<ErrorPolicy>,
+}
+
+impl From<SyncTomlConfig> for SyncOptions {
+    fn from(config: SyncTomlConfig) -> Self {
+        let defaults = SyncOptions::default();
+        Self {
+            exclude_patterns: config.exclude_patterns.unwrap_or_default(),
+            include_patterns: config.include_patterns.unwrap_or_default(),
+            respect_gitignore: config.respect_gitignore.unwrap_or(false),
+            conflict_policy: config.conflict_policy.unwrap_or_default(),
+            max_deletions: config.max_deletions.unwrap_or(defaults.max_deletions),
+            trash_dir: config.trash_dir.filter(|dir| !dir.trim().is_empty()),
+            on_error: config.on_error.unwrap_or_default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -89,8 +518,10 @@ pub struct VsCodeSftpConfig {
     pub password: Option<String>,
     #[serde(rename = "privateKeyPath")]
     pub private_key_path: Option<String>,
+    pub passphrase: Option<String>,
     #[serde(rename = "uploadOnSave")]
     pub upload_on_save: Option<bool>,
+    pub ignore: Option<Vec<String>>,
 }
 
 impl From<AstraTomlConfig> for SftpConfig {
@@ -110,6 +541,14 @@ impl From<AstraTomlConfig> for SftpConfig {
             }),
             language: config.language,
             enabled: config.enabled.or(Some(true)), // TOML configs default to enabled
+            known_hosts_path: config.sftp.known_hosts_path,
+            host_key_policy: config.sftp.host_key_policy,
+            auth_methods: config.sftp.auth_methods,
+            private_key_passphrase: config.sftp.private_key_passphrase,
+            private_key_passphrase_env: config.sftp.private_key_passphrase_env,
+            private_key_passphrase_command: config.sftp.private_key_passphrase_command,
+            sync: config.sync.map(SyncOptions::from).unwrap_or_default(),
+            advanced: config.advanced.unwrap_or_default(),
         }
     }
 }
@@ -129,6 +568,17 @@ impl From<VsCodeSftpConfig> for SftpConfig {
                 .to_string(),
             language: None,
             enabled: Some(true), // VSCode配置默认启用
+            known_hosts_path: None,
+            host_key_policy: None,
+            auth_methods: None,
+            private_key_passphrase: config.passphrase,
+            private_key_passphrase_env: None,
+            private_key_passphrase_command: None,
+            sync: SyncOptions {
+                exclude_patterns: config.ignore.unwrap_or_default(),
+                ..SyncOptions::default()
+            },
+            advanced: AdvancedConfig::default(),
         }
     }
 }
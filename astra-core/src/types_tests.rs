@@ -9,15 +9,9 @@ mod tests {
     #[test]
     fn test_config_serialization() {
         let config = SftpConfig {
-            enabled: Some(true),
-            host: "test.com".to_string(),
-            port: 22,
-            username: "user".to_string(),
             password: Some("pass".to_string()),
-            private_key_path: None,
-            remote_path: "/remote".to_string(),
-            local_path: "/local".to_string(),
             language: Some(crate::i18n::detect_language()),
+            ..SftpConfig::for_test("/local")
         };
 
         let json = serde_json::to_string(&config).unwrap();
@@ -58,6 +52,8 @@ mod tests {
             local_path: PathBuf::from("/local/file.txt"),
             remote_path: PathBuf::from("/remote/file.txt"),
             timestamp: Utc::now(),
+            reason: None,
+            bytes: 0,
         };
 
         matches!(operation.operation_type, OperationType::Upload);
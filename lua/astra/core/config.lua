@@ -388,8 +388,7 @@ function M._generate_toml_content(config)
     "sync_on_save = " .. tostring(config.sync_on_save),
     "sync_interval = " .. config.sync_interval,
     "",
-    "[filters]",
-    "# File patterns to exclude from synchronization",
+    "# File patterns to exclude from synchronization (gitignore syntax)",
     "exclude_patterns = [",
   }
 
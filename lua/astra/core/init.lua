@@ -126,6 +126,10 @@ function M._register_full_commands()
     Sync.status()
   end, { desc = "Astra: Check sync status" })
 
+  vim.api.nvim_create_user_command("AstraSyncPreview", function()
+    Sync.preview_sync()
+  end, { desc = "Astra: Preview the sync plan without changing files" })
+
   vim.api.nvim_create_user_command("AstraVersion", function()
     Sync.version()
   end, { desc = "Show Astra version" })
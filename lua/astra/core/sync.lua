@@ -45,7 +45,19 @@ function M._get_current_file()
   }
 end
 
--- 获取相对路径
+-- 规范化路径：统一使用 "/" 分隔符，合并重复分隔符，去掉末尾 "/"
+function M._normalize_path(path)
+  if not path or path == "" then return path end
+
+  local normalized = path:gsub("\\", "/"):gsub("/+", "/"):gsub("/%./", "/")
+  if #normalized > 1 then
+    normalized = normalized:gsub("/%.?$", "")
+  end
+
+  return normalized
+end
+
+-- 获取相对路径（相对于配置的 local_path），不在 local_path 之下时返回 nil
 function M._get_relative_path(file_path)
   if not M.config then return file_path end
 
@@ -54,25 +66,78 @@ function M._get_relative_path(file_path)
     local_path = vim.fn.getcwd()
   end
 
-  local relative_path = file_path:gsub("^" .. vim.pesc(local_path) .. "/", "")
-  return relative_path
+  local root = M._normalize_path(local_path)
+  local path = M._normalize_path(file_path)
+
+  if path == root then
+    return ""
+  end
+
+  local prefix = root == "/" and "/" or root .. "/"
+  if path:sub(1, #prefix) == prefix then
+    return path:sub(#prefix + 1)
+  end
+
+  return nil
 end
 
--- 构建远程路径
+-- 构建远程路径（远程根目录 + 相对路径，与 astra-core 的 PathMapper 保持一致）
+-- 路径不在 local_path 之下时提示并返回 nil
 function M._build_remote_path(local_file_path)
   if not M.config then return local_file_path end
 
   local relative_path = M._get_relative_path(local_file_path)
-  local remote_path = M.config.remote_path
+  if not relative_path then
+    vim.notify("❌ " .. local_file_path .. " is outside the project's local_path", vim.log.levels.ERROR)
+    return nil
+  end
+
+  local remote_root = M._normalize_path(M.config.remote_path)
 
-  if remote_path:sub(-1) ~= "/" then
-    remote_path = remote_path .. "/"
+  if relative_path == "" then
+    return remote_root
   end
 
-  return remote_path .. relative_path
+  if remote_root == "/" then
+    return "/" .. relative_path
+  end
+
+  return remote_root .. "/" .. relative_path
+end
+
+-- 后端进程退出码（与 astra-core 的 error::exit_code 保持一致）
+M.exit_codes = {
+  SUCCESS = 0,
+  FAILURE = 1,
+  USAGE = 2,
+  CONFIG_ERROR = 3,
+  AUTH_FAILURE = 4,
+  CONNECTION_FAILURE = 5,
+  HOST_KEY_REJECTED = 6,
+  DELETION_LIMIT = 7,
+  PARTIAL_FAILURE = 10,
+  CONFLICTS_PENDING = 11,
+}
+
+-- 退出码对应的说明
+function M._describe_exit_code(exit_code)
+  local codes = M.exit_codes
+  local messages = {
+    [codes.SUCCESS] = "Command completed successfully",
+    [codes.USAGE] = "Invalid command line arguments",
+    [codes.CONFIG_ERROR] = "Configuration error, check .astra-settings/settings.toml",
+    [codes.AUTH_FAILURE] = "Authentication failed",
+    [codes.CONNECTION_FAILURE] = "Could not connect to the server",
+    [codes.HOST_KEY_REJECTED] = "Server host key was rejected",
+    [codes.DELETION_LIMIT] = "Sync aborted: too many files would be deleted",
+    [codes.PARTIAL_FAILURE] = "Some files failed to transfer",
+    [codes.CONFLICTS_PENDING] = "Conflicts are waiting for a decision",
+  }
+  return messages[exit_code] or ("Command failed with exit code " .. exit_code)
 end
 
 -- 执行后端命令
+-- callback(success, message, output, exit_code)：success 仅在退出码为 0 时为 true
 function M._execute_backend_command(cmd_args, callback)
   if not M.binary_path then
     local Binary = require("astra.core.binary")
@@ -85,13 +150,23 @@ function M._execute_backend_command(cmd_args, callback)
   end
 
   local cmd = M.binary_path .. " " .. cmd_args
+  local output = {}
 
   local job = vim.fn.jobstart(cmd, {
     on_stdout = function(_, data)
       if data and #data > 0 then
         for _, line in ipairs(data) do
           if line and line ~= "" then
-            vim.notify("Backend: " .. line, vim.log.levels.DEBUG)
+            local event = M._decode_progress_event(line)
+            if event then
+              local message = M._progress_message(event)
+              if message then
+                vim.api.nvim_echo({ { message } }, false, {})
+              end
+            else
+              table.insert(output, line)
+              vim.notify("Backend: " .. line, vim.log.levels.DEBUG)
+            end
           end
         end
       end
@@ -100,16 +175,35 @@ function M._execute_backend_command(cmd_args, callback)
       if data and #data > 0 then
         for _, line in ipairs(data) do
           if line and line ~= "" then
-            vim.notify("Error: " .. line, vim.log.levels.ERROR)
+            if line:find("HostKeyMismatch", 1, true) then
+              vim.notify("⚠️  Host key mismatch! The server key changed or the connection is being intercepted.\n"
+                .. "Verify the server fingerprint and update known_hosts before retrying.\n" .. line,
+                vim.log.levels.WARN)
+            elseif line:find("HostKeyUnknown", 1, true) then
+              vim.notify("⚠️  Unknown host key (host_key_policy = \"strict\").\n"
+                .. "Add the server to known_hosts or use host_key_policy = \"accept-new\".\n" .. line,
+                vim.log.levels.WARN)
+            elseif line:find("PassphraseError", 1, true) then
+              vim.notify("🔑 Private key passphrase missing or wrong.\n"
+                .. "Set private_key_passphrase, private_key_passphrase_env or private_key_passphrase_command.\n" .. line,
+                vim.log.levels.WARN)
+            elseif line:find("DeletionLimitExceeded", 1, true) then
+              vim.notify("🗑️  Sync aborted: too many files would be deleted.\n"
+                .. "Check the plan, then raise max_deletions in [sync] or run the sync with --max-deletions.\n" .. line,
+                vim.log.levels.WARN)
+            elseif line:match("^Error") or line:find(" ERROR ", 1, true) then
+              vim.notify("Error: " .. line, vim.log.levels.ERROR)
+            else
+              -- 后端日志输出到 stderr
+              vim.notify("Backend: " .. line, vim.log.levels.DEBUG)
+            end
           end
         end
       end
     end,
     on_exit = function(_, exit_code)
-      if exit_code == 0 then
-        if callback then callback(true, "Command completed successfully") end
-      else
-        if callback then callback(false, "Command failed with exit code " .. exit_code) end
+      if callback then
+        callback(exit_code == M.exit_codes.SUCCESS, M._describe_exit_code(exit_code), output, exit_code)
       end
     end
   })
@@ -122,6 +216,149 @@ function M._execute_backend_command(cmd_args, callback)
   return job
 end
 
+-- 解析进度事件行（--output json 模式下结果文档之前，每行一个带 event 字段的 JSON 对象）
+function M._decode_progress_event(line)
+  if line:sub(1, 10) ~= '{"event":"' then
+    return nil
+  end
+
+  local ok, event = pcall(vim.json.decode, line)
+  if ok and type(event) == "table" and event.event then
+    return event
+  end
+  return nil
+end
+
+-- 进度事件的提示文本（进度百分比、速度和剩余时间），无需显示时返回 nil
+function M._progress_message(event)
+  local name = vim.fn.fnamemodify(event.path or "", ":t")
+
+  if event.event == "bytes_transferred" and type(event.total_bytes) == "number" and event.total_bytes > 0 then
+    local message = string.format("⏳ %s %d%%", name, math.floor(event.bytes * 100 / event.total_bytes))
+    -- 续传时已在目标端的部分不计入速度
+    local sent = event.bytes - (event.resumed_from or 0)
+    if event.elapsed_ms and event.elapsed_ms > 0 and sent > 0 then
+      local rate = sent * 1000 / event.elapsed_ms
+      local eta = math.ceil((event.total_bytes - event.bytes) / rate)
+      message = message .. string.format(" (%.1f MB/s, ETA %ds)", rate / 1048576, eta)
+    end
+    return message
+  elseif event.event == "file_finished" and event.success then
+    return "✅ " .. name
+  end
+  return nil
+end
+
+-- 后端输出末尾的 JSON 文本（从最后一个单独的 "{" 行开始）
+function M._extract_json_lines(output)
+  if not output then
+    return nil
+  end
+
+  for i = #output, 1, -1 do
+    if output[i] == "{" then
+      return vim.list_slice(output, i)
+    end
+  end
+  return nil
+end
+
+-- 从后端输出中解析同步结果
+-- --output json 模式下为单行 JSON 文档（结果在 data 字段中），文本模式下为输出末尾的 JSON 对象
+function M._parse_sync_result(output)
+  if not output then
+    return nil
+  end
+
+  for i = #output, 1, -1 do
+    local line = output[i]
+    if line:sub(1, 1) == "{" and line:sub(-1) == "}" then
+      local ok, doc = pcall(vim.json.decode, line)
+      if ok and type(doc) == "table" and doc.schema_version then
+        return type(doc.data) == "table" and doc.data or nil
+      end
+    end
+  end
+
+  local lines = M._extract_json_lines(output)
+  if not lines then
+    return nil
+  end
+
+  local ok, result = pcall(vim.json.decode, table.concat(lines, "\n"))
+  if ok and type(result) == "table" then
+    return result
+  end
+  return nil
+end
+
+-- 待用户处理的冲突（resolution 为空）
+function M._pending_conflicts(result)
+  local pending = {}
+  if not result or type(result.conflicts) ~= "table" then
+    return pending
+  end
+
+  for _, conflict in ipairs(result.conflicts) do
+    if conflict.resolution == nil or conflict.resolution == vim.NIL then
+      table.insert(pending, conflict)
+    end
+  end
+  return pending
+end
+
+-- 提示用户选择冲突处理方式，并按所选策略重新执行同步
+function M._resolve_conflicts(output, cmd_args, on_done)
+  local pending = M._pending_conflicts(M._parse_sync_result(output))
+  if #pending == 0 then
+    return false
+  end
+
+  local paths = {}
+  for _, conflict in ipairs(pending) do
+    table.insert(paths, conflict.path)
+  end
+
+  local choices = {
+    { label = "Keep local version", policy = "prefer-local" },
+    { label = "Keep remote version", policy = "prefer-remote" },
+    { label = "Keep the newest version", policy = "newest-wins" },
+    { label = "Keep both versions", policy = "keep-both" },
+    { label = "Decide later", policy = nil },
+  }
+
+  vim.ui.select(choices, {
+    prompt = string.format("⚠️  %d file(s) changed on both sides: %s", #pending, table.concat(paths, ", ")),
+    format_item = function(choice) return choice.label end,
+  }, function(choice)
+    if not choice or not choice.policy then
+      vim.notify("⚠️  Conflicts left unresolved", vim.log.levels.WARN)
+      return
+    end
+
+    M._execute_backend_command(cmd_args .. " --conflict-policy " .. choice.policy, on_done)
+  end)
+  return true
+end
+
+-- 汇报目录上传/下载结果（逐文件结果来自 JSON 输出）
+function M._report_directory_transfer(direction, dir_name, success, message, output, exit_code)
+  local result = M._parse_sync_result(output)
+  local transferred = result and type(result.files_transferred) == "table" and #result.files_transferred or 0
+  local verb = direction == "upload" and "uploaded" or "downloaded"
+
+  if success then
+    vim.notify(string.format("✅ Directory %s: %s (%d files)", verb, dir_name, transferred), vim.log.levels.INFO)
+  elseif exit_code == M.exit_codes.PARTIAL_FAILURE and result then
+    local failed = type(result.errors) == "table" and result.errors or {}
+    vim.notify(string.format("⚠️  Directory %s with errors: %s (%d ok, %d failed)\n%s",
+      verb, dir_name, transferred, #failed, table.concat(failed, "\n")), vim.log.levels.WARN)
+  else
+    vim.notify(string.format("❌ Directory %s failed: %s\n%s", direction, dir_name, message or ""),
+      vim.log.levels.ERROR)
+  end
+end
+
 -- 3. 单个文件上传
 function M.upload_current_file()
   local file_info = M._get_current_file()
@@ -136,6 +373,9 @@ function M.upload_current_file()
   end
 
   local remote_path = M._build_remote_path(file_info.path)
+  if not remote_path then
+    return
+  end
   local cmd_args = string.format('upload --local "%s" --remote "%s"', file_info.path, remote_path)
 
   vim.notify("📤 Uploading " .. file_info.name .. "...", vim.log.levels.INFO)
@@ -144,7 +384,7 @@ function M.upload_current_file()
     if success then
       vim.notify("✅ File uploaded successfully: " .. file_info.name, vim.log.levels.INFO)
     else
-      vim.notify("❌ Upload failed: " .. file_info.name, vim.log.levels.ERROR)
+      vim.notify("❌ Upload failed: " .. file_info.name .. " (" .. message .. ")", vim.log.levels.ERROR)
     end
   end)
 end
@@ -163,6 +403,9 @@ function M.download_current_file()
   end
 
   local remote_path = M._build_remote_path(file_info.path)
+  if not remote_path then
+    return
+  end
   local cmd_args = string.format('download --remote "%s" --local "%s"', remote_path, file_info.path)
 
   vim.notify("📥 Downloading " .. file_info.name .. "...", vim.log.levels.INFO)
@@ -173,7 +416,7 @@ function M.download_current_file()
       -- 重新加载文件
       vim.cmd("edit")
     else
-      vim.notify("❌ Download failed: " .. file_info.name, vim.log.levels.ERROR)
+      vim.notify("❌ Download failed: " .. file_info.name .. " (" .. message .. ")", vim.log.levels.ERROR)
     end
   end)
 end
@@ -187,17 +430,16 @@ function M.upload_directory()
 
   local current_dir = vim.fn.expand("%:p:h")
   local dir_name = vim.fn.fnamemodify(current_dir, ":t")
-  local cmd_args = string.format('upload --local "%s" --remote "%s/%s"',
-                                current_dir, M.config.remote_path, dir_name)
+  local remote_dir = M._build_remote_path(current_dir)
+  if not remote_dir then
+    return
+  end
+  local cmd_args = string.format('upload --recursive --output json --local "%s" --remote "%s"', current_dir, remote_dir)
 
   vim.notify("📤 Uploading directory: " .. dir_name .. "...", vim.log.levels.INFO)
 
-  M._execute_backend_command(cmd_args, function(success, message)
-    if success then
-      vim.notify("✅ Directory uploaded successfully: " .. dir_name, vim.log.levels.INFO)
-    else
-      vim.notify("❌ Directory upload failed: " .. dir_name, vim.log.levels.ERROR)
-    end
+  M._execute_backend_command(cmd_args, function(success, message, output, exit_code)
+    M._report_directory_transfer("upload", dir_name, success, message, output, exit_code)
   end)
 end
 
@@ -210,17 +452,16 @@ function M.download_directory()
 
   local current_dir = vim.fn.expand("%:p:h")
   local dir_name = vim.fn.fnamemodify(current_dir, ":t")
-  local remote_dir = M.config.remote_path .. "/" .. dir_name
-  local cmd_args = string.format('download --remote "%s" --local "%s"', remote_dir, current_dir)
+  local remote_dir = M._build_remote_path(current_dir)
+  if not remote_dir then
+    return
+  end
+  local cmd_args = string.format('download --recursive --output json --remote "%s" --local "%s"', remote_dir, current_dir)
 
   vim.notify("📥 Downloading directory: " .. dir_name .. "...", vim.log.levels.INFO)
 
-  M._execute_backend_command(cmd_args, function(success, message)
-    if success then
-      vim.notify("✅ Directory downloaded successfully: " .. dir_name, vim.log.levels.INFO)
-    else
-      vim.notify("❌ Directory download failed: " .. dir_name, vim.log.levels.ERROR)
-    end
+  M._execute_backend_command(cmd_args, function(success, message, output, exit_code)
+    M._report_directory_transfer("download", dir_name, success, message, output, exit_code)
   end)
 end
 
@@ -231,17 +472,55 @@ function M.sync_project()
     return
   end
 
-  local cmd_args = string.format('sync --local "%s" --remote "%s" --mode bidirectional',
+  local cmd_args = string.format('sync --local "%s" --remote "%s" --mode bidirectional --output json',
                                 M.config.local_path, M.config.remote_path)
 
   vim.notify("🔄 Syncing entire project...", vim.log.levels.INFO)
 
-  M._execute_backend_command(cmd_args, function(success, message)
+  local function on_done(success, message, output, exit_code)
     if success then
       vim.notify("✅ Project synced successfully", vim.log.levels.INFO)
+    elseif exit_code == M.exit_codes.CONFLICTS_PENDING then
+      if not M._resolve_conflicts(output, cmd_args, on_done) then
+        vim.notify("⚠️  " .. message, vim.log.levels.WARN)
+      end
+    elseif exit_code == M.exit_codes.PARTIAL_FAILURE then
+      vim.notify("⚠️  " .. message, vim.log.levels.WARN)
     else
-      vim.notify("❌ Project sync failed", vim.log.levels.ERROR)
+      vim.notify("❌ Project sync failed" .. ": " .. message, vim.log.levels.ERROR)
+    end
+  end
+
+  M._execute_backend_command(cmd_args, on_done)
+end
+
+-- 预览同步计划（--dry-run），在新缓冲区中显示 JSON，不修改任何文件
+function M.preview_sync()
+  if not M.config then
+    vim.notify("❌ No configuration available", vim.log.levels.ERROR)
+    return
+  end
+
+  local cmd_args = string.format('sync --local "%s" --remote "%s" --mode bidirectional --dry-run',
+                                M.config.local_path, M.config.remote_path)
+
+  vim.notify("🔍 Planning sync...", vim.log.levels.INFO)
+
+  M._execute_backend_command(cmd_args, function(success, message, output)
+    local lines = M._extract_json_lines(output)
+    if not success or not lines then
+      vim.notify("❌ Sync preview failed", vim.log.levels.ERROR)
+      return
     end
+
+    vim.cmd("new")
+    local buf = vim.api.nvim_get_current_buf()
+    vim.api.nvim_buf_set_lines(buf, 0, -1, false, lines)
+    vim.bo[buf].buftype = "nofile"
+    vim.bo[buf].bufhidden = "wipe"
+    vim.bo[buf].modifiable = false
+    vim.bo[buf].filetype = "json"
+    vim.api.nvim_buf_set_name(buf, "astra://sync-plan")
   end)
 end
 
@@ -252,18 +531,26 @@ function M.incremental_sync()
     return
   end
 
-  local cmd_args = string.format('sync --local "%s" --remote "%s" --mode incremental',
+  local cmd_args = string.format('sync --local "%s" --remote "%s" --mode incremental --output json',
                                 M.config.local_path, M.config.remote_path)
 
   vim.notify("🔄 Performing incremental sync...", vim.log.levels.INFO)
 
-  M._execute_backend_command(cmd_args, function(success, message)
+  local function on_done(success, message, output, exit_code)
     if success then
       vim.notify("✅ Incremental sync completed", vim.log.levels.INFO)
+    elseif exit_code == M.exit_codes.CONFLICTS_PENDING then
+      if not M._resolve_conflicts(output, cmd_args, on_done) then
+        vim.notify("⚠️  " .. message, vim.log.levels.WARN)
+      end
+    elseif exit_code == M.exit_codes.PARTIAL_FAILURE then
+      vim.notify("⚠️  " .. message, vim.log.levels.WARN)
     else
-      vim.notify("❌ Incremental sync failed", vim.log.levels.ERROR)
+      vim.notify("❌ Incremental sync failed" .. ": " .. message, vim.log.levels.ERROR)
     end
-  end)
+  end
+
+  M._execute_backend_command(cmd_args, on_done)
 end
 
 -- 兼容性函数（保持向后兼容）
@@ -337,6 +337,7 @@ function M.generate_help_content(functionality_level)
       "  :AstraDownload     - Download current file",
       "  :AstraSync         - Sync current file",
       "  :AstraStatus       - Check status",
+      "  :AstraSyncPreview  - Preview the sync plan (dry run)",
       "  :AstraVersion      - Show version",
       "  :AstraInit         - Initialize project configuration",
       "  :AstraQuickSetup   - Quick configuration wizard",
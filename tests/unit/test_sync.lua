@@ -66,6 +66,92 @@ Test.describe("Astra Core Sync Module", function()
     Test.assert_function(Sync._execute_backend_command, "_execute_backend_command should be a function")
   end)
 
+  Test.it("should build remote paths relative to local_path", function()
+    if not Sync then
+      Test.skip("Sync module not loaded")
+      return
+    end
+
+    local saved_config = Sync.config
+    Sync.config = { local_path = "/home/user/project/", remote_path = "/var/www/site/" }
+
+    Test.assert_equal(Sync._build_remote_path("/home/user/project/src/app/main.lua"),
+                      "/var/www/site/src/app/main.lua", "nested path should keep its structure")
+    Test.assert_equal(Sync._build_remote_path("/home/user/project"),
+                      "/var/www/site", "project root should map to remote root")
+    Test.assert_nil(Sync._build_remote_path("/home/user/other/main.lua"),
+                    "paths outside local_path should not be mapped")
+    Test.assert_nil(Sync._build_remote_path("/home/user/project-old/main.lua"),
+                    "a sibling sharing the root's prefix is outside local_path")
+    Test.assert_equal(Sync._normalize_path("C:\\work\\project\\"),
+                      "C:/work/project", "separators should be normalized")
+
+    Sync.config = saved_config
+  end)
+
+  Test.it("should find unresolved conflicts in sync output", function()
+    if not Sync then
+      Test.skip("Sync module not loaded")
+      return
+    end
+
+    local output = {
+      "⬆️  Upload: a.txt",
+      "{",
+      '  "success": true,',
+      '  "conflicts": [',
+      '    { "path": "a.txt", "resolution": "prefer-local" },',
+      '    { "path": "b.txt", "resolution": null }',
+      "  ]",
+      "}",
+    }
+
+    local pending = Sync._pending_conflicts(Sync._parse_sync_result(output))
+    Test.assert_equal(#pending, 1, "only unresolved conflicts should be pending")
+    Test.assert_equal(pending[1].path, "b.txt", "pending conflict should keep its path")
+    Test.assert_equal(Sync._parse_sync_result({ "no json here" }), nil, "output without JSON should give nil")
+
+    local document = {
+      '{"schema_version":1,"command":"sync","ok":true,"data":{"conflicts":[{"path":"c.txt","resolution":null}]},"error":null}',
+    }
+    pending = Sync._pending_conflicts(Sync._parse_sync_result(document))
+    Test.assert_equal(#pending, 1, "JSON output mode should be parsed from its data field")
+    Test.assert_equal(pending[1].path, "c.txt", "pending conflict should come from the document")
+  end)
+
+  Test.it("should turn progress events into messages", function()
+    if not Sync then
+      Test.skip("Sync module not loaded")
+      return
+    end
+
+    local event = Sync._decode_progress_event(
+      '{"event":"bytes_transferred","path":"/project/big.bin","bytes":1048576,"total_bytes":4194304,"elapsed_ms":1000}')
+    Test.assert_not_nil(event, "progress lines should be decoded")
+    Test.assert_equal(Sync._progress_message(event), "⏳ big.bin 25% (1.0 MB/s, ETA 3s)",
+                      "progress should show percent, rate and ETA")
+    event.resumed_from = 524288
+    Test.assert_equal(Sync._progress_message(event), "⏳ big.bin 25% (0.5 MB/s, ETA 6s)",
+                      "a resumed prefix should not count towards the rate")
+    Test.assert_equal(Sync._decode_progress_event('{"schema_version":1,"ok":true}'), nil,
+                      "the result document is not a progress event")
+    Test.assert_equal(Sync._progress_message({ event = "file_started", path = "a.txt" }), nil,
+                      "started files need no message")
+  end)
+
+  Test.it("should describe backend exit codes", function()
+    if not Sync then
+      Test.skip("Sync module not loaded")
+      return
+    end
+
+    Test.assert_equal(Sync.exit_codes.CONFLICTS_PENDING, 11, "exit codes should match astra-core")
+    Test.assert_equal(Sync._describe_exit_code(Sync.exit_codes.PARTIAL_FAILURE),
+                      "Some files failed to transfer", "partial failures should be described")
+    Test.assert_equal(Sync._describe_exit_code(42), "Command failed with exit code 42",
+                      "unknown codes should fall back to the number")
+  end)
+
   Test.it("should have correct initial state", function()
     if not Sync then
       Test.skip("Sync module not loaded")